@@ -0,0 +1,177 @@
+//! End-to-end SET/GET benchmark against the server binary.
+//!
+//! Starts the server on a spare port, opens `BENCH_CLIENTS` connections (default 50) that send
+//! `BENCH_REQUESTS` requests each (default 2000), once one request at a time and once in
+//! batches of `BENCH_PIPELINE` (default 16), and reports throughput and latency percentiles.
+//!
+//! ```sh
+//! cargo bench --bench throughput
+//! ```
+
+use std::{
+    env,
+    process::{Child, Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+const PORT: u16 = 7379;
+
+/// Kills the server when the benchmark finishes or panics.
+struct Server(Child);
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn env_or(name: &str, default: usize) -> usize {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+async fn connect() -> TcpStream {
+    for _ in 0..50 {
+        if let Ok(stream) = TcpStream::connect(("127.0.0.1", PORT)).await {
+            stream.set_nodelay(true).unwrap();
+            return stream;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("Server did not start listening on port {PORT}");
+}
+
+fn encode(args: &[&str]) -> Vec<u8> {
+    let mut buf = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        buf.extend(format!("${}\r\n{arg}\r\n", arg.len()).as_bytes());
+    }
+    buf
+}
+
+/// Length of the first complete reply in `buf`, only the scalar types SET and GET reply with.
+fn reply_len(buf: &[u8]) -> Option<usize> {
+    let line_end = buf.windows(2).position(|w| w == b"\r\n")? + 2;
+    match buf.first()? {
+        b'+' | b'-' | b':' => Some(line_end),
+        b'$' => {
+            let length: isize = std::str::from_utf8(&buf[1..line_end - 2])
+                .ok()?
+                .parse()
+                .ok()?;
+            let end = if length < 0 {
+                line_end
+            } else {
+                line_end + length as usize + 2
+            };
+            (buf.len() >= end).then_some(end)
+        }
+        c => panic!("Unexpected reply type {}", *c as char),
+    }
+}
+
+async fn read_replies(stream: &mut TcpStream, buf: &mut Vec<u8>, mut count: usize) {
+    while count > 0 {
+        while let Some(len) = reply_len(buf) {
+            buf.drain(..len);
+            count -= 1;
+            if count == 0 {
+                return;
+            }
+        }
+        if stream.read_buf(buf).await.unwrap() == 0 {
+            panic!("Server closed the connection");
+        }
+    }
+}
+
+/// Sends `requests` commands in batches of `pipeline`, returning the latency of every batch.
+async fn client(
+    id: usize,
+    requests: usize,
+    pipeline: usize,
+    command: fn(usize, usize) -> Vec<u8>,
+) -> Vec<Duration> {
+    let mut stream = connect().await;
+    let mut buf = Vec::with_capacity(4096);
+    let mut latencies = Vec::with_capacity(requests / pipeline + 1);
+    let mut sent = 0;
+    while sent < requests {
+        let batch = pipeline.min(requests - sent);
+        let request: Vec<u8> = (sent..sent + batch).flat_map(|i| command(id, i)).collect();
+        let started = Instant::now();
+        stream.write_all(&request).await.unwrap();
+        read_replies(&mut stream, &mut buf, batch).await;
+        latencies.push(started.elapsed());
+        sent += batch;
+    }
+    latencies
+}
+
+fn set(id: usize, i: usize) -> Vec<u8> {
+    encode(&["SET", &format!("key:{id}:{i}"), "value"])
+}
+
+fn get(id: usize, i: usize) -> Vec<u8> {
+    encode(&["GET", &format!("key:{id}:{i}")])
+}
+
+async fn run(
+    name: &str,
+    clients: usize,
+    requests: usize,
+    pipeline: usize,
+    command: fn(usize, usize) -> Vec<u8>,
+) {
+    let started = Instant::now();
+    let handles: Vec<_> = (0..clients)
+        .map(|id| tokio::spawn(client(id, requests, pipeline, command)))
+        .collect();
+    let mut latencies = vec![];
+    for handle in handles {
+        latencies.extend(handle.await.unwrap());
+    }
+    let elapsed = started.elapsed();
+    latencies.sort();
+
+    let total = clients * requests;
+    let percentile = |p: f64| latencies[((latencies.len() - 1) as f64 * p) as usize];
+    println!(
+        "{name:<4} pipeline={pipeline:<3} {total} requests in {:.2?}: {:.0} ops/sec, p50 {:.2?}, p99 {:.2?}",
+        elapsed,
+        total as f64 / elapsed.as_secs_f64(),
+        percentile(0.50),
+        percentile(0.99),
+    );
+}
+
+#[tokio::main]
+async fn main() {
+    let clients = env_or("BENCH_CLIENTS", 50);
+    let requests = env_or("BENCH_REQUESTS", 2000);
+    let pipeline = env_or("BENCH_PIPELINE", 16);
+
+    let _server = Server(
+        Command::new(env!("CARGO_BIN_EXE_redis-starter-rust"))
+            .args(["--port", &PORT.to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Can not start the server"),
+    );
+    drop(connect().await);
+
+    println!("{clients} clients, {requests} requests each");
+    for pipeline in [1, pipeline] {
+        run("SET", clients, requests, pipeline, set).await;
+        run("GET", clients, requests, pipeline, get).await;
+    }
+}
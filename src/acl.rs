@@ -0,0 +1,301 @@
+use std::{borrow::Cow, collections::HashMap, fmt::Write};
+
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::{command::Command, resp::Resp, utils::glob_match};
+
+pub const DEFAULT_USER: &str = "default";
+
+pub const CATEGORIES: &[&str] = &[
+    "keyspace",
+    "read",
+    "write",
+    "string",
+    "stream",
+    "admin",
+    "fast",
+    "slow",
+    "dangerous",
+    "connection",
+    "pubsub",
+];
+
+#[derive(Debug, Error)]
+pub enum AclError {
+    #[error("ERR Error in ACL SETUSER modifier '{0}': Syntax error")]
+    InvalidRule(String),
+
+    #[error("ERR Error in ACL SETUSER modifier '{0}': Unknown command or category name in ACL")]
+    UnknownCategory(String),
+
+    #[error("NOPERM User {0} has no permissions to run the '{1}' command")]
+    CommandNotAllowed(String, String),
+
+    #[error("NOPERM No permissions to access a key")]
+    KeyNotAllowed,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum CommandRule {
+    All,
+    Category(String),
+    Command(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct User {
+    pub name: String,
+    enabled: bool,
+    nopass: bool,
+    password_hashes: Vec<String>,
+    /// Applied in order, the last matching rule decides whether a command may run.
+    command_rules: Vec<(bool, CommandRule)>,
+    key_patterns: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct Acl {
+    users: HashMap<String, User>,
+}
+
+impl User {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            enabled: false,
+            nopass: false,
+            password_hashes: vec![],
+            command_rules: vec![],
+            key_patterns: vec![],
+        }
+    }
+
+    pub fn apply_rule(&mut self, rule: &str) -> Result<(), AclError> {
+        match rule {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.nopass = true;
+                self.password_hashes.clear();
+            }
+            "resetpass" => {
+                self.nopass = false;
+                self.password_hashes.clear();
+            }
+            "allkeys" => self.key_patterns = vec!["*".to_string()],
+            "resetkeys" => self.key_patterns.clear(),
+            "allcommands" => self.apply_rule("+@all")?,
+            "nocommands" => self.apply_rule("-@all")?,
+            "reset" => *self = Self::new(&self.name),
+            _ => {
+                if let Some(password) = rule.strip_prefix('>') {
+                    let hash = hash_password(password);
+                    if !self.password_hashes.contains(&hash) {
+                        self.password_hashes.push(hash);
+                    }
+                    self.nopass = false;
+                } else if let Some(password) = rule.strip_prefix('<') {
+                    let hash = hash_password(password);
+                    self.password_hashes.retain(|h| *h != hash);
+                } else if let Some(pattern) = rule.strip_prefix('~') {
+                    self.key_patterns.push(pattern.to_string());
+                } else if let Some(command) = rule.strip_prefix('+') {
+                    self.add_command_rule(true, command)?;
+                } else if let Some(command) = rule.strip_prefix('-') {
+                    self.add_command_rule(false, command)?;
+                } else {
+                    return Err(AclError::InvalidRule(rule.to_string()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn add_command_rule(&mut self, allow: bool, command: &str) -> Result<(), AclError> {
+        let rule = match command.strip_prefix('@') {
+            Some("all") => {
+                // +@all/-@all override everything that came before
+                self.command_rules.clear();
+                CommandRule::All
+            }
+            Some(category) if CATEGORIES.contains(&category) => {
+                CommandRule::Category(category.to_string())
+            }
+            Some(_) => {
+                let sign = if allow { '+' } else { '-' };
+                return Err(AclError::UnknownCategory(format!("{sign}{command}")));
+            }
+            None if command.is_empty() => return Err(AclError::InvalidRule(command.to_string())),
+            None => CommandRule::Command(command.to_lowercase()),
+        };
+        self.command_rules.push((allow, rule));
+
+        Ok(())
+    }
+
+    pub fn check_password(&self, password: &str) -> bool {
+        self.enabled && (self.nopass || self.password_hashes.contains(&hash_password(password)))
+    }
+
+    pub fn is_nopass(&self) -> bool {
+        self.enabled && self.nopass
+    }
+
+    pub fn check(&self, command: &Command<'_>) -> Result<(), AclError> {
+        let name = command.name().to_lowercase();
+        let categories = command.acl_categories();
+        let allowed = self
+            .command_rules
+            .iter()
+            .rev()
+            .find(|(_, rule)| match rule {
+                CommandRule::All => true,
+                CommandRule::Category(category) => categories.contains(&category.as_str()),
+                CommandRule::Command(command) => *command == name,
+            })
+            .is_some_and(|(allow, _)| *allow);
+        if !allowed {
+            return Err(AclError::CommandNotAllowed(self.name.clone(), name));
+        }
+
+        let keys_allowed = command.keys().iter().all(|key| {
+            key.expect_bulk_string().is_some_and(|key| {
+                self.key_patterns
+                    .iter()
+                    .any(|pattern| glob_match(pattern.as_bytes(), key.as_bytes()))
+            })
+        });
+        if !keys_allowed {
+            return Err(AclError::KeyNotAllowed);
+        }
+
+        Ok(())
+    }
+
+    fn flags(&self) -> Vec<&'static str> {
+        let mut flags = vec![if self.enabled { "on" } else { "off" }];
+        if self.nopass {
+            flags.push("nopass");
+        }
+        if self.key_patterns.iter().any(|p| p == "*") {
+            flags.push("allkeys");
+        }
+        flags
+    }
+
+    fn commands_description(&self) -> String {
+        if self.command_rules.is_empty() {
+            return "-@all".to_string();
+        }
+        self.command_rules
+            .iter()
+            .map(|(allow, rule)| {
+                let sign = if *allow { '+' } else { '-' };
+                match rule {
+                    CommandRule::All => format!("{sign}@all"),
+                    CommandRule::Category(category) => format!("{sign}@{category}"),
+                    CommandRule::Command(command) => format!("{sign}{command}"),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn keys_description(&self) -> String {
+        self.key_patterns
+            .iter()
+            .map(|p| format!("~{p}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// The `ACL LIST` form of the user, e.g. `user default on nopass ~* +@all`.
+    pub fn describe(&self) -> String {
+        let mut description = format!("user {}", self.name);
+        description.push_str(if self.enabled { " on" } else { " off" });
+        if self.nopass {
+            description.push_str(" nopass");
+        }
+        for hash in &self.password_hashes {
+            write!(description, " #{hash}").unwrap();
+        }
+        if !self.key_patterns.is_empty() {
+            write!(description, " {}", self.keys_description()).unwrap();
+        }
+        write!(description, " {}", self.commands_description()).unwrap();
+        description
+    }
+
+    /// The `ACL GETUSER` reply: a flat list of field/value pairs.
+    pub fn to_resp(&self) -> Resp<'static> {
+        Resp::Array(vec![
+            Resp::bulk_string("flags"),
+            Resp::Array(self.flags().into_iter().map(Resp::bulk_string).collect()),
+            Resp::bulk_string("passwords"),
+            Resp::Array(
+                self.password_hashes
+                    .iter()
+                    .map(|h| Resp::BulkString(Cow::Owned(h.clone())))
+                    .collect(),
+            ),
+            Resp::bulk_string("commands"),
+            Resp::BulkString(Cow::Owned(self.commands_description())),
+            Resp::bulk_string("keys"),
+            Resp::BulkString(Cow::Owned(self.keys_description())),
+        ])
+    }
+}
+
+impl Acl {
+    /// Creates the registry with the `default` user, protected by `requirepass` if set.
+    pub fn new(requirepass: Option<&str>) -> Self {
+        let mut default = User::new(DEFAULT_USER);
+        default.enabled = true;
+        match requirepass {
+            Some(password) => default.password_hashes.push(hash_password(password)),
+            None => default.nopass = true,
+        }
+        default.key_patterns.push("*".to_string());
+        default.command_rules.push((true, CommandRule::All));
+
+        Self {
+            users: HashMap::from([(DEFAULT_USER.to_string(), default)]),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&User> {
+        self.users.get(name)
+    }
+
+    pub fn set_user(&mut self, name: &str, rules: &[&str]) -> Result<(), AclError> {
+        // Validate every rule on a copy so a bad rule leaves the user untouched
+        let mut user = self
+            .users
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| User::new(name));
+        for rule in rules {
+            user.apply_rule(rule)?;
+        }
+        self.users.insert(name.to_string(), user);
+
+        Ok(())
+    }
+
+    pub fn users(&self) -> impl Iterator<Item = &User> {
+        let mut users: Vec<_> = self.users.values().collect();
+        users.sort_by(|a, b| a.name.cmp(&b.name));
+        users.into_iter()
+    }
+}
+
+fn hash_password(password: &str) -> String {
+    Sha256::digest(password.as_bytes())
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            write!(hex, "{byte:02x}").unwrap();
+            hex
+        })
+}
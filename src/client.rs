@@ -0,0 +1,64 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use tokio::sync::Notify;
+
+#[derive(Debug)]
+struct ClientHandle {
+    addr: SocketAddr,
+    kill: Arc<Notify>,
+}
+
+/// Registry of live client connections, used to address them by id (e.g. `CLIENT KILL`).
+///
+/// Uses a std mutex so connections can unregister themselves on drop.
+#[derive(Debug, Default)]
+pub struct Clients {
+    next_id: AtomicU64,
+    clients: Mutex<HashMap<u64, ClientHandle>>,
+}
+
+impl Clients {
+    /// Registers a new client, returning its id and the notifier that fires when it's killed.
+    pub fn register(&self, addr: SocketAddr) -> (u64, Arc<Notify>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let kill = Arc::new(Notify::new());
+        self.clients.lock().unwrap().insert(
+            id,
+            ClientHandle {
+                addr,
+                kill: kill.clone(),
+            },
+        );
+        (id, kill)
+    }
+
+    pub fn unregister(&self, id: u64) {
+        self.clients.lock().unwrap().remove(&id);
+    }
+
+    pub fn contains(&self, id: u64) -> bool {
+        self.clients.lock().unwrap().contains_key(&id)
+    }
+
+    /// Number of live connections.
+    pub fn len(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+
+    /// Kills every client accepted by `filter`, returning how many were killed.
+    pub fn kill(&self, filter: impl Fn(u64, &SocketAddr) -> bool) -> usize {
+        let clients = self.clients.lock().unwrap();
+        clients
+            .iter()
+            .filter(|(id, client)| filter(**id, &client.addr))
+            .inspect(|(_, client)| client.kill.notify_one())
+            .count()
+    }
+}
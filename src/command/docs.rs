@@ -0,0 +1,610 @@
+use crate::resp::Resp;
+
+/// Documentation of a command, as reported by `COMMAND DOCS`.
+#[derive(Debug)]
+pub struct CommandDoc {
+    pub name: &'static str,
+    pub summary: &'static str,
+    pub since: &'static str,
+    pub group: &'static str,
+    pub arguments: &'static [Argument],
+}
+
+/// An argument of a command. `oneof` and `block` arguments are made of nested arguments.
+#[derive(Debug)]
+pub struct Argument {
+    pub name: &'static str,
+    pub kind: &'static str,
+    pub token: Option<&'static str>,
+    pub optional: bool,
+    pub multiple: bool,
+    pub arguments: &'static [Argument],
+}
+
+const fn doc(
+    name: &'static str,
+    summary: &'static str,
+    since: &'static str,
+    group: &'static str,
+    arguments: &'static [Argument],
+) -> CommandDoc {
+    CommandDoc {
+        name,
+        summary,
+        since,
+        group,
+        arguments,
+    }
+}
+
+const fn arg(name: &'static str, kind: &'static str) -> Argument {
+    Argument {
+        name,
+        kind,
+        token: None,
+        optional: false,
+        multiple: false,
+        arguments: &[],
+    }
+}
+
+const fn oneof(name: &'static str, arguments: &'static [Argument]) -> Argument {
+    Argument {
+        arguments,
+        ..arg(name, "oneof")
+    }
+}
+
+const fn block(name: &'static str, arguments: &'static [Argument]) -> Argument {
+    Argument {
+        arguments,
+        ..arg(name, "block")
+    }
+}
+
+impl Argument {
+    const fn token(self, token: &'static str) -> Self {
+        Self {
+            token: Some(token),
+            ..self
+        }
+    }
+
+    const fn optional(self) -> Self {
+        Self {
+            optional: true,
+            ..self
+        }
+    }
+
+    const fn multiple(self) -> Self {
+        Self {
+            multiple: true,
+            ..self
+        }
+    }
+}
+
+/// One entry per command in `spec::COMMANDS`, in the same order.
+pub const DOCS: &[CommandDoc] = &[
+    doc(
+        "acl",
+        "A container for Access List Control commands.",
+        "6.0.0",
+        "server",
+        &[],
+    ),
+    doc(
+        "append",
+        "Appends a string to the value of a key. Creates the key if it doesn't exist.",
+        "2.0.0",
+        "string",
+        &[arg("key", "key"), arg("value", "string")],
+    ),
+    doc(
+        "asking",
+        "Signals that a cluster client is following an -ASK redirect.",
+        "3.0.0",
+        "cluster",
+        &[],
+    ),
+    doc(
+        "auth",
+        "Authenticates the connection.",
+        "1.0.0",
+        "connection",
+        &[arg("username", "string").optional(), arg("password", "string")],
+    ),
+    doc(
+        "client",
+        "A container for client connection commands.",
+        "2.4.0",
+        "connection",
+        &[],
+    ),
+    doc(
+        "cluster",
+        "A container for Redis Cluster commands.",
+        "3.0.0",
+        "cluster",
+        &[],
+    ),
+    doc(
+        "command",
+        "Returns detailed information about all commands.",
+        "2.8.13",
+        "server",
+        &[],
+    ),
+    doc(
+        "config",
+        "A container for server configuration commands.",
+        "2.0.0",
+        "server",
+        &[],
+    ),
+    doc(
+        "debug",
+        "A container for debugging commands.",
+        "1.0.0",
+        "server",
+        &[],
+    ),
+    doc(
+        "decr",
+        "Decrements the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.",
+        "1.0.0",
+        "string",
+        &[arg("key", "key")],
+    ),
+    doc(
+        "decrby",
+        "Decrements a number from the integer value of a key. Uses 0 as initial value if the key doesn't exist.",
+        "1.0.0",
+        "string",
+        &[arg("key", "key"), arg("decrement", "integer")],
+    ),
+    doc(
+        "del",
+        "Deletes one or more keys.",
+        "1.0.0",
+        "generic",
+        &[arg("key", "key").multiple()],
+    ),
+    doc(
+        "echo",
+        "Returns the given string.",
+        "1.0.0",
+        "connection",
+        &[arg("message", "string")],
+    ),
+    doc(
+        "exists",
+        "Determines whether one or more keys exist.",
+        "1.0.0",
+        "generic",
+        &[arg("key", "key").multiple()],
+    ),
+    doc(
+        "failover",
+        "Starts a coordinated failover from a server to one of its replicas.",
+        "6.2.0",
+        "server",
+        &[
+            block(
+                "target",
+                &[
+                    arg("host", "string"),
+                    arg("port", "integer"),
+                    arg("force", "pure-token").token("FORCE").optional(),
+                ],
+            )
+            .token("TO")
+            .optional(),
+            arg("abort", "pure-token").token("ABORT").optional(),
+            arg("milliseconds", "integer").token("TIMEOUT").optional(),
+        ],
+    ),
+    doc(
+        "get",
+        "Returns the string value of a key.",
+        "1.0.0",
+        "string",
+        &[arg("key", "key")],
+    ),
+    doc(
+        "getrange",
+        "Returns a substring of the string stored at a key.",
+        "2.4.0",
+        "string",
+        &[arg("key", "key"), arg("start", "integer"), arg("end", "integer")],
+    ),
+    doc(
+        "getset",
+        "Returns the previous string value of a key after setting it to a new value.",
+        "1.0.0",
+        "string",
+        &[arg("key", "key"), arg("value", "string")],
+    ),
+    doc(
+        "hello",
+        "Handshakes with the Redis server.",
+        "6.0.0",
+        "connection",
+        &[block(
+            "arguments",
+            &[
+                arg("protover", "integer"),
+                block("auth", &[arg("username", "string"), arg("password", "string")])
+                    .token("AUTH")
+                    .optional(),
+            ],
+        )
+        .optional()],
+    ),
+    doc(
+        "incr",
+        "Increments the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.",
+        "1.0.0",
+        "string",
+        &[arg("key", "key")],
+    ),
+    doc(
+        "incrby",
+        "Increments the integer value of a key by a number. Uses 0 as initial value if the key doesn't exist.",
+        "1.0.0",
+        "string",
+        &[arg("key", "key"), arg("increment", "integer")],
+    ),
+    doc(
+        "info",
+        "Returns information and statistics about the server.",
+        "1.0.0",
+        "server",
+        &[arg("section", "string").optional().multiple()],
+    ),
+    doc(
+        "keys",
+        "Returns all key names that match a pattern.",
+        "1.0.0",
+        "generic",
+        &[arg("pattern", "pattern")],
+    ),
+    doc(
+        "lcs",
+        "Finds the longest common substring.",
+        "7.0.0",
+        "string",
+        &[
+            arg("key1", "key"),
+            arg("key2", "key"),
+            arg("len", "pure-token").token("LEN").optional(),
+            arg("idx", "pure-token").token("IDX").optional(),
+            arg("min-match-len", "integer")
+                .token("MINMATCHLEN")
+                .optional(),
+            arg("withmatchlen", "pure-token")
+                .token("WITHMATCHLEN")
+                .optional(),
+        ],
+    ),
+    doc(
+        "lolwut",
+        "Displays computer art and the Redis version.",
+        "5.0.0",
+        "server",
+        &[arg("version", "integer").token("VERSION").optional()],
+    ),
+    doc(
+        "lpushx",
+        "Prepends one or more elements to a list only when the list exists.",
+        "2.2.0",
+        "list",
+        &[arg("key", "key"), arg("element", "string").multiple()],
+    ),
+    doc(
+        "memory",
+        "A container for memory diagnostics commands.",
+        "4.0.0",
+        "server",
+        &[],
+    ),
+    doc(
+        "monitor",
+        "Listens for all requests received by the server in real-time.",
+        "1.0.0",
+        "server",
+        &[],
+    ),
+    doc(
+        "object",
+        "A container for object introspection commands.",
+        "2.2.3",
+        "generic",
+        &[],
+    ),
+    doc(
+        "ping",
+        "Returns the server's liveliness response.",
+        "1.0.0",
+        "connection",
+        &[arg("message", "string").optional()],
+    ),
+    doc(
+        "psync",
+        "An internal command used in replication.",
+        "2.8.0",
+        "server",
+        &[arg("replicationid", "string"), arg("offset", "integer")],
+    ),
+    doc(
+        "publish",
+        "Posts a message to a channel.",
+        "2.0.0",
+        "pubsub",
+        &[arg("channel", "string"), arg("message", "string")],
+    ),
+    doc(
+        "pubsub",
+        "A container for Pub/Sub commands.",
+        "2.8.0",
+        "pubsub",
+        &[],
+    ),
+    doc(
+        "quit",
+        "Closes the connection.",
+        "1.0.0",
+        "connection",
+        &[],
+    ),
+    doc(
+        "readonly",
+        "Enables read-only queries for a connection to a cluster replica node.",
+        "3.0.0",
+        "cluster",
+        &[],
+    ),
+    doc(
+        "readwrite",
+        "Enables read-write queries for a connection to a cluster replica node.",
+        "3.0.0",
+        "cluster",
+        &[],
+    ),
+    doc(
+        "replconf",
+        "An internal command for configuring the replication stream.",
+        "3.0.0",
+        "server",
+        &[],
+    ),
+    doc(
+        "reset",
+        "Resets the connection.",
+        "6.2.0",
+        "connection",
+        &[],
+    ),
+    doc(
+        "rpushx",
+        "Appends an element to a list only when the list exists.",
+        "2.2.0",
+        "list",
+        &[arg("key", "key"), arg("element", "string").multiple()],
+    ),
+    doc(
+        "save",
+        "Synchronously saves the database to disk.",
+        "1.0.0",
+        "server",
+        &[],
+    ),
+    doc(
+        "scan",
+        "Iterates over the key names in the database.",
+        "2.8.0",
+        "generic",
+        &[
+            arg("cursor", "integer"),
+            arg("pattern", "pattern").token("MATCH").optional(),
+            arg("count", "integer").token("COUNT").optional(),
+            arg("type", "string").token("TYPE").optional(),
+        ],
+    ),
+    doc(
+        "select",
+        "Changes the selected database.",
+        "1.0.0",
+        "connection",
+        &[arg("index", "integer")],
+    ),
+    doc(
+        "set",
+        "Sets the string value of a key, ignoring its type. The key is created if it doesn't exist.",
+        "1.0.0",
+        "string",
+        &[
+            arg("key", "key"),
+            arg("value", "string"),
+            oneof(
+                "expiration",
+                &[
+                    arg("seconds", "integer").token("EX"),
+                    arg("milliseconds", "integer").token("PX"),
+                    arg("unix-time-seconds", "unix-time").token("EXAT"),
+                    arg("unix-time-milliseconds", "unix-time").token("PXAT"),
+                ],
+            )
+            .optional(),
+        ],
+    ),
+    doc(
+        "shutdown",
+        "Synchronously saves the database(s) to disk and shuts down the Redis server.",
+        "1.0.0",
+        "server",
+        &[
+            oneof(
+                "save-selector",
+                &[
+                    arg("nosave", "pure-token").token("NOSAVE"),
+                    arg("save", "pure-token").token("SAVE"),
+                ],
+            )
+            .optional(),
+            arg("now", "pure-token").token("NOW").optional(),
+            arg("force", "pure-token").token("FORCE").optional(),
+        ],
+    ),
+    doc(
+        "slowlog",
+        "A container for slow log commands.",
+        "2.2.12",
+        "server",
+        &[],
+    ),
+    doc(
+        "spublish",
+        "Posts a message to a shard channel.",
+        "7.0.0",
+        "pubsub",
+        &[arg("shardchannel", "string"), arg("message", "string")],
+    ),
+    doc(
+        "ssubscribe",
+        "Listens for messages published to shard channels.",
+        "7.0.0",
+        "pubsub",
+        &[arg("shardchannel", "string").multiple()],
+    ),
+    doc(
+        "subscribe",
+        "Listens for messages published to channels.",
+        "2.0.0",
+        "pubsub",
+        &[arg("channel", "string").multiple()],
+    ),
+    doc(
+        "substr",
+        "Returns a substring from a string value.",
+        "1.0.0",
+        "string",
+        &[arg("key", "key"), arg("start", "integer"), arg("end", "integer")],
+    ),
+    doc(
+        "sunsubscribe",
+        "Stops listening to messages posted to shard channels.",
+        "7.0.0",
+        "pubsub",
+        &[arg("shardchannel", "string").optional().multiple()],
+    ),
+    doc(
+        "type",
+        "Determines the type of value stored at a key.",
+        "1.0.0",
+        "generic",
+        &[arg("key", "key")],
+    ),
+    doc(
+        "unsubscribe",
+        "Stops listening to messages posted to channels.",
+        "2.0.0",
+        "pubsub",
+        &[arg("channel", "string").optional().multiple()],
+    ),
+    doc(
+        "wait",
+        "Blocks until the asynchronous replication of all preceding write commands sent by the connection is completed.",
+        "3.0.0",
+        "generic",
+        &[arg("numreplicas", "integer"), arg("timeout", "integer")],
+    ),
+    doc(
+        "xadd",
+        "Appends a new message to a stream. Creates the key if it doesn't exist.",
+        "5.0.0",
+        "stream",
+        &[
+            arg("key", "key"),
+            arg("nomkstream", "pure-token")
+                .token("NOMKSTREAM")
+                .optional(),
+            oneof(
+                "id-selector",
+                &[arg("auto-id", "pure-token").token("*"), arg("id", "string")],
+            ),
+            block("data", &[arg("field", "string"), arg("value", "string")]).multiple(),
+        ],
+    ),
+    doc(
+        "xrange",
+        "Returns the messages from a stream within a range of IDs.",
+        "5.0.0",
+        "stream",
+        &[arg("key", "key"), arg("start", "string"), arg("end", "string")],
+    ),
+    doc(
+        "xread",
+        "Returns messages from multiple streams with IDs greater than the ones requested.",
+        "5.0.0",
+        "stream",
+        &[block(
+            "streams",
+            &[arg("key", "key").multiple(), arg("id", "string").multiple()],
+        )
+        .token("STREAMS")],
+    ),
+];
+
+pub fn lookup(name: &str) -> Option<&'static CommandDoc> {
+    DOCS.iter().find(|doc| doc.name.eq_ignore_ascii_case(name))
+}
+
+impl CommandDoc {
+    /// The documentation map of `COMMAND DOCS`, leaving out `arguments` when there are none.
+    pub fn to_resp(&self) -> Resp<'static> {
+        let mut fields = vec![
+            (
+                Resp::bulk_string("summary"),
+                Resp::bulk_string(self.summary),
+            ),
+            (Resp::bulk_string("since"), Resp::bulk_string(self.since)),
+            (Resp::bulk_string("group"), Resp::bulk_string(self.group)),
+        ];
+        if !self.arguments.is_empty() {
+            fields.push((
+                Resp::bulk_string("arguments"),
+                Resp::Array(self.arguments.iter().map(Argument::to_resp).collect()),
+            ));
+        }
+        Resp::map(fields)
+    }
+}
+
+impl Argument {
+    fn to_resp(&self) -> Resp<'static> {
+        let mut fields = vec![
+            (Resp::bulk_string("name"), Resp::bulk_string(self.name)),
+            (Resp::bulk_string("type"), Resp::bulk_string(self.kind)),
+        ];
+        if let Some(token) = self.token {
+            fields.push((Resp::bulk_string("token"), Resp::bulk_string(token)));
+        }
+        let flags: Vec<_> = [("optional", self.optional), ("multiple", self.multiple)]
+            .into_iter()
+            .filter(|(_, set)| *set)
+            .map(|(flag, _)| Resp::simple_string(flag))
+            .collect();
+        if !flags.is_empty() {
+            fields.push((Resp::bulk_string("flags"), Resp::Array(flags)));
+        }
+        if !self.arguments.is_empty() {
+            fields.push((
+                Resp::bulk_string("arguments"),
+                Resp::Array(self.arguments.iter().map(Argument::to_resp).collect()),
+            ));
+        }
+        Resp::map(fields)
+    }
+}
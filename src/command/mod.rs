@@ -1,9 +1,15 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, time::Duration};
 
-use crate::resp::{Resp, RespError};
+use crate::{
+    pubsub::ChannelKind,
+    resp::{Resp, RespError},
+    utils::get_epoch_ms,
+};
 use thiserror::Error;
 
+pub mod docs;
 pub mod get;
+pub mod spec;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ConfigItem {
@@ -11,33 +17,266 @@ pub enum ConfigItem {
     DbFileName,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum AclSubcommand<'c> {
+    SetUser(Resp<'c>, Vec<Resp<'c>>),
+    GetUser(Resp<'c>),
+    List,
+    WhoAmI,
+    Cat,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientKillFilter {
+    Id(u64),
+    Addr(String),
+    SkipMe(bool),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientSubcommand {
+    Id,
+    /// Legacy `CLIENT KILL addr:port` form
+    KillAddr(String),
+    Kill(Vec<ClientKillFilter>),
+    NoEvict(bool),
+    NoTouch(bool),
+    /// `CLIENT TRACKING ON|OFF`, `None` turns tracking off
+    Tracking(Option<TrackingOptions>),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TrackingOptions {
+    pub redirect: Option<u64>,
+    pub bcast: bool,
+    pub prefixes: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjectSubcommand<'c> {
+    Encoding(Resp<'c>),
+    RefCount(Resp<'c>),
+}
+
+/// Cluster introspection answered as a standalone node, for clients that probe for cluster mode.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClusterSubcommand {
+    Info,
+    MyId,
+    Slots,
+    Shards,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MemorySubcommand {
+    Doctor,
+    Stats,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SlowLogSubcommand {
+    Get(Option<i64>),
+    Len,
+    Reset,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandSubcommand {
+    /// `COMMAND INFO [command ...]`, every command when none are given
+    Info(Vec<String>),
+    Count,
+    Docs(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanOptions {
+    pub cursor: u64,
+    pub pattern: Option<String>,
+    pub count: usize,
+    pub value_type: Option<String>,
+}
+
+/// `SHUTDOWN [NOSAVE|SAVE] [NOW] [FORCE]`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ShutdownOptions {
+    /// `Some(false)` for `NOSAVE`, `Some(true)` for `SAVE`
+    pub save: Option<bool>,
+    /// Exit without waiting for replicas to catch up
+    pub now: bool,
+    /// Exit even if the final save fails
+    pub force: bool,
+}
+
+/// `LCS key1 key2 [LEN] [IDX] [MINMATCHLEN len] [WITHMATCHLEN]`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LcsOptions {
+    /// Reply with the length of the subsequence only
+    pub len: bool,
+    /// Reply with the ranges of the matches instead of the subsequence
+    pub idx: bool,
+    /// Leave out matches shorter than this from the `IDX` reply
+    pub min_match_len: usize,
+    pub with_match_len: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DebugSubcommand {
+    SetActiveExpire(bool),
+    ChangeReplId,
+    Reload,
+    /// `DEBUG DUMP-KEY key`, a JSON description of a key for inspection
+    DumpKey(String),
+    /// `DEBUG STRINGMATCH-LEN pattern string`, whether the glob pattern matches
+    StringMatchLen(String, String),
+    /// `DEBUG SLEEP seconds`, blocks only the calling connection
+    Sleep(Duration),
+    /// `DEBUG OBJECT key`, internals of a stream, WRONGTYPE for any other value
+    Object(String),
+    /// `DEBUG REPLICATION-OFFSET`, the master offset and the last one each replica acknowledged
+    ReplicationOffset,
+    /// `DEBUG FLUSHALL`, empties the dataset without propagating or notifying anyone
+    FlushAll,
+    /// `DEBUG JMAP`, a no-op kept for compatibility
+    Jmap,
+}
+
+/// `SET` expiration options: relative `EX`/`PX` or absolute `EXAT`/`PXAT`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetExpiry {
+    Ex(i64),
+    Px(i64),
+    ExAt(i64),
+    PxAt(i64),
+}
+
+impl SetExpiry {
+    /// The deadline as a unix timestamp in milliseconds, which is how expiries are stored,
+    /// or `None` if it doesn't fit in an `i64`.
+    pub fn expires_at(&self) -> Option<i64> {
+        match *self {
+            SetExpiry::Ex(seconds) => seconds
+                .checked_mul(1000)?
+                .checked_add(get_epoch_ms() as i64),
+            SetExpiry::Px(milliseconds) => milliseconds.checked_add(get_epoch_ms() as i64),
+            SetExpiry::ExAt(seconds) => seconds.checked_mul(1000),
+            SetExpiry::PxAt(milliseconds) => Some(milliseconds),
+        }
+    }
+
+    pub fn option(&self) -> (&'static str, i64) {
+        match *self {
+            SetExpiry::Ex(value) => ("EX", value),
+            SetExpiry::Px(value) => ("PX", value),
+            SetExpiry::ExAt(value) => ("EXAT", value),
+            SetExpiry::PxAt(value) => ("PXAT", value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PubSubSubcommand {
+    Channels(ChannelKind, Option<String>),
+    NumSub(ChannelKind, Vec<String>),
+    NumPat,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Command<'c> {
     Ping,
     Echo(String),
     Get(Resp<'c>),
-    Set(Resp<'c>, Resp<'c>, Option<i64>),
+    GetRange(Resp<'c>, i64, i64),
+    /// Legacy alias of `GETRANGE`
+    Substr(Resp<'c>, i64, i64),
+    Set(Resp<'c>, Resp<'c>, Option<SetExpiry>),
+    /// `GETSET key value`, deprecated in favour of `SET key value GET`
+    GetSet(Resp<'c>, Resp<'c>),
+    Append(Resp<'c>, Resp<'c>),
+    Incr(Resp<'c>),
+    Decr(Resp<'c>),
+    IncrBy(Resp<'c>, i64),
+    DecrBy(Resp<'c>, i64),
+    Lcs(Resp<'c>, Resp<'c>, LcsOptions),
+    Del(Vec<Resp<'c>>),
+    Exists(Vec<Resp<'c>>),
+    /// `LPUSHX key element [element ...]`, pushes only to existing lists
+    LPushX(Resp<'c>, Vec<Resp<'c>>),
+    /// `RPUSHX key element [element ...]`, pushes only to existing lists
+    RPushX(Resp<'c>, Vec<Resp<'c>>),
     ConfigGet(ConfigItem),
     Keys(Resp<'c>),
+    /// `SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]`
+    Scan(ScanOptions),
     Info(Option<Resp<'c>>),
     Save,
+    Lolwut,
+    /// `FAILOVER [TO host port [FORCE]] [ABORT] [TIMEOUT ms]`, recognized but never performed
+    Failover,
+    Shutdown(ShutdownOptions),
     ReplConf(Resp<'c>, Resp<'c>),
     Psync(Resp<'c>, Resp<'c>),
-    Wait(Resp<'c>, Resp<'c>),
+    Wait(i64, i64),
     Select(Resp<'c>),
     Type(Resp<'c>),
-    XAdd(Resp<'c>, Resp<'c>, Vec<Resp<'c>>),
+    /// `XADD key [NOMKSTREAM] id field value [field value ...]`, the flag is `NOMKSTREAM`
+    XAdd(Resp<'c>, Resp<'c>, Vec<Resp<'c>>, bool),
     XRange(Resp<'c>, Resp<'c>, Resp<'c>),
     XRead(Resp<'c>, Vec<Resp<'c>>, Vec<Resp<'c>>),
+    Auth(Option<Resp<'c>>, Resp<'c>),
+    Quit,
+    /// `HELLO [protover [AUTH username password]]`
+    Hello(Option<i64>, Option<(Resp<'c>, Resp<'c>)>),
+    Reset,
+    /// `READONLY`, `READWRITE` and `ASKING` are only accepted for cluster client compatibility
+    ReadOnly,
+    ReadWrite,
+    Asking,
+    Monitor,
+    Acl(AclSubcommand<'c>),
+    Client(ClientSubcommand),
+    SlowLog(SlowLogSubcommand),
+    Object(ObjectSubcommand<'c>),
+    Memory(MemorySubcommand),
+    Cluster(ClusterSubcommand),
+    Debug(DebugSubcommand),
+    Commands(CommandSubcommand),
+    Subscribe(ChannelKind, Vec<String>),
+    Unsubscribe(ChannelKind, Vec<String>),
+    Publish(ChannelKind, String, String),
+    PubSub(PubSubSubcommand),
 }
 
 #[derive(Debug, Error)]
 pub enum CommandError {
-    #[error("Protocol parsing error")]
+    #[error("Protocol parsing error: {0}")]
     ProtocolError(#[from] RespError),
 
     #[error("Unsupported command: {0}")]
-    UnsupportedCommand(String),
+    UnsupportedCommand(String, Vec<String>),
+
+    #[error("Wrong number of arguments for {0}")]
+    WrongNumberOfArguments(String),
+
+    #[error("Value is not an integer")]
+    NotAnInteger,
+
+    #[error("Value is not a valid float")]
+    NotAFloat,
+
+    #[error("Invalid cursor")]
+    InvalidCursor,
+
+    #[error("Timeout is negative")]
+    NegativeTimeout,
+
+    #[error("Unknown {0} subcommand: {1}")]
+    UnknownSubcommand(&'static str, String),
+
+    #[error("Invalid expire time in {0}")]
+    InvalidExpireTime(&'static str),
+
+    #[error("Both LEN and IDX are given to LCS")]
+    LcsLenAndIdx,
 
     #[error("Incorrect command format")]
     IncorrectFormat,
@@ -45,18 +284,142 @@ pub enum CommandError {
 
 impl<'c> Command<'c> {
     pub fn is_write_command(&self) -> bool {
+        matches!(
+            self,
+            Command::Set(_, _, _)
+                | Command::GetSet(_, _)
+                | Command::Append(_, _)
+                | Command::Incr(_)
+                | Command::Decr(_)
+                | Command::IncrBy(_, _)
+                | Command::DecrBy(_, _)
+                | Command::Del(_)
+                | Command::LPushX(_, _)
+                | Command::RPushX(_, _)
+        )
+    }
+
+    pub fn should_account(&self) -> bool {
+        matches!(
+            self,
+            Command::Set(_, _, _)
+                | Command::GetSet(_, _)
+                | Command::Append(_, _)
+                | Command::Incr(_)
+                | Command::Decr(_)
+                | Command::IncrBy(_, _)
+                | Command::DecrBy(_, _)
+                | Command::Del(_)
+                | Command::LPushX(_, _)
+                | Command::RPushX(_, _)
+                | Command::Ping
+                | Command::ReplConf(_, _)
+        )
+    }
+
+    /// What `INCR`, `DECR`, `INCRBY` and `DECRBY` add to their key, `None` for other commands
+    /// and for decrements that can't be negated.
+    pub fn increment(&self) -> Option<i64> {
         match self {
-            Command::Set(_, _, _) => true,
-            _ => false,
+            Command::Incr(_) => Some(1),
+            Command::Decr(_) => Some(-1),
+            Command::IncrBy(_, increment) => Some(*increment),
+            Command::DecrBy(_, decrement) => decrement.checked_neg(),
+            _ => None,
         }
     }
 
-    pub fn should_account(&self) -> bool {
+    pub fn acl_categories(&self) -> &'static [&'static str] {
         match self {
-            Command::Set(_, _, _) => true,
-            Command::Ping => true,
-            Command::ReplConf(_, _) => true,
-            _ => false,
+            Command::Ping | Command::Echo(_) => &["fast", "connection"],
+            Command::Auth(_, _) | Command::Quit | Command::Hello(_, _) | Command::Reset => {
+                &["fast", "connection"]
+            }
+            Command::ReadOnly | Command::ReadWrite | Command::Asking => &["fast", "connection"],
+            Command::Commands(_) => &["slow", "connection"],
+            Command::Get(_) => &["read", "string", "fast"],
+            Command::GetRange(_, _, _) | Command::Substr(_, _, _) => &["read", "string", "slow"],
+            Command::Lcs(_, _, _) => &["read", "string", "slow"],
+            Command::Set(_, _, _) | Command::GetSet(_, _) => &["write", "string", "slow"],
+            Command::Append(_, _)
+            | Command::Incr(_)
+            | Command::Decr(_)
+            | Command::IncrBy(_, _)
+            | Command::DecrBy(_, _) => &["write", "string", "fast"],
+            Command::Del(_) => &["keyspace", "write", "slow"],
+            Command::Exists(_) => &["keyspace", "read", "fast"],
+            Command::LPushX(_, _) | Command::RPushX(_, _) => &["write", "list", "fast"],
+            Command::Keys(_) => &["keyspace", "read", "slow", "dangerous"],
+            Command::Scan(_) | Command::Object(_) => &["keyspace", "read", "slow"],
+            Command::Type(_) => &["keyspace", "read", "fast"],
+            Command::Lolwut => &["read", "fast"],
+            Command::Select(_) => &["keyspace", "fast"],
+            Command::XAdd(_, _, _, _) => &["write", "stream", "fast"],
+            Command::XRange(_, _, _) | Command::XRead(_, _, _) => &["read", "stream", "slow"],
+            Command::Wait(_, _) => &["slow", "connection"],
+            Command::ConfigGet(_)
+            | Command::Info(_)
+            | Command::Save
+            | Command::Failover
+            | Command::Shutdown(_)
+            | Command::ReplConf(_, _)
+            | Command::Psync(_, _)
+            | Command::Acl(_) => &["admin", "slow", "dangerous"],
+            Command::Client(_) => &["admin", "slow", "dangerous", "connection"],
+            Command::SlowLog(_) | Command::Monitor | Command::Debug(_) => {
+                &["admin", "slow", "dangerous"]
+            }
+            Command::Subscribe(_, _) | Command::Unsubscribe(_, _) | Command::PubSub(_) => {
+                &["pubsub", "slow"]
+            }
+            Command::Publish(_, _, _) => &["pubsub", "fast"],
+            Command::Memory(_) => &["slow"],
+            Command::Cluster(_) => &["slow"],
+        }
+    }
+
+    /// The command as it was sent, one string per argument.
+    pub fn argv(&self) -> Vec<String> {
+        let Resp::Array(args) = Resp::from(self.clone()) else {
+            return vec![];
+        };
+        args.iter()
+            .map(|arg| match arg {
+                Resp::Integer(i) => i.to_string(),
+                Resp::SimpleString(s)
+                | Resp::SimpleError(s)
+                | Resp::BulkString(s)
+                | Resp::Verbatim(_, s) => s.to_string(),
+                Resp::Null | Resp::Array(_) | Resp::Map(_) | Resp::Push(_) => String::new(),
+            })
+            .collect()
+    }
+
+    /// Keys the command reads or writes, used to enforce ACL key patterns.
+    pub fn keys(&self) -> Vec<&Resp<'c>> {
+        match self {
+            Command::Get(key)
+            | Command::GetRange(key, _, _)
+            | Command::Substr(key, _, _)
+            | Command::Set(key, _, _)
+            | Command::GetSet(key, _)
+            | Command::Append(key, _)
+            | Command::Incr(key)
+            | Command::Decr(key)
+            | Command::IncrBy(key, _)
+            | Command::DecrBy(key, _)
+            | Command::LPushX(key, _)
+            | Command::RPushX(key, _)
+            | Command::Type(key)
+            | Command::XAdd(key, _, _, _)
+            | Command::XRange(key, _, _)
+            | Command::Object(ObjectSubcommand::Encoding(key))
+            | Command::Object(ObjectSubcommand::RefCount(key)) => vec![key],
+            Command::Lcs(key1, key2, _) => vec![key1, key2],
+            Command::Del(keys) | Command::Exists(keys) | Command::XRead(_, keys, _) => {
+                keys.iter().collect()
+            }
+            _ => vec![],
         }
     }
 
@@ -65,24 +428,54 @@ impl<'c> Command<'c> {
             Command::Ping => Command::Ping,
             Command::Echo(msg) => Command::Echo(msg),
             Command::Get(resp) => Command::Get(resp.into_owned()),
+            Command::GetRange(key, start, end) => Command::GetRange(key.into_owned(), start, end),
+            Command::Substr(key, start, end) => Command::Substr(key.into_owned(), start, end),
+            Command::Lcs(key1, key2, options) => {
+                Command::Lcs(key1.into_owned(), key2.into_owned(), options)
+            }
             Command::Set(resp, resp1, resp2) => {
                 Command::Set(resp.into_owned(), resp1.into_owned(), resp2)
             }
+            Command::GetSet(key, value) => Command::GetSet(key.into_owned(), value.into_owned()),
+            Command::Append(key, value) => Command::Append(key.into_owned(), value.into_owned()),
+            Command::Incr(key) => Command::Incr(key.into_owned()),
+            Command::Decr(key) => Command::Decr(key.into_owned()),
+            Command::IncrBy(key, increment) => Command::IncrBy(key.into_owned(), increment),
+            Command::DecrBy(key, decrement) => Command::DecrBy(key.into_owned(), decrement),
+            Command::Del(keys) => {
+                Command::Del(keys.into_iter().map(|key| key.into_owned()).collect())
+            }
+            Command::Exists(keys) => {
+                Command::Exists(keys.into_iter().map(|key| key.into_owned()).collect())
+            }
+            Command::LPushX(key, elements) => Command::LPushX(
+                key.into_owned(),
+                elements.into_iter().map(Resp::into_owned).collect(),
+            ),
+            Command::RPushX(key, elements) => Command::RPushX(
+                key.into_owned(),
+                elements.into_iter().map(Resp::into_owned).collect(),
+            ),
             Command::ConfigGet(config_item) => Command::ConfigGet(config_item),
             Command::Keys(resp) => Command::Keys(resp.into_owned()),
+            Command::Scan(options) => Command::Scan(options),
             Command::Info(resp) => Command::Info(resp.map(|resp| resp.into_owned())),
             Command::Save => Command::Save,
+            Command::Lolwut => Command::Lolwut,
+            Command::Failover => Command::Failover,
+            Command::Shutdown(options) => Command::Shutdown(options),
             Command::ReplConf(resp, resp1) => {
                 Command::ReplConf(resp.into_owned(), resp1.into_owned())
             }
             Command::Psync(resp, resp1) => Command::Psync(resp.into_owned(), resp1.into_owned()),
-            Command::Wait(resp, resp1) => Command::Wait(resp.into_owned(), resp1.into_owned()),
+            Command::Wait(numofreplicas, timeout) => Command::Wait(numofreplicas, timeout),
             Command::Select(resp) => Command::Select(resp.into_owned()),
             Command::Type(resp) => Command::Type(resp.into_owned()),
-            Command::XAdd(key, id, array) => Command::XAdd(
+            Command::XAdd(key, id, array, nomkstream) => Command::XAdd(
                 key.into_owned(),
                 id.into_owned(),
                 array.into_iter().map(|i| i.into_owned()).collect(),
+                nomkstream,
             ),
             Command::XRange(key, from, to) => {
                 Command::XRange(key.into_owned(), from.into_owned(), to.into_owned())
@@ -92,6 +485,44 @@ impl<'c> Command<'c> {
                 streams.into_iter().map(|s| s.into_owned()).collect(),
                 ids.into_iter().map(|id| id.into_owned()).collect(),
             ),
+            Command::Auth(username, password) => Command::Auth(
+                username.map(|username| username.into_owned()),
+                password.into_owned(),
+            ),
+            Command::Quit => Command::Quit,
+            Command::Hello(protover, auth) => Command::Hello(
+                protover,
+                auth.map(|(username, password)| (username.into_owned(), password.into_owned())),
+            ),
+            Command::Reset => Command::Reset,
+            Command::ReadOnly => Command::ReadOnly,
+            Command::ReadWrite => Command::ReadWrite,
+            Command::Asking => Command::Asking,
+            Command::Monitor => Command::Monitor,
+            Command::Acl(subcommand) => Command::Acl(match subcommand {
+                AclSubcommand::SetUser(username, rules) => AclSubcommand::SetUser(
+                    username.into_owned(),
+                    rules.into_iter().map(|r| r.into_owned()).collect(),
+                ),
+                AclSubcommand::GetUser(username) => AclSubcommand::GetUser(username.into_owned()),
+                AclSubcommand::List => AclSubcommand::List,
+                AclSubcommand::WhoAmI => AclSubcommand::WhoAmI,
+                AclSubcommand::Cat => AclSubcommand::Cat,
+            }),
+            Command::Client(subcommand) => Command::Client(subcommand),
+            Command::SlowLog(subcommand) => Command::SlowLog(subcommand),
+            Command::Memory(subcommand) => Command::Memory(subcommand),
+            Command::Cluster(subcommand) => Command::Cluster(subcommand),
+            Command::Object(subcommand) => Command::Object(match subcommand {
+                ObjectSubcommand::Encoding(key) => ObjectSubcommand::Encoding(key.into_owned()),
+                ObjectSubcommand::RefCount(key) => ObjectSubcommand::RefCount(key.into_owned()),
+            }),
+            Command::Debug(subcommand) => Command::Debug(subcommand),
+            Command::Commands(subcommand) => Command::Commands(subcommand),
+            Command::Subscribe(kind, channels) => Command::Subscribe(kind, channels),
+            Command::Unsubscribe(kind, channels) => Command::Unsubscribe(kind, channels),
+            Command::Publish(kind, channel, message) => Command::Publish(kind, channel, message),
+            Command::PubSub(subcommand) => Command::PubSub(subcommand),
         }
     }
 
@@ -101,6 +532,9 @@ impl<'c> Command<'c> {
         let (packet, rest) = Resp::parse_inner(input)?;
         let result = match packet {
             Resp::Array(array) => match array.first().ok_or(IncorrectFormat)? {
+                Resp::BulkString(Cow::Borrowed(c)) if !has_valid_arity(c, array.len()) => {
+                    Err(WrongNumberOfArguments(c.to_string()))
+                }
                 Resp::BulkString(Cow::Borrowed(c)) => match &c.to_uppercase().as_str() {
                     &"PING" => Ok(Ping),
                     &"ECHO" => {
@@ -114,28 +548,127 @@ impl<'c> Command<'c> {
                         let key = array.get(1).ok_or(IncorrectFormat)?;
                         Ok(Self::Get(key.clone()))
                     }
+                    &"GETRANGE" | &"SUBSTR" => {
+                        let key = array.get(1).ok_or(IncorrectFormat)?;
+                        let start = array.get(2).ok_or(IncorrectFormat)?;
+                        let end = array.get(3).ok_or(IncorrectFormat)?;
+                        let start = start.expect_integer().ok_or(NotAnInteger)?;
+                        let end = end.expect_integer().ok_or(NotAnInteger)?;
+                        if c.eq_ignore_ascii_case("SUBSTR") {
+                            Ok(Self::Substr(key.clone(), start, end))
+                        } else {
+                            Ok(Self::GetRange(key.clone(), start, end))
+                        }
+                    }
+                    &"LCS" => {
+                        let key1 = array.get(1).ok_or(IncorrectFormat)?;
+                        let key2 = array.get(2).ok_or(IncorrectFormat)?;
+                        let mut options = LcsOptions::default();
+                        let mut args = array.get(3..).unwrap_or_default().iter();
+                        while let Some(arg) = args.next() {
+                            let arg = arg.expect_bulk_string().ok_or(IncorrectFormat)?;
+                            match arg.to_uppercase().as_str() {
+                                "LEN" => options.len = true,
+                                "IDX" => options.idx = true,
+                                "WITHMATCHLEN" => options.with_match_len = true,
+                                "MINMATCHLEN" => {
+                                    let len = args.next().ok_or(IncorrectFormat)?;
+                                    let len = len.expect_integer().ok_or(NotAnInteger)?;
+                                    options.min_match_len = len.max(0) as usize;
+                                }
+                                _ => return Err(IncorrectFormat),
+                            }
+                        }
+                        if options.len && options.idx {
+                            return Err(LcsLenAndIdx);
+                        }
+                        Ok(Self::Lcs(key1.clone(), key2.clone(), options))
+                    }
 
                     &"SET" => {
                         let key = array.get(1).ok_or(IncorrectFormat)?;
                         let value = array.get(2).ok_or(IncorrectFormat)?;
-                        assert!(matches!(array.get(3), Some(Resp::BulkString(_)) | None));
-                        let expiry = array.get(4).and_then(|e| e.expect_integer());
+                        let expiry = match &array[3..] {
+                            [] => None,
+                            [option, value] => {
+                                let option = option.expect_bulk_string().ok_or(IncorrectFormat)?;
+                                let value = value.expect_integer().ok_or(NotAnInteger)?;
+                                let expiry = match option.to_uppercase().as_str() {
+                                    "EX" => SetExpiry::Ex(value),
+                                    "PX" => SetExpiry::Px(value),
+                                    "EXAT" => SetExpiry::ExAt(value),
+                                    "PXAT" => SetExpiry::PxAt(value),
+                                    _ => return Err(IncorrectFormat),
+                                };
+                                if value <= 0 || expiry.expires_at().is_none() {
+                                    return Err(InvalidExpireTime("set"));
+                                }
+                                Some(expiry)
+                            }
+                            _ => return Err(IncorrectFormat),
+                        };
                         Ok(Self::Set(key.clone(), value.clone(), expiry))
                     }
-                    &"CONFIG" => match array.get(1).ok_or(IncorrectFormat)? {
-                        Resp::BulkString(Cow::Borrowed("GET")) => {
-                            match array.get(2).ok_or(IncorrectFormat)? {
-                                Resp::BulkString(Cow::Borrowed("dir")) => {
-                                    Ok(Self::ConfigGet(ConfigItem::Dir))
-                                }
-                                Resp::BulkString(Cow::Borrowed("dbfilename")) => {
-                                    Ok(Self::ConfigGet(ConfigItem::DbFileName))
+                    &"GETSET" => {
+                        let key = array.get(1).ok_or(IncorrectFormat)?;
+                        let value = array.get(2).ok_or(IncorrectFormat)?;
+                        Ok(Self::GetSet(key.clone(), value.clone()))
+                    }
+                    &"APPEND" => {
+                        let key = array.get(1).ok_or(IncorrectFormat)?;
+                        let value = array.get(2).ok_or(IncorrectFormat)?;
+                        Ok(Self::Append(key.clone(), value.clone()))
+                    }
+                    &"INCR" | &"DECR" => {
+                        let key = array.get(1).ok_or(IncorrectFormat)?.clone();
+                        if c.eq_ignore_ascii_case("INCR") {
+                            Ok(Self::Incr(key))
+                        } else {
+                            Ok(Self::Decr(key))
+                        }
+                    }
+                    &"INCRBY" | &"DECRBY" => {
+                        let key = array.get(1).ok_or(IncorrectFormat)?.clone();
+                        let by = array.get(2).ok_or(IncorrectFormat)?;
+                        let by = by.expect_integer().ok_or(NotAnInteger)?;
+                        if c.eq_ignore_ascii_case("INCRBY") {
+                            Ok(Self::IncrBy(key, by))
+                        } else {
+                            Ok(Self::DecrBy(key, by))
+                        }
+                    }
+                    &"DEL" => Ok(Self::Del(array[1..].to_vec())),
+                    &"EXISTS" => Ok(Self::Exists(array[1..].to_vec())),
+                    &"LPUSHX" | &"RPUSHX" => {
+                        let key = array.get(1).ok_or(IncorrectFormat)?.clone();
+                        let elements = array[2..].to_vec();
+                        if c.eq_ignore_ascii_case("LPUSHX") {
+                            Ok(Self::LPushX(key, elements))
+                        } else {
+                            Ok(Self::RPushX(key, elements))
+                        }
+                    }
+                    &"CONFIG" => {
+                        let args = bulk_strings(&array[1..])?;
+                        match args.as_slice() {
+                            [subcommand, parameters @ ..]
+                                if subcommand.eq_ignore_ascii_case("GET") =>
+                            {
+                                let [parameter] = parameters else {
+                                    return Err(WrongNumberOfArguments("config|get".to_string()));
+                                };
+                                match parameter.to_lowercase().as_str() {
+                                    "dir" => Ok(Self::ConfigGet(ConfigItem::Dir)),
+                                    "dbfilename" => Ok(Self::ConfigGet(ConfigItem::DbFileName)),
+                                    _ => Err(IncorrectFormat),
                                 }
-                                _ => Err(IncorrectFormat),
                             }
+                            [subcommand, ..] => {
+                                Err(UnknownSubcommand("CONFIG", subcommand.to_string()))
+                            }
+                            [] => Err(IncorrectFormat),
                         }
-                        _ => todo!(),
-                    },
+                    }
                     &"KEYS" => Ok(Self::Keys(
                         array
                             .get(1)
@@ -146,7 +679,49 @@ impl<'c> Command<'c> {
                             })
                             .ok_or(IncorrectFormat)?,
                     )),
+                    &"SCAN" => {
+                        let args = bulk_strings(&array[1..])?;
+                        let mut options = ScanOptions {
+                            cursor: args[0].parse().map_err(|_| InvalidCursor)?,
+                            pattern: None,
+                            count: 10,
+                            value_type: None,
+                        };
+                        for option in args[1..].chunks(2) {
+                            match option {
+                                [name, pattern] if name.eq_ignore_ascii_case("MATCH") => {
+                                    options.pattern = Some(pattern.clone());
+                                }
+                                [name, count] if name.eq_ignore_ascii_case("COUNT") => {
+                                    options.count = count.parse().map_err(|_| NotAnInteger)?;
+                                    if options.count == 0 {
+                                        return Err(IncorrectFormat);
+                                    }
+                                }
+                                [name, value_type] if name.eq_ignore_ascii_case("TYPE") => {
+                                    options.value_type = Some(value_type.clone());
+                                }
+                                _ => return Err(IncorrectFormat),
+                            }
+                        }
+                        Ok(Self::Scan(options))
+                    }
                     &"SAVE" => Ok(Self::Save),
+                    &"LOLWUT" => Ok(Self::Lolwut),
+                    &"FAILOVER" => Ok(Self::Failover),
+                    &"SHUTDOWN" => {
+                        let mut options = ShutdownOptions::default();
+                        for arg in bulk_strings(&array[1..])? {
+                            match arg.to_uppercase().as_str() {
+                                "NOSAVE" if options.save.is_none() => options.save = Some(false),
+                                "SAVE" if options.save.is_none() => options.save = Some(true),
+                                "NOW" => options.now = true,
+                                "FORCE" => options.force = true,
+                                _ => return Err(IncorrectFormat),
+                            }
+                        }
+                        Ok(Self::Shutdown(options))
+                    }
                     &"INFO" => Ok(Self::Info(array.get(1).and_then(|parameter| {
                         Some(Resp::BulkString(
                             parameter.expect_bulk_string()?.clone().into_owned().into(),
@@ -188,24 +763,16 @@ impl<'c> Command<'c> {
                             })
                             .unwrap(),
                     )),
-                    &"WAIT" => Ok(Self::Wait(
-                        array
-                            .get(1)
-                            .and_then(|parameter| {
-                                Some(Resp::BulkString(
-                                    parameter.expect_bulk_string()?.clone().into_owned().into(),
-                                ))
-                            })
-                            .unwrap(),
-                        array
-                            .get(2)
-                            .and_then(|parameter| {
-                                Some(Resp::BulkString(
-                                    parameter.expect_bulk_string()?.clone().into_owned().into(),
-                                ))
-                            })
-                            .unwrap(),
-                    )),
+                    &"WAIT" => {
+                        let numofreplicas = array.get(1).ok_or(IncorrectFormat)?;
+                        let timeout = array.get(2).ok_or(IncorrectFormat)?;
+                        let numofreplicas = numofreplicas.expect_integer().ok_or(NotAnInteger)?;
+                        let timeout = timeout.expect_integer().ok_or(NotAnInteger)?;
+                        if timeout < 0 {
+                            return Err(NegativeTimeout);
+                        }
+                        Ok(Self::Wait(numofreplicas, timeout))
+                    }
                     &"SELECT" => Ok(Self::Select(
                         array
                             .get(1)
@@ -226,25 +793,37 @@ impl<'c> Command<'c> {
                             })
                             .ok_or(IncorrectFormat)?,
                     )),
-                    &"XADD" => Ok(Self::XAdd(
-                        array
-                            .get(1)
-                            .and_then(|k| {
-                                Some(Resp::BulkString(
-                                    k.expect_bulk_string()?.clone().into_owned().into(),
-                                ))
-                            })
-                            .ok_or(IncorrectFormat)?,
-                        array
+                    &"XADD" => {
+                        let nomkstream = array
                             .get(2)
-                            .and_then(|k| {
-                                Some(Resp::BulkString(
-                                    k.expect_bulk_string()?.clone().into_owned().into(),
-                                ))
-                            })
-                            .ok_or(IncorrectFormat)?,
-                        array[3..].to_vec(),
-                    )),
+                            .and_then(|arg| arg.expect_bulk_string())
+                            .is_some_and(|arg| arg.eq_ignore_ascii_case("NOMKSTREAM"));
+                        let id_index = if nomkstream { 3 } else { 2 };
+                        let items = array.get(id_index + 1..).unwrap_or_default();
+                        if items.is_empty() || items.len() % 2 != 0 {
+                            return Err(WrongNumberOfArguments("xadd".to_string()));
+                        }
+                        Ok(Self::XAdd(
+                            array
+                                .get(1)
+                                .and_then(|k| {
+                                    Some(Resp::BulkString(
+                                        k.expect_bulk_string()?.clone().into_owned().into(),
+                                    ))
+                                })
+                                .ok_or(IncorrectFormat)?,
+                            array
+                                .get(id_index)
+                                .and_then(|k| {
+                                    Some(Resp::BulkString(
+                                        k.expect_bulk_string()?.clone().into_owned().into(),
+                                    ))
+                                })
+                                .ok_or(IncorrectFormat)?,
+                            items.to_vec(),
+                            nomkstream,
+                        ))
+                    }
                     &"XRANGE" => Ok(Self::XRange(
                         array
                             .get(1)
@@ -285,7 +864,307 @@ impl<'c> Command<'c> {
                         let ids = rest[rest.len() / 2..].to_vec();
                         Ok(Self::XRead(key, streams, ids))
                     }
-                    c => Err(UnsupportedCommand(c.to_string())),
+                    &"AUTH" => match &array[1..] {
+                        [password] => Ok(Self::Auth(None, password.clone())),
+                        [username, password] => {
+                            Ok(Self::Auth(Some(username.clone()), password.clone()))
+                        }
+                        _ => Err(IncorrectFormat),
+                    },
+                    &"QUIT" => Ok(Self::Quit),
+                    &"HELLO" => {
+                        let mut args = array[1..].iter();
+                        let protover = match args.next() {
+                            Some(protover) => {
+                                Some(protover.expect_integer().ok_or(IncorrectFormat)?)
+                            }
+                            None => None,
+                        };
+                        let mut auth = None;
+                        while let Some(option) = args.next() {
+                            let option = option.expect_bulk_string().ok_or(IncorrectFormat)?;
+                            if !option.eq_ignore_ascii_case("AUTH") {
+                                return Err(IncorrectFormat);
+                            }
+                            let username = args.next().ok_or(IncorrectFormat)?;
+                            let password = args.next().ok_or(IncorrectFormat)?;
+                            auth = Some((username.clone(), password.clone()));
+                        }
+                        Ok(Self::Hello(protover, auth))
+                    }
+                    &"RESET" => Ok(Self::Reset),
+                    &"READONLY" => Ok(Self::ReadOnly),
+                    &"READWRITE" => Ok(Self::ReadWrite),
+                    &"ASKING" => Ok(Self::Asking),
+                    &"MONITOR" => Ok(Self::Monitor),
+                    &"ACL" => {
+                        let subcommand = array
+                            .get(1)
+                            .and_then(|s| s.expect_bulk_string())
+                            .ok_or(IncorrectFormat)?;
+                        match subcommand.to_uppercase().as_str() {
+                            "SETUSER" => Ok(Self::Acl(AclSubcommand::SetUser(
+                                array.get(2).ok_or(IncorrectFormat)?.clone(),
+                                array[3..].to_vec(),
+                            ))),
+                            "GETUSER" => Ok(Self::Acl(AclSubcommand::GetUser(
+                                array.get(2).ok_or(IncorrectFormat)?.clone(),
+                            ))),
+                            "LIST" => Ok(Self::Acl(AclSubcommand::List)),
+                            "WHOAMI" => Ok(Self::Acl(AclSubcommand::WhoAmI)),
+                            "CAT" => Ok(Self::Acl(AclSubcommand::Cat)),
+                            _ => Err(IncorrectFormat),
+                        }
+                    }
+                    &"CLIENT" => {
+                        let subcommand = array
+                            .get(1)
+                            .and_then(|s| s.expect_bulk_string())
+                            .ok_or(IncorrectFormat)?;
+                        match subcommand.to_uppercase().as_str() {
+                            "ID" => Ok(Self::Client(ClientSubcommand::Id)),
+                            "KILL" => match &array[2..] {
+                                [addr] => Ok(Self::Client(ClientSubcommand::KillAddr(
+                                    addr.expect_bulk_string()
+                                        .ok_or(IncorrectFormat)?
+                                        .to_string(),
+                                ))),
+                                filters if !filters.is_empty() && filters.len() % 2 == 0 => {
+                                    let filters = filters
+                                        .chunks(2)
+                                        .map(|pair| {
+                                            let name = pair[0]
+                                                .expect_bulk_string()
+                                                .ok_or(IncorrectFormat)?;
+                                            let value = pair[1]
+                                                .expect_bulk_string()
+                                                .ok_or(IncorrectFormat)?;
+                                            match name.to_uppercase().as_str() {
+                                                "ID" => Ok(ClientKillFilter::Id(
+                                                    value.parse().map_err(|_| IncorrectFormat)?,
+                                                )),
+                                                "ADDR" => {
+                                                    Ok(ClientKillFilter::Addr(value.to_string()))
+                                                }
+                                                "SKIPME" => Ok(ClientKillFilter::SkipMe(
+                                                    parse_switch(value, "YES", "NO")?,
+                                                )),
+                                                _ => Err(IncorrectFormat),
+                                            }
+                                        })
+                                        .collect::<Result<_, _>>()?;
+                                    Ok(Self::Client(ClientSubcommand::Kill(filters)))
+                                }
+                                _ => Err(IncorrectFormat),
+                            },
+                            "TRACKING" => {
+                                let args = bulk_strings(&array[2..])?;
+                                let (switch, args) = args.split_first().ok_or(IncorrectFormat)?;
+                                let mut options = TrackingOptions::default();
+                                let mut args = args.iter();
+                                while let Some(option) = args.next() {
+                                    match option.to_uppercase().as_str() {
+                                        "REDIRECT" => {
+                                            let id = args.next().ok_or(IncorrectFormat)?;
+                                            options.redirect =
+                                                Some(id.parse().map_err(|_| NotAnInteger)?);
+                                        }
+                                        "BCAST" => options.bcast = true,
+                                        "PREFIX" => options
+                                            .prefixes
+                                            .push(args.next().ok_or(IncorrectFormat)?.clone()),
+                                        _ => return Err(IncorrectFormat),
+                                    }
+                                }
+                                let on = parse_switch(switch, "ON", "OFF")?;
+                                Ok(Self::Client(ClientSubcommand::Tracking(
+                                    on.then_some(options),
+                                )))
+                            }
+                            "NO-EVICT" | "NO-TOUCH" => {
+                                let value = array
+                                    .get(2)
+                                    .and_then(|v| v.expect_bulk_string())
+                                    .ok_or(IncorrectFormat)?;
+                                let value = parse_switch(value, "ON", "OFF")?;
+                                if subcommand.eq_ignore_ascii_case("NO-EVICT") {
+                                    Ok(Self::Client(ClientSubcommand::NoEvict(value)))
+                                } else {
+                                    Ok(Self::Client(ClientSubcommand::NoTouch(value)))
+                                }
+                            }
+                            _ => Err(IncorrectFormat),
+                        }
+                    }
+                    &"SUBSCRIBE" | &"SSUBSCRIBE" => {
+                        Ok(Self::Subscribe(channel_kind(c), bulk_strings(&array[1..])?))
+                    }
+                    &"UNSUBSCRIBE" | &"SUNSUBSCRIBE" => Ok(Self::Unsubscribe(
+                        channel_kind(c),
+                        bulk_strings(&array[1..])?,
+                    )),
+                    &"PUBLISH" | &"SPUBLISH" => match bulk_strings(&array[1..])?.as_slice() {
+                        [channel, message] => Ok(Self::Publish(
+                            channel_kind(c),
+                            channel.clone(),
+                            message.clone(),
+                        )),
+                        _ => Err(WrongNumberOfArguments(c.to_string())),
+                    },
+                    &"PUBSUB" => {
+                        let subcommand = array
+                            .get(1)
+                            .and_then(|s| s.expect_bulk_string())
+                            .ok_or(IncorrectFormat)?;
+                        let args = bulk_strings(&array[2..])?;
+                        let kind = if subcommand.to_uppercase().starts_with("SHARD") {
+                            ChannelKind::Shard
+                        } else {
+                            ChannelKind::Global
+                        };
+                        match subcommand.to_uppercase().as_str() {
+                            "CHANNELS" | "SHARDCHANNELS" if args.len() <= 1 => Ok(Self::PubSub(
+                                PubSubSubcommand::Channels(kind, args.into_iter().next()),
+                            )),
+                            "NUMSUB" | "SHARDNUMSUB" => {
+                                Ok(Self::PubSub(PubSubSubcommand::NumSub(kind, args)))
+                            }
+                            "NUMPAT" => Ok(Self::PubSub(PubSubSubcommand::NumPat)),
+                            _ => Err(IncorrectFormat),
+                        }
+                    }
+                    &"OBJECT" => {
+                        let subcommand = array
+                            .get(1)
+                            .and_then(|s| s.expect_bulk_string())
+                            .ok_or(IncorrectFormat)?;
+                        match (subcommand.to_uppercase().as_str(), &array[2..]) {
+                            ("ENCODING", [key]) => {
+                                Ok(Self::Object(ObjectSubcommand::Encoding(key.clone())))
+                            }
+                            ("REFCOUNT", [key]) => {
+                                Ok(Self::Object(ObjectSubcommand::RefCount(key.clone())))
+                            }
+                            _ => Err(IncorrectFormat),
+                        }
+                    }
+                    &"MEMORY" => {
+                        let args = bulk_strings(&array[1..])?;
+                        match args.as_slice() {
+                            [subcommand] if subcommand.eq_ignore_ascii_case("DOCTOR") => {
+                                Ok(Self::Memory(MemorySubcommand::Doctor))
+                            }
+                            [subcommand] if subcommand.eq_ignore_ascii_case("STATS") => {
+                                Ok(Self::Memory(MemorySubcommand::Stats))
+                            }
+                            _ => Err(IncorrectFormat),
+                        }
+                    }
+                    &"CLUSTER" => {
+                        let args = bulk_strings(&array[1..])?;
+                        let [subcommand] = args.as_slice() else {
+                            return Err(IncorrectFormat);
+                        };
+                        match subcommand.to_uppercase().as_str() {
+                            "INFO" => Ok(Self::Cluster(ClusterSubcommand::Info)),
+                            "MYID" => Ok(Self::Cluster(ClusterSubcommand::MyId)),
+                            "SLOTS" => Ok(Self::Cluster(ClusterSubcommand::Slots)),
+                            "SHARDS" => Ok(Self::Cluster(ClusterSubcommand::Shards)),
+                            _ => Err(IncorrectFormat),
+                        }
+                    }
+                    &"SLOWLOG" => {
+                        let subcommand = array
+                            .get(1)
+                            .and_then(|s| s.expect_bulk_string())
+                            .ok_or(IncorrectFormat)?;
+                        match subcommand.to_uppercase().as_str() {
+                            "GET" => {
+                                Ok(Self::SlowLog(SlowLogSubcommand::Get(match array.get(2) {
+                                    Some(count) => {
+                                        Some(count.expect_integer().ok_or(NotAnInteger)?)
+                                    }
+                                    None => None,
+                                })))
+                            }
+                            "LEN" => Ok(Self::SlowLog(SlowLogSubcommand::Len)),
+                            "RESET" => Ok(Self::SlowLog(SlowLogSubcommand::Reset)),
+                            _ => Err(IncorrectFormat),
+                        }
+                    }
+                    &"COMMAND" => {
+                        let args = bulk_strings(&array[1..])?;
+                        match args.split_first() {
+                            Some((subcommand, names))
+                                if subcommand.eq_ignore_ascii_case("INFO") =>
+                            {
+                                Ok(Self::Commands(CommandSubcommand::Info(names.to_vec())))
+                            }
+                            Some((subcommand, names))
+                                if subcommand.eq_ignore_ascii_case("DOCS") =>
+                            {
+                                Ok(Self::Commands(CommandSubcommand::Docs(names.to_vec())))
+                            }
+                            Some((subcommand, [])) if subcommand.eq_ignore_ascii_case("COUNT") => {
+                                Ok(Self::Commands(CommandSubcommand::Count))
+                            }
+                            _ => Err(IncorrectFormat),
+                        }
+                    }
+                    &"DEBUG" => {
+                        let args = bulk_strings(&array[1..])?;
+                        match args.as_slice() {
+                            [subcommand, value]
+                                if subcommand.eq_ignore_ascii_case("SET-ACTIVE-EXPIRE") =>
+                            {
+                                Ok(Self::Debug(DebugSubcommand::SetActiveExpire(parse_switch(
+                                    value, "1", "0",
+                                )?)))
+                            }
+                            [subcommand] if subcommand.eq_ignore_ascii_case("CHANGE-REPL-ID") => {
+                                Ok(Self::Debug(DebugSubcommand::ChangeReplId))
+                            }
+                            [subcommand] if subcommand.eq_ignore_ascii_case("RELOAD") => {
+                                Ok(Self::Debug(DebugSubcommand::Reload))
+                            }
+                            [subcommand] if subcommand.eq_ignore_ascii_case("FLUSHALL") => {
+                                Ok(Self::Debug(DebugSubcommand::FlushAll))
+                            }
+                            [subcommand] if subcommand.eq_ignore_ascii_case("JMAP") => {
+                                Ok(Self::Debug(DebugSubcommand::Jmap))
+                            }
+                            [subcommand]
+                                if subcommand.eq_ignore_ascii_case("REPLICATION-OFFSET") =>
+                            {
+                                Ok(Self::Debug(DebugSubcommand::ReplicationOffset))
+                            }
+                            [subcommand, pattern, string]
+                                if subcommand.eq_ignore_ascii_case("STRINGMATCH-LEN") =>
+                            {
+                                Ok(Self::Debug(DebugSubcommand::StringMatchLen(
+                                    pattern.to_string(),
+                                    string.to_string(),
+                                )))
+                            }
+                            [subcommand, seconds] if subcommand.eq_ignore_ascii_case("SLEEP") => {
+                                let seconds = seconds.parse().map_err(|_| NotAFloat)?;
+                                Ok(Self::Debug(DebugSubcommand::Sleep(
+                                    Duration::try_from_secs_f64(seconds).map_err(|_| NotAFloat)?,
+                                )))
+                            }
+                            [subcommand, key] if subcommand.eq_ignore_ascii_case("OBJECT") => {
+                                Ok(Self::Debug(DebugSubcommand::Object(key.to_string())))
+                            }
+                            [subcommand, key] if subcommand.eq_ignore_ascii_case("DUMP-KEY") => {
+                                Ok(Self::Debug(DebugSubcommand::DumpKey(key.to_string())))
+                            }
+                            _ => Err(IncorrectFormat),
+                        }
+                    }
+                    c => Err(UnsupportedCommand(
+                        c.to_string(),
+                        bulk_strings(&array[1..]).unwrap_or_default(),
+                    )),
                 },
                 _ => Err(IncorrectFormat),
             },
@@ -295,24 +1174,98 @@ impl<'c> Command<'c> {
         result.map(|ok| (ok, rest))
     }
 
-    pub fn name(&self) -> String {
+    pub fn name(&self) -> &'static str {
         match self {
-            Command::Ping => "PING".to_string(),
-            Command::Echo(_) => "ECHO".to_string(),
-            Command::Get(_) => "GET".to_string(),
-            Command::Set(_, _, _) => "SET".to_string(),
-            Command::ConfigGet(_) => "CONFIG".to_string(),
-            Command::Keys(_) => "KEYS".to_string(),
-            Command::Info(_) => "INFO".to_string(),
-            Command::Save => "SAVE".to_string(),
-            Command::ReplConf(_, _) => "REPLCONF".to_string(),
-            Command::Psync(_, _) => "PSYNC".to_string(),
-            Command::Wait(_, _) => "WAIT".to_string(),
-            Command::Select(_) => "SELECT".to_string(),
-            Command::Type(_) => "TYPE".to_string(),
-            Command::XAdd(_, _, _) => "XADD".to_string(),
-            Command::XRange(_, _, _) => "XRANGE".to_string(),
-            Command::XRead(_, _, _) => "XREAD".to_string(),
+            Command::Ping => "PING",
+            Command::Echo(_) => "ECHO",
+            Command::Get(_) => "GET",
+            Command::GetRange(_, _, _) => "GETRANGE",
+            Command::Substr(_, _, _) => "SUBSTR",
+            Command::Lcs(_, _, _) => "LCS",
+            Command::Set(_, _, _) => "SET",
+            Command::GetSet(_, _) => "GETSET",
+            Command::Append(_, _) => "APPEND",
+            Command::Incr(_) => "INCR",
+            Command::Decr(_) => "DECR",
+            Command::IncrBy(_, _) => "INCRBY",
+            Command::DecrBy(_, _) => "DECRBY",
+            Command::Del(_) => "DEL",
+            Command::Exists(_) => "EXISTS",
+            Command::LPushX(_, _) => "LPUSHX",
+            Command::RPushX(_, _) => "RPUSHX",
+            Command::ConfigGet(_) => "CONFIG",
+            Command::Keys(_) => "KEYS",
+            Command::Scan(_) => "SCAN",
+            Command::Info(_) => "INFO",
+            Command::Save => "SAVE",
+            Command::Lolwut => "LOLWUT",
+            Command::Failover => "FAILOVER",
+            Command::Shutdown(_) => "SHUTDOWN",
+            Command::ReplConf(_, _) => "REPLCONF",
+            Command::Psync(_, _) => "PSYNC",
+            Command::Wait(_, _) => "WAIT",
+            Command::Select(_) => "SELECT",
+            Command::Type(_) => "TYPE",
+            Command::XAdd(_, _, _, _) => "XADD",
+            Command::XRange(_, _, _) => "XRANGE",
+            Command::XRead(_, _, _) => "XREAD",
+            Command::Auth(_, _) => "AUTH",
+            Command::Quit => "QUIT",
+            Command::Hello(_, _) => "HELLO",
+            Command::Reset => "RESET",
+            Command::ReadOnly => "READONLY",
+            Command::ReadWrite => "READWRITE",
+            Command::Asking => "ASKING",
+            Command::Monitor => "MONITOR",
+            Command::Acl(_) => "ACL",
+            Command::Client(_) => "CLIENT",
+            Command::SlowLog(_) => "SLOWLOG",
+            Command::Object(_) => "OBJECT",
+            Command::Memory(_) => "MEMORY",
+            Command::Cluster(_) => "CLUSTER",
+            Command::Debug(_) => "DEBUG",
+            Command::Commands(_) => "COMMAND",
+            Command::Subscribe(ChannelKind::Global, _) => "SUBSCRIBE",
+            Command::Subscribe(ChannelKind::Shard, _) => "SSUBSCRIBE",
+            Command::Unsubscribe(ChannelKind::Global, _) => "UNSUBSCRIBE",
+            Command::Unsubscribe(ChannelKind::Shard, _) => "SUNSUBSCRIBE",
+            Command::Publish(ChannelKind::Global, _, _) => "PUBLISH",
+            Command::Publish(ChannelKind::Shard, _, _) => "SPUBLISH",
+            Command::PubSub(_) => "PUBSUB",
         }
     }
 }
+
+/// Unknown commands always pass, they are rejected later with a more specific error.
+fn has_valid_arity(command: &str, argc: usize) -> bool {
+    spec::lookup(command).is_none_or(|spec| spec.accepts(argc))
+}
+
+fn channel_kind(command: &str) -> ChannelKind {
+    if matches!(
+        command.to_uppercase().as_str(),
+        "SSUBSCRIBE" | "SUNSUBSCRIBE" | "SPUBLISH"
+    ) {
+        ChannelKind::Shard
+    } else {
+        ChannelKind::Global
+    }
+}
+
+fn bulk_strings(args: &[Resp<'_>]) -> Result<Vec<String>, CommandError> {
+    args.iter()
+        .map(|arg| arg.expect_bulk_string().map(|arg| arg.to_string()))
+        .collect::<Option<_>>()
+        .ok_or(CommandError::IncorrectFormat)
+}
+
+/// Parses a two-state argument such as `ON|OFF` or `YES|NO`, case-insensitively.
+fn parse_switch(value: &str, on: &str, off: &str) -> Result<bool, CommandError> {
+    if value.eq_ignore_ascii_case(on) {
+        Ok(true)
+    } else if value.eq_ignore_ascii_case(off) {
+        Ok(false)
+    } else {
+        Err(CommandError::IncorrectFormat)
+    }
+}
@@ -0,0 +1,301 @@
+use crate::resp::Resp;
+
+/// Static description of a command, as reported by `COMMAND INFO`.
+///
+/// Arity counts the command name: a positive arity is the exact number of arguments, a negative
+/// one the minimum. `first_key`, `last_key` and `step` locate the key arguments, a `last_key` of
+/// -1 meaning the keys run to the end.
+#[derive(Debug)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub arity: i64,
+    pub flags: &'static [&'static str],
+    pub first_key: i64,
+    pub last_key: i64,
+    pub step: i64,
+}
+
+const fn spec(
+    name: &'static str,
+    arity: i64,
+    flags: &'static [&'static str],
+    (first_key, last_key, step): (i64, i64, i64),
+) -> CommandSpec {
+    CommandSpec {
+        name,
+        arity,
+        flags,
+        first_key,
+        last_key,
+        step,
+    }
+}
+
+const NO_KEYS: (i64, i64, i64) = (0, 0, 0);
+const FIRST_KEY: (i64, i64, i64) = (1, 1, 1);
+const ALL_KEYS: (i64, i64, i64) = (1, -1, 1);
+
+const CONNECTION: &[&str] = &[
+    "noscript",
+    "loading",
+    "stale",
+    "fast",
+    "no_auth",
+    "allow_busy",
+];
+const CLUSTER: &[&str] = &["loading", "stale", "fast"];
+const SUBSCRIBE: &[&str] = &["pubsub", "noscript", "loading", "stale"];
+const PUBLISH: &[&str] = &["pubsub", "loading", "stale", "fast"];
+
+pub const COMMANDS: &[CommandSpec] = &[
+    spec("acl", -2, &[], NO_KEYS),
+    spec("append", 3, &["write", "denyoom", "fast"], FIRST_KEY),
+    spec("asking", 1, CLUSTER, NO_KEYS),
+    spec("auth", -2, CONNECTION, NO_KEYS),
+    spec("client", -2, &[], NO_KEYS),
+    spec("cluster", -2, &[], NO_KEYS),
+    spec("command", -1, &["loading", "stale"], NO_KEYS),
+    spec("config", -2, &[], NO_KEYS),
+    spec(
+        "debug",
+        -2,
+        &["admin", "noscript", "loading", "stale", "protected"],
+        NO_KEYS,
+    ),
+    spec("decr", 2, &["write", "denyoom", "fast"], FIRST_KEY),
+    spec("decrby", 3, &["write", "denyoom", "fast"], FIRST_KEY),
+    spec("del", -2, &["write"], ALL_KEYS),
+    spec("echo", 2, &["fast"], NO_KEYS),
+    spec("exists", -2, &["readonly", "fast"], ALL_KEYS),
+    spec("failover", -1, &["admin", "noscript", "stale"], NO_KEYS),
+    spec("get", 2, &["readonly", "fast"], FIRST_KEY),
+    spec("getrange", 4, &["readonly"], FIRST_KEY),
+    spec("getset", 3, &["write", "denyoom", "fast"], FIRST_KEY),
+    spec("hello", -1, CONNECTION, NO_KEYS),
+    spec("incr", 2, &["write", "denyoom", "fast"], FIRST_KEY),
+    spec("incrby", 3, &["write", "denyoom", "fast"], FIRST_KEY),
+    spec("info", -1, &["loading", "stale"], NO_KEYS),
+    spec("keys", 2, &["readonly"], NO_KEYS),
+    spec("lcs", -3, &["readonly"], (1, 2, 1)),
+    spec("lolwut", -1, &["readonly", "fast"], NO_KEYS),
+    spec("lpushx", -3, &["write", "denyoom", "fast"], FIRST_KEY),
+    spec("memory", -2, &[], NO_KEYS),
+    spec(
+        "monitor",
+        1,
+        &["admin", "noscript", "loading", "stale"],
+        NO_KEYS,
+    ),
+    spec("object", -2, &[], NO_KEYS),
+    spec("ping", -1, &["fast"], NO_KEYS),
+    spec(
+        "psync",
+        -3,
+        &["admin", "noscript", "no_async_loading", "no_multi"],
+        NO_KEYS,
+    ),
+    spec("publish", 3, PUBLISH, NO_KEYS),
+    spec("pubsub", -2, &[], NO_KEYS),
+    spec("quit", -1, CONNECTION, NO_KEYS),
+    spec("readonly", 1, CLUSTER, NO_KEYS),
+    spec("readwrite", 1, CLUSTER, NO_KEYS),
+    // Redis accepts a bare REPLCONF, but every form handled here takes an option and a value
+    spec(
+        "replconf",
+        -3,
+        &["admin", "noscript", "loading", "stale", "allow_busy"],
+        NO_KEYS,
+    ),
+    spec("reset", 1, CONNECTION, NO_KEYS),
+    spec("rpushx", -3, &["write", "denyoom", "fast"], FIRST_KEY),
+    spec(
+        "save",
+        1,
+        &["admin", "noscript", "no_async_loading", "no_multi"],
+        NO_KEYS,
+    ),
+    spec("scan", -2, &["readonly"], NO_KEYS),
+    spec("select", 2, &["loading", "stale", "fast"], NO_KEYS),
+    spec("set", -3, &["write", "denyoom"], FIRST_KEY),
+    spec(
+        "shutdown",
+        -1,
+        &[
+            "admin",
+            "noscript",
+            "loading",
+            "stale",
+            "no_multi",
+            "allow_busy",
+        ],
+        NO_KEYS,
+    ),
+    spec("slowlog", -2, &[], NO_KEYS),
+    spec("spublish", 3, PUBLISH, FIRST_KEY),
+    spec("ssubscribe", -2, SUBSCRIBE, ALL_KEYS),
+    spec("subscribe", -2, SUBSCRIBE, NO_KEYS),
+    spec("substr", 4, &["readonly"], FIRST_KEY),
+    spec("sunsubscribe", -1, SUBSCRIBE, ALL_KEYS),
+    spec("type", 2, &["readonly", "fast"], FIRST_KEY),
+    spec("unsubscribe", -1, SUBSCRIBE, NO_KEYS),
+    spec("wait", 3, &["noscript"], NO_KEYS),
+    spec("xadd", -5, &["write", "denyoom", "fast"], FIRST_KEY),
+    spec("xrange", -4, &["readonly"], FIRST_KEY),
+    spec(
+        "xread",
+        -4,
+        &["readonly", "blocking", "movablekeys"],
+        NO_KEYS,
+    ),
+];
+
+pub fn lookup(name: &str) -> Option<&'static CommandSpec> {
+    index(name).map(|index| &COMMANDS[index])
+}
+
+/// Position of the command `name`, in any case, in `COMMANDS`.
+pub fn index(name: &str) -> Option<usize> {
+    COMMANDS
+        .binary_search_by(|spec| {
+            spec.name
+                .bytes()
+                .cmp(name.bytes().map(|b| b.to_ascii_lowercase()))
+        })
+        .ok()
+}
+
+impl CommandSpec {
+    /// Whether `argc` arguments, counting the command name, satisfy the arity.
+    pub fn accepts(&self, argc: usize) -> bool {
+        if self.arity >= 0 {
+            argc as i64 == self.arity
+        } else {
+            argc as i64 >= -self.arity
+        }
+    }
+
+    /// The `[name, arity, [flags], first_key, last_key, step]` entry of `COMMAND INFO`.
+    pub fn to_resp(&self) -> Resp<'static> {
+        Resp::Array(vec![
+            Resp::bulk_string(self.name),
+            Resp::Integer(self.arity),
+            Resp::Array(
+                self.flags
+                    .iter()
+                    .map(|flag| Resp::simple_string(flag))
+                    .collect(),
+            ),
+            Resp::Integer(self.first_key),
+            Resp::Integer(self.last_key),
+            Resp::Integer(self.step),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{docs::DOCS, Command, CommandError};
+    use crate::errors;
+
+    fn encode(args: &[&str]) -> Vec<u8> {
+        let mut buf = format!("*{}\r\n", args.len()).into_bytes();
+        for arg in args {
+            buf.extend(format!("${}\r\n{arg}\r\n", arg.len()).as_bytes());
+        }
+        buf
+    }
+
+    #[test]
+    fn commands_are_sorted_for_lookup() {
+        for pair in COMMANDS.windows(2) {
+            assert!(
+                pair[0].name < pair[1].name,
+                "{} should come after {}",
+                pair[0].name,
+                pair[1].name
+            );
+        }
+        for (position, spec) in COMMANDS.iter().enumerate() {
+            assert_eq!(index(spec.name), Some(position));
+            assert_eq!(index(&spec.name.to_uppercase()), Some(position));
+        }
+        assert_eq!(index("nosuchcommand"), None);
+    }
+
+    #[test]
+    fn docs_follow_commands() {
+        let specs: Vec<_> = COMMANDS.iter().map(|spec| spec.name).collect();
+        let docs: Vec<_> = DOCS.iter().map(|doc| doc.name).collect();
+        assert_eq!(specs, docs);
+    }
+
+    #[test]
+    fn every_parsed_command_has_a_spec() {
+        // The names `Command::parse` dispatches on, e.g. `&"GET" => ...`
+        let source = include_str!("mod.rs");
+        let names: Vec<&str> = source
+            .split("&\"")
+            .skip(1)
+            .filter_map(|arm| arm.split_once('"').map(|(name, _)| name))
+            .filter(|name| !name.is_empty() && name.bytes().all(|b| b.is_ascii_uppercase()))
+            .collect();
+        assert!(
+            names.contains(&"GET") && names.contains(&"XREAD"),
+            "{names:?}"
+        );
+        for name in names {
+            assert!(lookup(name).is_some(), "{name} has no entry in COMMANDS");
+        }
+    }
+
+    #[test]
+    fn arity_is_checked_before_parsing() {
+        let cases: &[(&[&str], &str)] = &[
+            (&["GET"], "get"),
+            (&["get", "a", "b"], "get"),
+            (&["SET", "key"], "set"),
+            (&["ECHO"], "echo"),
+            (&["ECHO", "a", "b"], "echo"),
+            (&["INCRBY", "key"], "incrby"),
+            (&["IncrBy", "key", "1", "2"], "incrby"),
+            (&["EXISTS"], "exists"),
+            (&["TYPE", "a", "b"], "type"),
+            (&["LCS", "a"], "lcs"),
+            (&["WAIT", "1"], "wait"),
+            (&["WAIT", "1", "2", "3"], "wait"),
+            (&["XADD", "stream", "*", "field"], "xadd"),
+        ];
+        for (args, name) in cases {
+            let input = encode(args);
+            let err = Command::parse(&input).unwrap_err();
+            assert!(
+                matches!(err, CommandError::WrongNumberOfArguments(_)),
+                "{args:?} gave {err:?}"
+            );
+            assert_eq!(
+                errors::command_error(&err),
+                errors::error(format!(
+                    "ERR wrong number of arguments for '{name}' command"
+                )),
+                "{args:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn commands_within_their_arity_parse() {
+        let cases: &[&[&str]] = &[
+            &["GET", "key"],
+            &["SET", "key", "value"],
+            &["ECHO", "message"],
+            &["INCRBY", "key", "1"],
+            &["EXISTS", "a", "b", "c"],
+            &["WAIT", "1", "0"],
+        ];
+        for args in cases {
+            let input = encode(args);
+            assert!(Command::parse(&input).is_ok(), "{args:?}");
+        }
+    }
+}
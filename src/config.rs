@@ -1,16 +1,102 @@
-use clap::Parser;
+use std::net::Ipv4Addr;
+
+use clap::{ArgAction, Parser, ValueEnum};
+use tracing::level_filters::LevelFilter;
 
 #[derive(Debug, Parser, Clone)]
 pub struct Config {
     #[arg(short, long)]
     pub dir: Option<String>,
 
-    #[arg(short, long)]
+    // No `-d`, it belongs to --dir and clap panics on duplicate short flags
+    #[arg(long)]
     pub dbfilename: Option<String>,
 
     #[arg(short, long, default_value_t = 6379)]
     pub port: u16,
 
+    /// Address to listen on, 0.0.0.0 accepts clients on every interface
+    #[arg(long, default_value = "127.0.0.1")]
+    pub bind: Ipv4Addr,
+
     #[arg(short, long)]
     pub replicaof: Option<String>,
+
+    #[arg(long)]
+    pub tls_port: Option<u16>,
+
+    #[arg(long)]
+    pub tls_cert_file: Option<String>,
+
+    #[arg(long)]
+    pub tls_key_file: Option<String>,
+
+    #[arg(long)]
+    pub requirepass: Option<String>,
+
+    #[arg(long, default_value_t = 10000)]
+    pub maxclients: usize,
+
+    /// Without a password, only accept clients connecting over the loopback interface
+    #[arg(long, default_value = "yes", value_parser = parse_yes_no, action = ArgAction::Set)]
+    pub protected_mode: bool,
+
+    /// Seconds after which idle clients are disconnected, 0 disables the timeout
+    #[arg(long, default_value_t = 0)]
+    pub timeout: u64,
+
+    /// Seconds `SHUTDOWN` waits for replicas to acknowledge every write, 0 doesn't wait
+    #[arg(long, default_value_t = 10)]
+    pub shutdown_timeout: u64,
+
+    /// Seconds between TCP keepalive probes, 0 disables keepalive
+    #[arg(long, default_value_t = 300)]
+    pub tcp_keepalive: u64,
+
+    /// Largest string in bytes a command may build, which also bounds the memory LCS may use
+    #[arg(long, default_value_t = 512 * 1024 * 1024)]
+    pub proto_max_bulk_len: usize,
+
+    #[arg(long, default_value_t = 10000, allow_negative_numbers = true)]
+    pub slowlog_log_slower_than: i64,
+
+    #[arg(long, default_value_t = 128)]
+    pub slowlog_max_len: usize,
+
+    #[arg(long, value_enum, default_value_t = LogLevel::Notice)]
+    pub loglevel: LogLevel,
+
+    /// Append logs to this file instead of writing them to stdout
+    #[arg(long)]
+    pub logfile: Option<String>,
+}
+
+fn parse_yes_no(value: &str) -> Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err("argument must be 'yes' or 'no'".to_string()),
+    }
+}
+
+/// Redis log levels, from the most to the least verbose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogLevel {
+    Debug,
+    Verbose,
+    Notice,
+    Warning,
+    Nothing,
+}
+
+impl LogLevel {
+    pub fn filter(&self) -> LevelFilter {
+        match self {
+            LogLevel::Debug => LevelFilter::TRACE,
+            LogLevel::Verbose => LevelFilter::DEBUG,
+            LogLevel::Notice => LevelFilter::INFO,
+            LogLevel::Warning => LevelFilter::WARN,
+            LogLevel::Nothing => LevelFilter::OFF,
+        }
+    }
 }
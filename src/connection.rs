@@ -2,48 +2,123 @@ use core::str;
 use indexmap::IndexMap;
 use std::{
     borrow::Cow,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    fmt::Write,
     net::SocketAddr,
     pin::Pin,
-    sync::{atomic::AtomicUsize, Arc},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize},
+        Arc, Mutex,
+    },
     task::{Context, Poll},
-    time::Duration,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use thiserror::Error;
 use tokio::io::AsyncWriteExt;
 use tokio::io::{self, AsyncRead};
 use tokio::io::{AsyncReadExt, AsyncWrite};
 use tokio::net::TcpStream;
-use tokio::sync::broadcast::Sender as BroadcastSender;
-use tokio::sync::RwLock;
+use tokio::sync::broadcast::{error::RecvError, Sender as BroadcastSender};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::{Notify, RwLock};
+use tokio_rustls::server::TlsStream;
+use tracing::{debug, info, warn};
 
 use crate::{
+    acl::{Acl, CATEGORIES, DEFAULT_USER},
+    client::Clients,
     command::{
-        Command, CommandError,
+        docs,
+        spec::{self, COMMANDS},
+        AclSubcommand, ClientKillFilter, ClientSubcommand, ClusterSubcommand, Command,
+        CommandError, CommandSubcommand,
         ConfigItem::{DbFileName, Dir},
+        DebugSubcommand, MemorySubcommand, ObjectSubcommand, PubSubSubcommand, SetExpiry,
+        SlowLogSubcommand,
     },
     config::Config,
     data::{
+        append, expect_type, incr_by, lcs, lcs_len, push, scan,
         stream::{Stream, StreamError, StreamId},
-        Value,
+        string_range, Value,
     },
-    resp::{Resp, RespError},
-    Db, Expiries,
+    errors, expiry,
+    pubsub::{ChannelKind, PubSub},
+    rdb::Rdb,
+    replica::{self, MasterLinkStatus, ReplicaState},
+    resp::{Resp, RespDecoder, RespError},
+    server,
+    slowlog::SlowLog,
+    stats::CommandStats,
+    tracking::{Tracking, TrackingTarget},
+    utils, Db, Expiries, VERSION,
 };
 
+/// Below this much memory `MEMORY DOCTOR` has too little to go on, like in Redis.
+const MEMORY_DOCTOR_MIN_BYTES: usize = 5 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum ConnectionStream {
+    Tcp(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
 #[derive(Debug)]
 pub struct Connection {
-    pub tcp: TcpStream,
+    pub stream: ConnectionStream,
     pub addr: SocketAddr,
+    pub id: u64,
+    pub kill: Arc<Notify>,
     db: Db,
     expiries: Expiries,
     config: Arc<Config>,
-    server_replication_id: String,
+    server_replication_id: Arc<std::sync::RwLock<String>>,
+    /// Reported by `CLUSTER MYID`, generated at startup like a cluster node id.
+    node_id: Arc<str>,
     pub is_promoted_to_replica: bool,
+    /// Announced by a replica with `REPLCONF listening-port` before it is promoted
+    pub replica_listening_port: Option<u16>,
+    is_authenticated: bool,
+    /// RESP version negotiated with `HELLO`
+    protocol: u8,
+    username: String,
+    is_closed: bool,
+    is_monitoring: bool,
     propagation_sender: BroadcastSender<Command<'static>>,
     pub number_of_replicas: Arc<AtomicUsize>,
-    pub replica_offsets: Arc<RwLock<HashMap<SocketAddr, usize>>>,
+    pub replicas: Arc<RwLock<HashMap<SocketAddr, ReplicaState>>>,
     pub server_replication_offset: Arc<AtomicUsize>,
+    /// Replication offset right after this client's last write, 0 if it hasn't written yet.
+    ///
+    /// A write is committed locally once it is applied to the dataset, which happens before the
+    /// reply is sent; there is no AOF, so nothing is fsynced. `WAIT` only waits for replicas
+    /// to acknowledge this offset, so writes of other clients issued later don't delay it.
+    last_write_offset: usize,
+    /// Number of keys changed by the command being handled. Writes that changed nothing, like a
+    /// `DEL` of missing keys, are not propagated.
+    dirty: usize,
+    /// What to propagate instead of the command being handled, for commands whose effect
+    /// depends on when or where they run.
+    propagate: Option<Command<'static>>,
+    active_expire: Arc<AtomicBool>,
+    /// Notified whenever a replica acknowledges an offset, wakes up `WAIT`.
+    pub replica_ack: Arc<Notify>,
+    acl: Arc<RwLock<Acl>>,
+    clients: Arc<Clients>,
+    slowlog: Arc<Mutex<SlowLog>>,
+    command_stats: Arc<CommandStats>,
+    monitor_sender: BroadcastSender<String>,
+    write_buf: Vec<u8>,
+    pubsub: Arc<PubSub>,
+    tracking: Arc<Tracking>,
+    /// Only meaningful on replicas
+    master_link: Arc<std::sync::Mutex<MasterLinkStatus>>,
+    /// `CLIENT TRACKING` is on in the default mode, so keys read have to be remembered.
+    tracks_reads: bool,
+    subscriptions: HashMap<ChannelKind, HashSet<String>>,
+    /// Messages from subscribed channels, drained by [`Connection::handle`].
+    message_sender: UnboundedSender<Resp<'static>>,
+    message_receiver: Option<UnboundedReceiver<Resp<'static>>>,
 }
 
 #[derive(Debug, Error)]
@@ -62,127 +137,599 @@ pub enum ConnectionError {
 }
 
 impl Connection {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        (tcp, addr): (TcpStream, SocketAddr),
+        (stream, addr): (ConnectionStream, SocketAddr),
         db: Db,
         expiries: Expiries,
         config: Arc<Config>,
-        server_replication_id: String,
+        server_replication_id: Arc<std::sync::RwLock<String>>,
+        node_id: Arc<str>,
         propagation_sender: BroadcastSender<Command<'static>>,
         number_of_replicas: Arc<AtomicUsize>,
-        replica_offsets: Arc<RwLock<HashMap<SocketAddr, usize>>>,
+        replicas: Arc<RwLock<HashMap<SocketAddr, ReplicaState>>>,
         server_replication_offset: Arc<AtomicUsize>,
+        active_expire: Arc<AtomicBool>,
+        replica_ack: Arc<Notify>,
+        acl: Arc<RwLock<Acl>>,
+        clients: Arc<Clients>,
+        slowlog: Arc<Mutex<SlowLog>>,
+        command_stats: Arc<CommandStats>,
+        monitor_sender: BroadcastSender<String>,
+        pubsub: Arc<PubSub>,
+        tracking: Arc<Tracking>,
+        master_link: Arc<std::sync::Mutex<MasterLinkStatus>>,
     ) -> Self {
+        let (id, kill) = clients.register(addr);
+        let (message_sender, message_receiver) = mpsc::unbounded_channel();
         Self {
-            tcp,
+            stream,
             addr,
+            id,
+            kill,
             db,
             expiries,
             config,
             server_replication_id,
+            node_id,
             is_promoted_to_replica: false,
+            replica_listening_port: None,
+            is_authenticated: false,
+            protocol: 2,
+            username: DEFAULT_USER.to_string(),
+            is_closed: false,
+            is_monitoring: false,
             propagation_sender,
             number_of_replicas,
-            replica_offsets,
+            replicas,
             server_replication_offset,
+            last_write_offset: 0,
+            dirty: 0,
+            propagate: None,
+            active_expire,
+            replica_ack,
+            acl,
+            clients,
+            slowlog,
+            command_stats,
+            monitor_sender,
+            write_buf: Vec::with_capacity(4096),
+            pubsub,
+            tracking,
+            master_link,
+            tracks_reads: false,
+            subscriptions: HashMap::new(),
+            message_sender,
+            message_receiver: Some(message_receiver),
         }
     }
 
     pub async fn handle(&mut self) -> Result<(), ConnectionError> {
-        println!("accepted new connection: {}", self.addr);
-        let mut buf = Vec::with_capacity(4096);
-        let mut failed = false;
-        'main: while !self.is_promoted_to_replica {
-            if buf.is_empty() || failed {
-                let n = self.read_buf(&mut buf).await?;
-                if n == 0 {
+        debug!("accepted new connection");
+        self.is_authenticated = self
+            .acl
+            .read()
+            .await
+            .get(DEFAULT_USER)
+            .is_some_and(|user| user.is_nopass());
+        let mut decoder = RespDecoder::default();
+        let kill = self.kill.clone();
+        let mut messages = self
+            .message_receiver
+            .take()
+            .expect("connection is handled only once");
+        while !self.is_promoted_to_replica && !self.is_closed {
+            let frame = match decoder.next_request() {
+                Ok(Some(frame)) => frame,
+                Ok(None) => {
+                    // Subscribers are expected to sit idle waiting for messages
+                    let idle_timeout = (self.config.timeout > 0 && !self.is_subscribed())
+                        .then(|| Duration::from_secs(self.config.timeout));
+                    let n = tokio::select! {
+                        n = self.read_buf(decoder.buffer_mut()) => n?,
+                        _ = kill.notified() => break,
+                        _ = async {
+                            match idle_timeout {
+                                Some(timeout) => tokio::time::sleep(timeout).await,
+                                None => std::future::pending().await,
+                            }
+                        } => {
+                            debug!("closing idle connection");
+                            break;
+                        }
+                        Some(message) = messages.recv() => {
+                            self.write_resp(&message).await?;
+                            continue;
+                        }
+                    };
+                    if n == 0 {
+                        break;
+                    }
+                    continue;
+                }
+                Err(err) => {
+                    // There is no way to find the next frame after a malformed one
+                    warn!("Malformed request: {err}");
+                    self.write_resp(&errors::protocol_error()).await?;
                     break;
                 }
+            };
+
+            match Command::parse(&frame) {
+                Ok((c, _)) => {
+                    let started = Instant::now();
+                    self.handle_command(&c).await?;
+                    let elapsed = started.elapsed();
+                    self.command_stats.record(c.name(), elapsed);
+                    self.slowlog.lock().unwrap().record(elapsed, &c, self.addr);
+                }
+                Err(err @ CommandError::ProtocolError(_)) => {
+                    warn!("Malformed request: {err}");
+                    self.write_resp(&errors::command_error(&err)).await?;
+                }
+                Err(err) => {
+                    debug!("{err}");
+                    self.write_resp(&errors::command_error(&err)).await?;
+                }
+            }
+
+            if self.is_monitoring {
+                self.monitor(&mut decoder).await?;
+            }
+        }
+
+        if !self.is_promoted_to_replica {
+            self.stream.shutdown().await.unwrap();
+        }
+
+        Ok(())
+    }
+
+    /// Switches to `username` if `password` matches.
+    async fn authenticate(&mut self, username: &str, password: &str) -> bool {
+        let authenticated = self
+            .acl
+            .read()
+            .await
+            .get(username)
+            .is_some_and(|user| user.check_password(password));
+        if authenticated {
+            self.is_authenticated = true;
+            self.username = username.to_string();
+        }
+        authenticated
+    }
+
+    /// Approximate bytes used by values, and by keys and the maps holding them.
+    async fn memory_usage(&self) -> (usize, usize) {
+        let db = self.db.read().await;
+        let expiries = self.expiries.read().await;
+        let dataset = db.values().map(Value::approx_size).sum();
+        let key_size = |key: &Resp<'_>| {
+            std::mem::size_of::<Resp<'_>>() + key.expect_bulk_string().map_or(0, |key| key.len())
+        };
+        let overhead = db.keys().map(key_size).sum::<usize>()
+            + expiries
+                .keys()
+                .map(|key| key_size(key) + std::mem::size_of::<i64>())
+                .sum::<usize>();
+        (dataset, overhead)
+    }
+
+    /// Free-form text replies are verbatim strings in RESP3 and plain bulk strings in RESP2.
+    fn text(&self, text: String) -> Resp<'static> {
+        if self.protocol == 3 {
+            Resp::Verbatim("txt", Cow::Owned(text))
+        } else {
+            Resp::BulkString(Cow::Owned(text))
+        }
+    }
+
+    /// Maps are sent as is to RESP3 clients and as flat key-value arrays to RESP2 ones.
+    fn map<'a>(&self, pairs: Vec<(Resp<'a>, Resp<'a>)>) -> Resp<'a> {
+        if self.protocol == 3 {
+            Resp::map(pairs)
+        } else {
+            Resp::map(pairs).flatten_maps()
+        }
+    }
+
+    fn is_subscribed(&self) -> bool {
+        self.subscriptions
+            .values()
+            .any(|channels| !channels.is_empty())
+    }
+
+    fn unsubscribe_all(&mut self) {
+        for (kind, channels) in self.subscriptions.drain() {
+            for channel in channels {
+                self.pubsub.unsubscribe(kind, &channel, self.id);
             }
+        }
+    }
+
+    /// Writes `resp` through a buffer reused across replies.
+    pub async fn write_resp(&mut self, resp: &Resp<'_>) -> io::Result<()> {
+        let mut buf = std::mem::take(&mut self.write_buf);
+        buf.clear();
+        resp.encode_into(&mut buf);
+        let result = self.write_all(&buf).await;
+        self.write_buf = buf;
+        result
+    }
 
-            let mut rest = buf.as_slice();
-            while !rest.is_empty() {
-                match Command::parse(rest) {
-                    Ok((c, new_rest)) => {
-                        self.handle_command(c).await?;
-                        rest = new_rest;
-                        failed = false;
+    /// Streams every command processed by the server to the client until it sends QUIT or RESET,
+    /// anything else it sends is ignored.
+    async fn monitor(&mut self, decoder: &mut RespDecoder) -> Result<(), ConnectionError> {
+        let mut lines = self.monitor_sender.subscribe();
+        let kill = self.kill.clone();
+        while self.is_monitoring && !self.is_closed {
+            tokio::select! {
+                _ = kill.notified() => self.is_closed = true,
+                line = lines.recv() => match line {
+                    Ok(line) => {
+                        self.write_resp(&Resp::SimpleString(Cow::Owned(line)))
+                            .await?
                     }
-                    Err(err) => {
-                        eprintln!("{}", err);
-                        match err {
-                            CommandError::IncorrectFormat | CommandError::ProtocolError(_) => {
-                                failed = true;
-                                continue 'main;
-                            }
-                            CommandError::UnsupportedCommand(_) => {
-                                self.write_all(
-                                    &Resp::SimpleError(Cow::Borrowed("unknown command")).encode(),
-                                )
-                                .await?;
-                                break;
-                            }
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                },
+                n = self.read_buf(decoder.buffer_mut()) => {
+                    if n? == 0 {
+                        self.is_closed = true;
+                        break;
+                    }
+                    while self.is_monitoring && !self.is_closed {
+                        let Some(frame) = decoder.next_request()? else {
+                            break;
+                        };
+                        if let Ok((c @ (Command::Quit | Command::Reset), _)) = Command::parse(&frame) {
+                            self.handle_command(&c).await?;
                         }
                     }
                 }
             }
-            buf.clear();
         }
 
-        if !self.is_promoted_to_replica {
-            self.tcp.shutdown().await.unwrap();
+        Ok(())
+    }
+
+    /// Lazily expires `key` on access. Replicas only hide expired keys from reads, deleting
+    /// them is up to the master.
+    async fn is_expired(&self, key: &Resp<'_>) -> bool {
+        let Some(expires_at) = self.expiries.read().await.get(key).copied() else {
+            return false;
+        };
+        if !expiry::is_due(expires_at) {
+            return false;
         }
+        if self.config.replicaof.is_some() {
+            return true;
+        }
+        expiry::expire(
+            &key.clone().into_owned(),
+            expires_at,
+            &self.db,
+            &self.expiries,
+            &self.propagation_sender,
+            &self.server_replication_offset,
+        )
+        .await
+    }
 
-        Ok(())
+    /// A `MONITOR` line, e.g. `1339518083.107412 [0 127.0.0.1:60866] "keys" "*"`.
+    fn monitor_line(&self, command: &Command<'_>) -> String {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let argv: Vec<String> = command
+            .argv()
+            .iter()
+            .map(|arg| format!("{arg:?}"))
+            .collect();
+        format!(
+            "{}.{:06} [0 {}] {}",
+            now.as_secs(),
+            now.subsec_micros(),
+            self.addr,
+            argv.join(" ")
+        )
     }
 
     pub async fn handle_command<'c>(
         &mut self,
-        command: Command<'c>,
+        command: &Command<'c>,
     ) -> Result<(), ConnectionError> {
-        let resp = match &command {
-            Command::Ping => Resp::simple_string("PONG"),
-            Command::Echo(msg) => Resp::bulk_string(msg),
-            Command::Get(key) => self
-                .db
+        self.dirty = 0;
+        self.propagate = None;
+        let is_auth_command = matches!(
+            command,
+            Command::Auth(_, _) | Command::Hello(_, Some(_)) | Command::Quit | Command::Reset
+        );
+        if !self.is_authenticated && !is_auth_command {
+            self.write_resp(&errors::no_auth()).await?;
+            return Ok(());
+        }
+        if self.is_subscribed()
+            && !matches!(
+                command,
+                Command::Subscribe(_, _)
+                    | Command::Unsubscribe(_, _)
+                    | Command::Ping
+                    | Command::Quit
+                    | Command::Reset
+            )
+        {
+            let resp = errors::error(format!(
+                "ERR Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context",
+                command.name().to_lowercase()
+            ));
+            self.write_resp(&resp).await?;
+            return Ok(());
+        }
+        if !is_auth_command {
+            let permission = self
+                .acl
                 .read()
                 .await
-                .get(key)
-                .cloned()
-                .unwrap_or(Value::Str("".to_string()))
-                .try_into()?,
-            Command::Set(key, value, expiry) => {
-                self.db.write().await.insert(
-                    key.clone().into_owned().into(),
-                    value.clone().into_owned().into(),
-                );
-                if let Some(expiry) = expiry {
-                    let expiry = *expiry;
-                    let db = self.db.clone();
-                    self.expiries
-                        .write()
-                        .await
-                        .insert(key.clone().into_owned(), expiry);
-                    let key = key.clone().into_owned();
-                    let expiries = self.expiries.clone();
-                    tokio::spawn(async move {
-                        tokio::time::sleep(Duration::from_millis(expiry as u64)).await;
-                        db.write().await.remove(&key);
-                        expiries.write().await.remove(&key);
-                    });
+                .get(&self.username)
+                .map(|user| user.check(command));
+            if let Some(Err(err)) = permission {
+                self.write_resp(&errors::error(err.to_string())).await?;
+                return Ok(());
+            }
+        }
+
+        // AUTH is never shown to monitors since it carries the password
+        if !matches!(
+            command,
+            Command::Auth(_, _) | Command::Hello(_, Some(_)) | Command::Monitor
+        ) && self.monitor_sender.receiver_count() > 0
+        {
+            let _ = self.monitor_sender.send(self.monitor_line(command));
+        }
+
+        let resp = match command {
+            Command::Ping if self.is_subscribed() => {
+                Resp::Array(vec![Resp::bulk_string("pong"), Resp::bulk_string("")])
+            }
+            Command::Ping => Resp::simple_string("PONG"),
+            Command::Echo(msg) => Resp::bulk_string(msg),
+            Command::Get(key) => {
+                if self.is_expired(key).await {
+                    Resp::Null
+                } else {
+                    match expect_type(self.db.read().await.get(key), "string") {
+                        Ok(Some(Value::Str(value, _))) => {
+                            Resp::BulkString(Cow::Owned(value.clone()))
+                        }
+                        Ok(_) => Resp::Null,
+                        Err(err) => err,
+                    }
+                }
+            }
+            Command::Set(key, value, expiry) => 'set: {
+                let expires_at = match expiry.as_ref().map(SetExpiry::expires_at) {
+                    Some(None) => break 'set errors::invalid_expire_time("set"),
+                    expires_at => expires_at.flatten(),
+                };
+                let key = key.clone().into_owned();
+                self.db
+                    .write()
+                    .await
+                    .insert(key.clone(), value.clone().into_owned().into());
+                self.dirty += 1;
+                match expires_at {
+                    Some(expires_at) => {
+                        self.expiries.write().await.insert(key.clone(), expires_at);
+                        // Replicas must expire the key at the same time, not relative to when
+                        // they apply the command
+                        self.propagate = Some(Command::Set(
+                            key.clone(),
+                            value.clone().into_owned(),
+                            Some(SetExpiry::PxAt(expires_at)),
+                        ));
+                        expiry::spawn_expiration(
+                            key,
+                            expires_at,
+                            self.db.clone(),
+                            self.expiries.clone(),
+                            self.propagation_sender.clone(),
+                            self.server_replication_offset.clone(),
+                            self.active_expire.clone(),
+                        );
+                    }
+                    None => {
+                        self.expiries.write().await.remove(&key);
+                    }
                 }
                 Resp::bulk_string("OK")
             }
+            Command::GetRange(key, start, end) | Command::Substr(key, start, end) => {
+                if self.is_expired(key).await {
+                    Resp::bulk_string("")
+                } else {
+                    match expect_type(self.db.read().await.get(key), "string") {
+                        Ok(Some(Value::Str(value, _))) => {
+                            Resp::BulkString(Cow::Owned(string_range(value, *start, *end)))
+                        }
+                        Ok(_) => Resp::bulk_string(""),
+                        Err(err) => err,
+                    }
+                }
+            }
+            Command::Lcs(key1, key2, options) => {
+                let expired = [self.is_expired(key1).await, self.is_expired(key2).await];
+                let db = self.db.read().await;
+                let value = |key, expired: bool| match expect_type(db.get(key), "string") {
+                    Ok(Some(Value::Str(value, _))) if !expired => Ok(value.as_bytes()),
+                    Ok(_) => Ok(&b""[..]),
+                    Err(err) => Err(err),
+                };
+                match (value(key1, expired[0]), value(key2, expired[1])) {
+                    (Err(err), _) | (_, Err(err)) => err,
+                    (Ok(a), Ok(b)) if options.len => Resp::Integer(lcs_len(a, b) as i64),
+                    (Ok(a), Ok(b)) if options.idx => 'idx: {
+                        let (subsequence, matches) = match lcs(a, b, self.config.proto_max_bulk_len)
+                        {
+                            Ok(lcs) => lcs,
+                            Err(err) => break 'idx err,
+                        };
+                        let matches = matches
+                            .iter()
+                            .filter(|m| m.len() >= options.min_match_len)
+                            .map(|m| {
+                                let mut entry = vec![
+                                    Resp::pair(
+                                        Resp::Integer(m.a.0 as i64),
+                                        Resp::Integer(m.a.1 as i64),
+                                    ),
+                                    Resp::pair(
+                                        Resp::Integer(m.b.0 as i64),
+                                        Resp::Integer(m.b.1 as i64),
+                                    ),
+                                ];
+                                if options.with_match_len {
+                                    entry.push(Resp::Integer(m.len() as i64));
+                                }
+                                Resp::Array(entry)
+                            })
+                            .collect();
+                        self.map(vec![
+                            (Resp::bulk_string("matches"), Resp::Array(matches)),
+                            (
+                                Resp::bulk_string("len"),
+                                Resp::Integer(subsequence.len() as i64),
+                            ),
+                        ])
+                    }
+                    (Ok(a), Ok(b)) => match lcs(a, b, self.config.proto_max_bulk_len) {
+                        Ok((subsequence, _)) => Resp::BulkString(Cow::Owned(
+                            String::from_utf8_lossy(&subsequence).into_owned(),
+                        )),
+                        Err(err) => err,
+                    },
+                }
+            }
+            Command::GetSet(key, value) => {
+                let expired = self.is_expired(key).await;
+                let mut db = self.db.write().await;
+                let old = if expired {
+                    Ok(None)
+                } else {
+                    expect_type(db.get(key), "string")
+                };
+                match old {
+                    Ok(old) => {
+                        let old = match old {
+                            Some(Value::Str(old, _)) => Resp::BulkString(Cow::Owned(old.clone())),
+                            _ => Resp::Null,
+                        };
+                        let key = key.clone().into_owned();
+                        db.insert(key.clone(), value.clone().into_owned().into());
+                        self.expiries.write().await.remove(&key);
+                        self.dirty += 1;
+                        old
+                    }
+                    Err(err) => err,
+                }
+            }
+            Command::Del(keys) => {
+                let mut db = self.db.write().await;
+                let mut expiries = self.expiries.write().await;
+                let deleted = keys
+                    .iter()
+                    .map(|key| key.clone().into_owned())
+                    .filter(|key| {
+                        expiries.remove(key);
+                        db.swap_remove(key).is_some()
+                    })
+                    .count();
+                self.dirty += deleted;
+                Resp::Integer(deleted as i64)
+            }
+            Command::Append(key, value) => 'append: {
+                let Some(suffix) = value.expect_bulk_string() else {
+                    break 'append errors::syntax_error();
+                };
+                let expired = self.is_expired(key).await;
+                let key = key.clone().into_owned();
+                let mut db = self.db.write().await;
+                let mut expiries = self.expiries.write().await;
+                // Only replicas keep expired keys around, writes replace them
+                if expired {
+                    db.swap_remove(&key);
+                }
+                match append(
+                    &mut db,
+                    &mut expiries,
+                    key,
+                    suffix,
+                    self.config.proto_max_bulk_len,
+                ) {
+                    Ok(len) => {
+                        self.dirty += 1;
+                        Resp::Integer(len as i64)
+                    }
+                    Err(err) => err,
+                }
+            }
+            Command::Incr(key)
+            | Command::Decr(key)
+            | Command::IncrBy(key, _)
+            | Command::DecrBy(key, _) => 'incr: {
+                let Some(delta) = command.increment() else {
+                    break 'incr errors::error("ERR decrement would overflow");
+                };
+                let expired = self.is_expired(key).await;
+                let key = key.clone().into_owned();
+                let mut db = self.db.write().await;
+                let mut expiries = self.expiries.write().await;
+                if expired {
+                    db.swap_remove(&key);
+                }
+                match incr_by(&mut db, &mut expiries, key, delta) {
+                    Ok(value) => {
+                        self.dirty += 1;
+                        Resp::Integer(value)
+                    }
+                    Err(err) => err,
+                }
+            }
+            Command::Exists(keys) => {
+                let mut count = 0;
+                for key in keys {
+                    if !self.is_expired(key).await && self.db.read().await.contains_key(key) {
+                        count += 1;
+                    }
+                }
+                Resp::Integer(count)
+            }
+            Command::LPushX(key, elements) | Command::RPushX(key, elements) => {
+                let expired = self.is_expired(key).await;
+                let mut db = self.db.write().await;
+                match db.get_mut(&key.clone().into_owned()) {
+                    Some(_) if expired => Resp::Integer(0),
+                    Some(Value::List(list)) => {
+                        push(list, elements, matches!(command, Command::LPushX(_, _)));
+                        self.dirty += elements.len();
+                        Resp::Integer(list.len() as i64)
+                    }
+                    Some(_) => errors::wrong_type(),
+                    None => Resp::Integer(0),
+                }
+            }
             Command::ConfigGet(item) => match item {
-                Dir if self.config.dir.is_some() => Resp::array(vec![
+                Dir if self.config.dir.is_some() => self.map(vec![(
                     Resp::bulk_string("dir"),
                     Resp::BulkString(Cow::Owned(self.config.dir.clone().unwrap())),
-                ]),
-                DbFileName if self.config.dbfilename.is_some() => Resp::array(vec![
+                )]),
+                DbFileName if self.config.dbfilename.is_some() => self.map(vec![(
                     Resp::bulk_string("dbfilename"),
                     Resp::BulkString(Cow::Owned(self.config.dbfilename.clone().unwrap())),
-                ]),
-                _ => todo!(),
+                )]),
+                // Unset parameters are left out, like ones matching no pattern in Redis
+                _ => self.map(vec![]),
             },
             Command::Keys(key) => {
                 let keys: Vec<Resp<'_>> = self
@@ -190,45 +737,159 @@ impl Connection {
                     .read()
                     .await
                     .keys()
-                    .filter(|_k| {
-                        let Some(key) = key.expect_bulk_string() else {
+                    .filter(|k| {
+                        let (Some(pattern), Some(k)) =
+                            (key.expect_bulk_string(), k.expect_bulk_string())
+                        else {
                             return false;
                         };
-
-                        if key == "*" {
-                            return true;
-                        } else {
-                            return false; // TODO filter by key
-                        }
+                        utils::glob_match(pattern.as_bytes(), k.as_bytes())
                     })
                     .cloned()
                     .collect();
                 Resp::Array(keys)
             }
+            Command::Scan(options) => {
+                let db = self.db.read().await;
+                let expiries = self.expiries.read().await;
+                let (cursor, keys) = scan(&db, &expiries, options);
+                Resp::Array(vec![
+                    Resp::BulkString(Cow::Owned(cursor.to_string())),
+                    Resp::Array(keys),
+                ])
+            }
             Command::Save => {
                 todo!()
             }
+            Command::Info(Some(section))
+                if section
+                    .expect_bulk_string()
+                    .is_some_and(|s| s.eq_ignore_ascii_case("commandstats")) =>
+            {
+                self.text(self.command_stats.info())
+            }
             Command::Info(_parameter) => {
-                let is_replica = self.config.replicaof.is_some();
-                let role = if is_replica {
-                    "role:slave\r\n"
+                let offset = self
+                    .server_replication_offset
+                    .load(std::sync::atomic::Ordering::Acquire);
+                let mut info = String::new();
+                if self.config.replicaof.is_some() {
+                    info.push_str("role:slave\r\n");
+                    let link = *self.master_link.lock().unwrap();
+                    if let Some((host, port)) = self
+                        .config
+                        .replicaof
+                        .as_deref()
+                        .and_then(|master| master.split_once(' '))
+                    {
+                        write!(info, "master_host:{host}\r\nmaster_port:{port}\r\n").unwrap();
+                    }
+                    write!(
+                        info,
+                        "master_link_status:{}\r\n",
+                        if link.up { "up" } else { "down" }
+                    )
+                    .unwrap();
+                    write!(
+                        info,
+                        "master_last_io_seconds_ago:{}\r\n",
+                        link.last_io
+                            .map_or(-1, |last_io| last_io.elapsed().as_secs() as i64)
+                    )
+                    .unwrap();
+                    write!(
+                        info,
+                        "master_sync_in_progress:{}\r\n",
+                        link.sync_in_progress as u8
+                    )
+                    .unwrap();
+                    write!(info, "slave_repl_offset:{offset}\r\n").unwrap();
+                    // Replicas accept writes from clients too
+                    info.push_str("slave_read_only:0\r\n");
                 } else {
-                    "role:master\r\n"
-                };
-                let master_replid = format!("master_replid:{}\r\n", self.server_replication_id);
-                let master_repl_offset = "master_repl_offset:0\r\n";
-                Resp::BulkString(Cow::Owned(format!(
-                    "{}{}{}",
-                    role, master_replid, master_repl_offset
-                )))
-            }
-            Command::ReplConf(_, _) => Resp::bulk_string("OK"),
+                    info.push_str("role:master\r\n");
+                    let mut replicas: Vec<_> = self
+                        .replicas
+                        .read()
+                        .await
+                        .iter()
+                        .map(|(addr, replica)| (*addr, *replica))
+                        .collect();
+                    replicas.sort_by_key(|(addr, _)| *addr);
+                    write!(info, "connected_slaves:{}\r\n", replicas.len()).unwrap();
+                    for (i, (addr, replica)) in replicas.iter().enumerate() {
+                        write!(
+                            info,
+                            "slave{i}:ip={},port={},state={},offset={},lag={}\r\n",
+                            addr.ip(),
+                            replica.port,
+                            replica.state,
+                            replica.offset,
+                            replica.last_ack.elapsed().as_secs()
+                        )
+                        .unwrap();
+                    }
+                }
+                write!(
+                    info,
+                    "master_replid:{}\r\n",
+                    self.server_replication_id.read().unwrap()
+                )
+                .unwrap();
+                write!(info, "master_repl_offset:{offset}\r\n").unwrap();
+                self.text(info)
+            }
+            Command::Shutdown(options) => {
+                if !options.now {
+                    server::drain_replicas(
+                        &self.config,
+                        &self.replicas,
+                        &self.replica_ack,
+                        &self.propagation_sender,
+                        self.server_replication_offset
+                            .load(std::sync::atomic::Ordering::Acquire),
+                    )
+                    .await;
+                }
+                // There is no RDB persistence to save the dataset with
+                if options.save == Some(true) && !options.force {
+                    warn!("Saving the final RDB snapshot is not supported");
+                    errors::error("ERR Errors trying to SHUTDOWN. Check logs.")
+                } else {
+                    info!("Redis is now ready to exit, bye bye...");
+                    std::process::exit(0);
+                }
+            }
+            Command::Failover if self.config.replicaof.is_some() => {
+                errors::error("ERR FAILOVER is not valid when server is a replica.")
+            }
+            Command::Failover
+                if self
+                    .number_of_replicas
+                    .load(std::sync::atomic::Ordering::Acquire)
+                    == 0 =>
+            {
+                errors::error("ERR FAILOVER requires connected replicas.")
+            }
+            Command::Failover => errors::error("ERR FAILOVER is not supported by this server."),
+            Command::Lolwut => self.text(format!("Redis ver. {VERSION}\n")),
+            Command::ReplConf(key, value) => {
+                if key
+                    .expect_bulk_string()
+                    .is_some_and(|key| key.eq_ignore_ascii_case("listening-port"))
+                {
+                    self.replica_listening_port = value
+                        .expect_integer()
+                        .and_then(|port| u16::try_from(port).ok());
+                }
+                Resp::bulk_string("OK")
+            }
             Command::Psync(_master_replication_id, _master_offset) => {
                 let fullresync = Resp::SimpleString(Cow::Owned(format!(
                     "FULLRESYNC {} 0",
-                    self.server_replication_id
+                    self.server_replication_id.read().unwrap()
                 )));
-                self.write_all(&fullresync.encode()).await?;
+                self.write_resp(&fullresync).await?;
                 // TODO: use include_bytes!
                 let empty_rdb: &[u8] = &[
                     0x52, 0x45, 0x44, 0x49, 0x53, 0x30, 0x30, 0x31, 0x31, 0xfa, 0x09, 0x72, 0x65,
@@ -247,98 +908,63 @@ impl Connection {
                 return Ok(());
             }
             Command::Wait(numofreplicas, timeout) => {
-                let numofreplicas = numofreplicas.expect_integer().unwrap();
-
-                if self
-                    .server_replication_offset
-                    .load(std::sync::atomic::Ordering::Acquire)
-                    == 0
-                {
-                    let resp = Resp::Integer(
-                        self.number_of_replicas
-                            .load(std::sync::atomic::Ordering::Acquire)
-                            as i64,
-                    );
-                    self.write_all(&resp.encode()).await?;
+                let connected_replicas = self
+                    .number_of_replicas
+                    .load(std::sync::atomic::Ordering::Acquire);
+                // Nothing to wait for, or nobody who could acknowledge
+                if *numofreplicas <= 0 || self.last_write_offset == 0 || connected_replicas == 0 {
+                    self.write_resp(&Resp::Integer(connected_replicas as i64))
+                        .await?;
                     return Ok(());
                 }
-                let mut syncronized_replicas = self
-                    .replica_offsets
-                    .read()
-                    .await
-                    .iter()
-                    .filter(|(_, offset)| {
-                        **offset
-                            >= self
-                                .server_replication_offset
-                                .load(std::sync::atomic::Ordering::Acquire)
-                    })
-                    .count();
-
-                // Ask for offset from replicas if there is not enough replicas with up to date offset
-                if syncronized_replicas < numofreplicas as usize {
-                    let _ = self.propagation_sender.send(Command::ReplConf(
-                        Resp::bulk_string("GETACK"),
-                        Resp::bulk_string("*"),
-                    ));
-                }
-
-                if syncronized_replicas < numofreplicas as usize {
-                    let timeout = timeout.expect_integer().unwrap();
-                    let replica_offsets = self.replica_offsets.clone();
-                    let _ = tokio::time::timeout(Duration::from_millis(timeout as u64), async {
-                        loop {
-                            syncronized_replicas = replica_offsets
-                                .read()
-                                .await
-                                .iter()
-                                .filter(|(_, offset)| {
-                                    **offset
-                                        >= self
-                                            .server_replication_offset
-                                            .load(std::sync::atomic::Ordering::Acquire)
-                                })
-                                .count();
-                            if syncronized_replicas >= numofreplicas as usize {
-                                break;
-                            }
-                        }
-                    })
-                    .await;
-                }
-                Resp::Integer(syncronized_replicas as i64)
+                // A timeout of 0 blocks until enough replicas acknowledge
+                let timeout = (*timeout > 0).then(|| Duration::from_millis(*timeout as u64));
+                // Fixed for the whole wait, writes by other clients meanwhile don't raise the bar
+                let synchronized = replica::wait_for_replicas(
+                    &self.replicas,
+                    &self.replica_ack,
+                    &self.propagation_sender,
+                    self.last_write_offset,
+                    *numofreplicas as usize,
+                    timeout,
+                )
+                .await;
+                Resp::Integer(synchronized as i64)
             }
             Command::Select(_) => return Ok(()),
             Command::Type(key) => {
                 let value = self.db.read().await.get(key).cloned();
                 Resp::simple_string(value.map(|v| v.value_type()).unwrap_or("none"))
             }
-            Command::XAdd(key, id, items) => {
+            Command::XAdd(key, id, items, nomkstream) => 'xadd: {
                 let mut db = self.db.write().await;
+                if let Err(err) = expect_type(db.get(key), "stream") {
+                    break 'xadd err;
+                }
                 let entry = db.entry(key.clone().into_owned());
                 let mut err = None;
                 let mut id = id.clone();
 
                 match entry {
-                    std::collections::hash_map::Entry::Occupied(mut occupied_entry) => {
-                        let value = occupied_entry.get_mut();
-                        match value {
-                            Value::Stream(ref mut stream) => {
-                                for pair in items.chunks(2) {
-                                    let key = pair[0].expect_bulk_string().unwrap();
-                                    let value = Value::from(pair[1].clone());
-                                    match stream.insert(&id, key.to_string(), value) {
-                                        Ok(stream_id) => id = stream_id.into(),
-                                        Err(stream_err) => {
-                                            err = Some(stream_err);
-                                        }
-                                    }
+                    indexmap::map::Entry::Occupied(mut occupied_entry) => {
+                        let Value::Stream(stream) = occupied_entry.get_mut() else {
+                            unreachable!("type is checked above");
+                        };
+                        for pair in items.chunks(2) {
+                            let key = pair[0].expect_bulk_string().unwrap();
+                            let value = Value::from(pair[1].clone());
+                            match stream.insert(&id, key.to_string(), value) {
+                                Ok(stream_id) => id = stream_id.into(),
+                                Err(stream_err) => {
+                                    err = Some(stream_err);
                                 }
                             }
-                            _ => todo!("error"),
                         }
                     }
-                    std::collections::hash_map::Entry::Vacant(vacant_entry) => {
+                    indexmap::map::Entry::Vacant(_) if *nomkstream => {
+                        break 'xadd Resp::Null;
+                    }
+                    indexmap::map::Entry::Vacant(vacant_entry) => {
                         let mut stream = Stream::new();
                         for pair in items.chunks(2) {
                             if pair.len() == 2 {
@@ -358,68 +984,620 @@ impl Connection {
                         vacant_entry.insert(Value::Stream(stream));
                     }
                 };
-                err.map(|err| Resp::SimpleError(Cow::Owned(err.to_string())))
+                err.map(|err| errors::error(err.to_string()))
                     .unwrap_or(id.clone())
             }
+            Command::Auth(username, password) => {
+                let username = username
+                    .as_ref()
+                    .and_then(|u| u.expect_bulk_string())
+                    .map(|u| u.to_string())
+                    .unwrap_or(DEFAULT_USER.to_string());
+                let password = password
+                    .expect_bulk_string()
+                    .ok_or(CommandError::IncorrectFormat)?;
+                let default_is_nopass = self
+                    .acl
+                    .read()
+                    .await
+                    .get(DEFAULT_USER)
+                    .is_some_and(|user| user.is_nopass());
+                if username == DEFAULT_USER && default_is_nopass {
+                    errors::error(
+                        "ERR AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?",
+                    )
+                } else if self.authenticate(&username, password).await {
+                    Resp::simple_string("OK")
+                } else {
+                    errors::wrong_pass()
+                }
+            }
+            Command::Hello(protover, auth) => 'hello: {
+                if !matches!(protover, None | Some(2) | Some(3)) {
+                    break 'hello errors::no_proto();
+                }
+                if let Some((username, password)) = auth {
+                    let (Some(username), Some(password)) =
+                        (username.expect_bulk_string(), password.expect_bulk_string())
+                    else {
+                        return Err(CommandError::IncorrectFormat.into());
+                    };
+                    if !self.authenticate(username, password).await {
+                        break 'hello errors::wrong_pass();
+                    }
+                }
+                if let Some(protover) = protover {
+                    self.protocol = *protover as u8;
+                }
+
+                let role = if self.config.replicaof.is_some() {
+                    "replica"
+                } else {
+                    "master"
+                };
+                let fields = vec![
+                    (Resp::bulk_string("server"), Resp::bulk_string("redis")),
+                    (Resp::bulk_string("version"), Resp::bulk_string(VERSION)),
+                    (
+                        Resp::bulk_string("proto"),
+                        Resp::Integer(self.protocol as i64),
+                    ),
+                    (Resp::bulk_string("id"), Resp::Integer(self.id as i64)),
+                    (Resp::bulk_string("mode"), Resp::bulk_string("standalone")),
+                    (Resp::bulk_string("role"), Resp::bulk_string(role)),
+                    (Resp::bulk_string("modules"), Resp::Array(vec![])),
+                ];
+                self.map(fields)
+            }
+            Command::Quit => {
+                self.is_closed = true;
+                Resp::simple_string("OK")
+            }
+            Command::Reset => {
+                self.is_monitoring = false;
+                self.protocol = 2;
+                self.username = DEFAULT_USER.to_string();
+                self.unsubscribe_all();
+                self.tracking.disable(self.id);
+                self.tracks_reads = false;
+                self.is_authenticated = self
+                    .acl
+                    .read()
+                    .await
+                    .get(DEFAULT_USER)
+                    .is_some_and(|user| user.is_nopass());
+                Resp::simple_string("RESET")
+            }
+            // Without cluster mode replicas always serve reads, and there are no slot migrations
+            // to ask about on a single node
+            Command::ReadOnly | Command::ReadWrite | Command::Asking => Resp::simple_string("OK"),
+            Command::Monitor => {
+                self.is_monitoring = true;
+                Resp::simple_string("OK")
+            }
+            Command::Subscribe(kind, channels) => {
+                for channel in channels {
+                    self.pubsub
+                        .subscribe(*kind, channel, self.id, self.message_sender.clone());
+                    let subscriptions = self.subscriptions.entry(*kind).or_default();
+                    subscriptions.insert(channel.clone());
+                    let resp = Resp::Array(vec![
+                        Resp::bulk_string(kind.subscribe_frame()),
+                        Resp::BulkString(Cow::Owned(channel.clone())),
+                        Resp::Integer(subscriptions.len() as i64),
+                    ]);
+                    self.write_resp(&resp).await?;
+                }
+                return Ok(());
+            }
+            Command::Unsubscribe(kind, channels) => {
+                let subscriptions = self.subscriptions.entry(*kind).or_default();
+                let mut channels = channels.clone();
+                // No channels means all of them
+                if channels.is_empty() {
+                    channels = subscriptions.iter().cloned().collect();
+                    channels.sort();
+                }
+                if channels.is_empty() {
+                    let resp = Resp::Array(vec![
+                        Resp::bulk_string(kind.unsubscribe_frame()),
+                        Resp::Null,
+                        Resp::Integer(0),
+                    ]);
+                    self.write_resp(&resp).await?;
+                }
+                for channel in channels {
+                    self.pubsub.unsubscribe(*kind, &channel, self.id);
+                    let subscriptions = self.subscriptions.entry(*kind).or_default();
+                    subscriptions.remove(&channel);
+                    let resp = Resp::Array(vec![
+                        Resp::bulk_string(kind.unsubscribe_frame()),
+                        Resp::BulkString(Cow::Owned(channel)),
+                        Resp::Integer(subscriptions.len() as i64),
+                    ]);
+                    self.write_resp(&resp).await?;
+                }
+                return Ok(());
+            }
+            Command::Publish(kind, channel, message) => {
+                Resp::Integer(self.pubsub.publish(*kind, channel, message) as i64)
+            }
+            Command::PubSub(subcommand) => match subcommand {
+                PubSubSubcommand::Channels(kind, pattern) => Resp::Array(
+                    self.pubsub
+                        .channels(*kind, pattern.as_deref())
+                        .into_iter()
+                        .map(|channel| Resp::BulkString(Cow::Owned(channel)))
+                        .collect(),
+                ),
+                PubSubSubcommand::NumSub(kind, channels) => Resp::Array(
+                    channels
+                        .iter()
+                        .flat_map(|channel| {
+                            [
+                                Resp::BulkString(Cow::Owned(channel.clone())),
+                                Resp::Integer(self.pubsub.numsub(*kind, channel) as i64),
+                            ]
+                        })
+                        .collect(),
+                ),
+                PubSubSubcommand::NumPat => Resp::Integer(0),
+            },
+            Command::Acl(subcommand) => match subcommand {
+                AclSubcommand::SetUser(username, rules) => {
+                    let username = username
+                        .expect_bulk_string()
+                        .ok_or(CommandError::IncorrectFormat)?;
+                    let rules: Vec<&str> = rules
+                        .iter()
+                        .flat_map(|rule| rule.expect_bulk_string())
+                        .map(|rule| rule.as_ref())
+                        .collect();
+                    match self.acl.write().await.set_user(username, &rules) {
+                        Ok(()) => Resp::simple_string("OK"),
+                        Err(err) => errors::error(err.to_string()),
+                    }
+                }
+                AclSubcommand::GetUser(username) => {
+                    let username = username
+                        .expect_bulk_string()
+                        .ok_or(CommandError::IncorrectFormat)?;
+                    match self.acl.read().await.get(username) {
+                        Some(user) => user.to_resp(),
+                        None => Resp::Null,
+                    }
+                }
+                AclSubcommand::List => Resp::Array(
+                    self.acl
+                        .read()
+                        .await
+                        .users()
+                        .map(|user| Resp::BulkString(Cow::Owned(user.describe())))
+                        .collect(),
+                ),
+                AclSubcommand::WhoAmI => Resp::BulkString(Cow::Owned(self.username.clone())),
+                AclSubcommand::Cat => {
+                    Resp::Array(CATEGORIES.iter().map(|c| Resp::bulk_string(c)).collect())
+                }
+            },
+            Command::Client(subcommand) => match subcommand {
+                ClientSubcommand::Id => Resp::Integer(self.id as i64),
+                ClientSubcommand::KillAddr(addr) => {
+                    let killed = self.clients.kill(|_, a| a.to_string() == *addr);
+                    if killed > 0 {
+                        Resp::simple_string("OK")
+                    } else {
+                        errors::no_such_client()
+                    }
+                }
+                ClientSubcommand::Kill(filters) => {
+                    let skip_me = filters
+                        .iter()
+                        .rev()
+                        .find_map(|f| match f {
+                            ClientKillFilter::SkipMe(skip) => Some(*skip),
+                            _ => None,
+                        })
+                        .unwrap_or(true);
+                    let killed = self.clients.kill(|id, addr| {
+                        (!skip_me || id != self.id)
+                            && filters.iter().all(|filter| match filter {
+                                ClientKillFilter::Id(filter_id) => id == *filter_id,
+                                ClientKillFilter::Addr(filter_addr) => {
+                                    addr.to_string() == *filter_addr
+                                }
+                                ClientKillFilter::SkipMe(_) => true,
+                            })
+                    });
+                    Resp::Integer(killed as i64)
+                }
+                // Keys are never evicted, there is no maxmemory
+                ClientSubcommand::NoEvict(_) => Resp::simple_string("OK"),
+                // Keys don't track their access time, so there is nothing for NO-TOUCH to skip
+                ClientSubcommand::NoTouch(true) => {
+                    errors::error("ERR CLIENT NO-TOUCH is not supported, keys have no access time")
+                }
+                ClientSubcommand::NoTouch(false) => Resp::simple_string("OK"),
+                ClientSubcommand::Tracking(None) => {
+                    self.tracking.disable(self.id);
+                    self.tracks_reads = false;
+                    Resp::simple_string("OK")
+                }
+                ClientSubcommand::Tracking(Some(options)) => 'tracking: {
+                    if !options.prefixes.is_empty() && !options.bcast {
+                        break 'tracking errors::error(
+                            "ERR PREFIX option requires BCAST mode to be enabled",
+                        );
+                    }
+                    let target = match options.redirect {
+                        Some(id) if !self.clients.contains(id) => {
+                            break 'tracking errors::error(
+                                "ERR The client ID you want redirect to does not exist",
+                            );
+                        }
+                        Some(id) => TrackingTarget::Redirect(id),
+                        None if self.protocol == 3 => {
+                            TrackingTarget::Push(self.message_sender.clone())
+                        }
+                        // RESP2 clients can only receive invalidations while subscribed
+                        None => TrackingTarget::Redirect(self.id),
+                    };
+                    self.tracking
+                        .enable(self.id, target, options.bcast, options.prefixes.clone());
+                    self.tracks_reads = !options.bcast;
+                    Resp::simple_string("OK")
+                }
+            },
+            Command::Object(ObjectSubcommand::Encoding(key)) => {
+                if self.is_expired(key).await {
+                    Resp::Null
+                } else {
+                    match self.db.read().await.get(key) {
+                        Some(value) => Resp::bulk_string(value.encoding()),
+                        None => Resp::Null,
+                    }
+                }
+            }
+            Command::Object(ObjectSubcommand::RefCount(key)) => {
+                if self.is_expired(key).await {
+                    Resp::Null
+                } else {
+                    match self.db.read().await.get(key) {
+                        Some(value) => Resp::Integer(value.refcount()),
+                        None => Resp::Null,
+                    }
+                }
+            }
+            Command::Cluster(ClusterSubcommand::Info) => {
+                self.text("cluster_enabled:0\r\ncluster_state:ok\r\n".to_string())
+            }
+            Command::Cluster(ClusterSubcommand::MyId) => {
+                Resp::BulkString(Cow::Owned(self.node_id.to_string()))
+            }
+            Command::Cluster(ClusterSubcommand::Slots | ClusterSubcommand::Shards) => {
+                Resp::Array(vec![])
+            }
+            Command::Memory(MemorySubcommand::Doctor) => {
+                let (dataset, overhead) = self.memory_usage().await;
+                self.text(if dataset + overhead < MEMORY_DOCTOR_MIN_BYTES {
+                    "Hi Sam, this instance is empty or is using very little memory, my issues detector can't be used in these conditions. Please, leave for your mission on Earth and fill it with some data. The new Sam and I will be back to our programming as soon as I finished rebooting.".to_string()
+                } else {
+                    "Hi Sam, I can't find any memory issue in your instance. I can only account for what occurs on this base.".to_string()
+                })
+            }
+            Command::Memory(MemorySubcommand::Stats) => {
+                let (dataset, overhead) = self.memory_usage().await;
+                let keys = self.db.read().await.len();
+                let total = dataset + overhead;
+                self.map(vec![
+                    (
+                        Resp::bulk_string("total.allocated"),
+                        Resp::Integer(total as i64),
+                    ),
+                    (
+                        Resp::bulk_string("clients.slaves"),
+                        Resp::Integer(
+                            self.number_of_replicas
+                                .load(std::sync::atomic::Ordering::Acquire)
+                                as i64,
+                        ),
+                    ),
+                    (
+                        Resp::bulk_string("clients.normal"),
+                        Resp::Integer(self.clients.len() as i64),
+                    ),
+                    (
+                        Resp::bulk_string("overhead.total"),
+                        Resp::Integer(overhead as i64),
+                    ),
+                    (Resp::bulk_string("keys.count"), Resp::Integer(keys as i64)),
+                    (
+                        Resp::bulk_string("keys.bytes-per-key"),
+                        Resp::Integer(total.checked_div(keys).unwrap_or(0) as i64),
+                    ),
+                    (
+                        Resp::bulk_string("dataset.bytes"),
+                        Resp::Integer(dataset as i64),
+                    ),
+                ])
+            }
+            Command::SlowLog(subcommand) => {
+                let mut slowlog = self.slowlog.lock().unwrap();
+                match subcommand {
+                    SlowLogSubcommand::Get(count) => slowlog.get(count.unwrap_or(10)),
+                    SlowLogSubcommand::Len => Resp::Integer(slowlog.len() as i64),
+                    SlowLogSubcommand::Reset => {
+                        slowlog.reset();
+                        Resp::simple_string("OK")
+                    }
+                }
+            }
+            Command::Commands(CommandSubcommand::Info(names)) if names.is_empty() => {
+                Resp::Array(COMMANDS.iter().map(|spec| spec.to_resp()).collect())
+            }
+            Command::Commands(CommandSubcommand::Info(names)) => Resp::Array(
+                names
+                    .iter()
+                    .map(|name| spec::lookup(name).map_or(Resp::Null, |spec| spec.to_resp()))
+                    .collect(),
+            ),
+            Command::Commands(CommandSubcommand::Count) => Resp::Integer(COMMANDS.len() as i64),
+            Command::Commands(CommandSubcommand::Docs(names)) => {
+                // Unknown commands are left out rather than replied with nil
+                let entries = if names.is_empty() {
+                    docs::DOCS.iter().collect()
+                } else {
+                    names
+                        .iter()
+                        .filter_map(|name| docs::lookup(name))
+                        .collect::<Vec<_>>()
+                };
+                self.map(
+                    entries
+                        .into_iter()
+                        .map(|doc| (Resp::bulk_string(doc.name), doc.to_resp()))
+                        .collect(),
+                )
+            }
+            Command::Debug(DebugSubcommand::ChangeReplId) => {
+                *self.server_replication_id.write().unwrap() = utils::generate_replication_id();
+                Resp::simple_string("OK")
+            }
+            Command::Debug(DebugSubcommand::FlushAll) => {
+                let mut db = self.db.write().await;
+                // Pending expiration timers find their deadline gone and leave the keys alone
+                self.expiries.write().await.clear();
+                db.clear();
+                Resp::simple_string("OK")
+            }
+            Command::Debug(DebugSubcommand::Jmap) => Resp::simple_string("OK"),
+            Command::Debug(DebugSubcommand::ReplicationOffset) => {
+                let offset = self
+                    .server_replication_offset
+                    .load(std::sync::atomic::Ordering::Acquire);
+                let mut replicas: Vec<_> = self
+                    .replicas
+                    .read()
+                    .await
+                    .iter()
+                    .map(|(addr, replica)| (*addr, replica.offset))
+                    .collect();
+                replicas.sort();
+                Resp::Array(vec![
+                    Resp::Integer(offset as i64),
+                    Resp::Array(
+                        replicas
+                            .into_iter()
+                            .map(|(addr, offset)| {
+                                Resp::Array(vec![
+                                    Resp::BulkString(Cow::Owned(addr.to_string())),
+                                    Resp::Integer(offset as i64),
+                                ])
+                            })
+                            .collect(),
+                    ),
+                ])
+            }
+            Command::Debug(DebugSubcommand::Reload) => {
+                let mut db = self.db.write().await;
+                let mut expiries = self.expiries.write().await;
+                match Rdb::dump(&db, &expiries).and_then(|rdb| Rdb::decode(&rdb)) {
+                    Ok(rdb) => {
+                        *db = std::mem::take(&mut *rdb.database.write().await);
+                        *expiries = std::mem::take(&mut *rdb.expiries.write().await);
+                        Resp::simple_string("OK")
+                    }
+                    Err(err) => {
+                        errors::error(format!("ERR Error trying to reload the dataset: {err}"))
+                    }
+                }
+            }
+            Command::Debug(DebugSubcommand::StringMatchLen(pattern, string)) => {
+                Resp::Integer(utils::glob_match(pattern.as_bytes(), string.as_bytes()) as i64)
+            }
+            Command::Debug(DebugSubcommand::Sleep(duration)) => {
+                tokio::time::sleep(*duration).await;
+                Resp::simple_string("OK")
+            }
+            Command::Debug(DebugSubcommand::Object(key)) => 'object: {
+                let key = Resp::BulkString(Cow::Owned(key.clone()));
+                if self.is_expired(&key).await {
+                    break 'object errors::error("ERR no such key");
+                }
+                match expect_type(self.db.read().await.get(&key), "stream") {
+                    Ok(Some(value @ Value::Stream(stream))) => {
+                        // No serializedlength, streams can't be saved to RDB files yet
+                        Resp::SimpleString(Cow::Owned(format!(
+                            "Value at:{value:p} refcount:{} encoding:{} lru:0 lru_seconds_idle:0 \
+                             entries:{} last_id:{} radix_tree_nodes:{}",
+                            value.refcount(),
+                            value.encoding(),
+                            stream.len(),
+                            stream.last_id().unwrap_or(StreamId::MIN),
+                            stream.radix_tree_nodes(),
+                        )))
+                    }
+                    Ok(_) => errors::error("ERR no such key"),
+                    Err(err) => err,
+                }
+            }
+            Command::Debug(DebugSubcommand::DumpKey(key)) => {
+                let key = Resp::BulkString(Cow::Owned(key.clone()));
+                if self.is_expired(&key).await {
+                    Resp::Null
+                } else {
+                    match self.db.read().await.get(&key) {
+                        Some(value) => {
+                            let mut description = value.describe();
+                            // Milliseconds left, -1 for keys without an expiry like PTTL
+                            description["ttl"] = self.expiries.read().await.get(&key).map_or(
+                                (-1).into(),
+                                |expires_at| {
+                                    (expires_at - utils::get_epoch_ms() as i64).max(0).into()
+                                },
+                            );
+                            Resp::BulkString(Cow::Owned(description.to_string()))
+                        }
+                        None => Resp::Null,
+                    }
+                }
+            }
+            Command::Debug(DebugSubcommand::SetActiveExpire(on)) => {
+                self.active_expire
+                    .store(*on, std::sync::atomic::Ordering::Relaxed);
+                Resp::simple_string("OK")
+            }
             Command::XRange(key, from, to) => {
                 let db = self.db.read().await;
-                let value = db.get(key).cloned();
-                match value {
-                    Some(Value::Stream(stream)) => stream.range(&from, &to)?,
-                    _ => todo!(),
+                match expect_type(db.get(key), "stream") {
+                    Ok(Some(Value::Stream(stream))) => stream.range(from, to)?,
+                    Ok(_) => Resp::Array(vec![]),
+                    Err(err) => err,
                 }
             }
-            Command::XRead(key, streams, ids) => {
+            Command::XRead(_key, streams, ids) => {
                 let db = self.db.read().await;
-                let res = streams
-                    .into_iter()
-                    .enumerate()
-                    .flat_map(|(idx, key)| match db.get(&key).cloned() {
-                        Some(Value::Stream(stream)) => Some(Resp::Array(vec![
-                            key.clone(),
-                            stream.range(&ids[idx], &StreamId::MAX.into()).ok()?,
-                        ])),
-                        _ => todo!(),
-                    })
-                    .collect();
-                Resp::Array(res)
+                let values = streams
+                    .iter()
+                    .map(|key| expect_type(db.get(key), "stream"))
+                    .collect::<Result<Vec<_>, _>>();
+                match values {
+                    Ok(values) => Resp::Array(
+                        streams
+                            .iter()
+                            .zip(values)
+                            .zip(ids)
+                            .flat_map(|((key, value), id)| match value {
+                                Some(Value::Stream(stream)) => Some(Resp::Array(vec![
+                                    key.clone(),
+                                    stream.range(id, &StreamId::MAX.into()).ok()?,
+                                ])),
+                                _ => None,
+                            })
+                            .collect(),
+                    ),
+                    Err(err) => err,
+                }
             }
         };
-        self.write_all(&resp.encode()).await?;
+        self.write_resp(&resp).await?;
 
-        if command.is_write_command() && !self.is_promoted_to_replica {
+        let is_read =
+            spec::lookup(command.name()).is_some_and(|spec| spec.flags.contains(&"readonly"));
+        if self.tracks_reads && is_read {
+            let keys = command.keys();
+            self.tracking.track(
+                self.id,
+                keys.iter()
+                    .filter_map(|key| key.expect_bulk_string())
+                    .map(|key| key.as_ref()),
+            );
+        }
+
+        if command.is_write_command() && self.dirty > 0 && !self.is_promoted_to_replica {
+            let command = self
+                .propagate
+                .take()
+                .unwrap_or_else(|| command.clone().into_owned());
             // TODO: this is not optimal
             let resp: Resp<'_> = command.clone().into();
-            self.server_replication_offset
-                .fetch_add(resp.len(), std::sync::atomic::Ordering::Release);
-            let _ = self.propagation_sender.send(command.into_owned());
+            self.last_write_offset = self
+                .server_replication_offset
+                .fetch_add(resp.len(), std::sync::atomic::Ordering::Release)
+                + resp.len();
+            let _ = self.propagation_sender.send(command);
         }
 
         Ok(())
     }
 }
 
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.clients.unregister(self.id);
+        self.unsubscribe_all();
+        self.tracking.disable(self.id);
+    }
+}
+
+impl AsyncWrite for ConnectionStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        match self.get_mut() {
+            ConnectionStream::Tcp(tcp) => Pin::new(tcp).poll_write(cx, buf),
+            ConnectionStream::Tls(tls) => Pin::new(tls).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        match self.get_mut() {
+            ConnectionStream::Tcp(tcp) => Pin::new(tcp).poll_flush(cx),
+            ConnectionStream::Tls(tls) => Pin::new(tls).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        match self.get_mut() {
+            ConnectionStream::Tcp(tcp) => Pin::new(tcp).poll_shutdown(cx),
+            ConnectionStream::Tls(tls) => Pin::new(tls).poll_shutdown(cx),
+        }
+    }
+}
+
+impl AsyncRead for ConnectionStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut io::ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            ConnectionStream::Tcp(tcp) => Pin::new(tcp).poll_read(cx, buf),
+            ConnectionStream::Tls(tls) => Pin::new(tls).poll_read(cx, buf),
+        }
+    }
+}
+
 impl AsyncWrite for Connection {
     fn poll_write(
         mut self: Pin<&mut Self>,
         cx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<Result<usize, io::Error>> {
-        let tcp = Pin::new(&mut self.tcp);
-        TcpStream::poll_write(tcp, cx, buf)
+        Pin::new(&mut self.stream).poll_write(cx, buf)
     }
 
     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
-        let tcp = Pin::new(&mut self.tcp);
-        TcpStream::poll_flush(tcp, cx)
+        Pin::new(&mut self.stream).poll_flush(cx)
     }
 
     fn poll_shutdown(
         mut self: Pin<&mut Self>,
         cx: &mut Context<'_>,
     ) -> Poll<Result<(), io::Error>> {
-        let tcp = Pin::new(&mut self.tcp);
-        TcpStream::poll_shutdown(tcp, cx)
+        Pin::new(&mut self.stream).poll_shutdown(cx)
     }
 }
 
@@ -429,7 +1607,6 @@ impl AsyncRead for Connection {
         cx: &mut Context<'_>,
         buf: &mut io::ReadBuf<'_>,
     ) -> Poll<std::io::Result<()>> {
-        let tcp = Pin::new(&mut self.tcp);
-        TcpStream::poll_read(tcp, cx, buf)
+        Pin::new(&mut self.stream).poll_read(cx, buf)
     }
 }
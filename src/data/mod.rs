@@ -1,14 +1,31 @@
 use std::collections::HashMap;
 
 use indexmap::IndexMap;
+use serde_json::json;
 
-use crate::{rdb::RdbString, resp::Resp};
+use crate::{
+    command::ScanOptions, errors, expiry, rdb::RdbString, resp::Resp, utils, InnerDb, InnerExpiries,
+};
 
 pub mod stream;
 
+/// Strings up to this length are allocated together with their object header in Redis.
+const EMBSTR_MAX_LEN: usize = 44;
+
+/// Redis preallocates the integers below this and shares them between keys.
+const SHARED_INTEGERS: i64 = 10000;
+
+/// The refcount Redis reports for shared objects, which are never freed.
+const SHARED_REFCOUNT: i64 = i32::MAX as i64;
+
+/// How many elements of a collection `describe` includes.
+const SAMPLE_SIZE: usize = 10;
+
 #[derive(Debug, Clone)]
 pub enum Value {
-    Str(String),
+    /// A string, and whether it was modified in place since it was set, which makes Redis keep
+    /// it in the `raw` encoding whatever it holds.
+    Str(String, bool),
     List(Vec<Value>),
     Stream(stream::Stream),
 }
@@ -16,31 +33,326 @@ pub enum Value {
 impl Value {
     pub fn expect_string(self) -> Option<String> {
         match self {
-            Value::Str(str) => Some(str),
+            Value::Str(str, _) => Some(str),
             _ => None,
         }
     }
 
     pub fn value_type(&self) -> &'static str {
         match self {
-            Value::Str(_) => "string",
+            Value::Str(_, _) => "string",
             Value::List(_) => "list",
             Value::Stream(_) => "stream",
         }
     }
+
+    /// The internal encoding Redis would use for the value, as reported by `OBJECT ENCODING`.
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Value::Str(value, false) if parse_integer(value).is_some() => "int",
+            Value::Str(value, false) if value.len() <= EMBSTR_MAX_LEN => "embstr",
+            Value::Str(_, _) => "raw",
+            Value::List(_) => "listpack",
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    /// The refcount reported by `OBJECT REFCOUNT`. Values are never shared here, but small
+    /// integers report the shared refcount like in Redis, where clients rely on it.
+    pub fn refcount(&self) -> i64 {
+        match self {
+            Value::Str(value, _)
+                if self.encoding() == "int"
+                    && value
+                        .parse::<i64>()
+                        .is_ok_and(|n| (0..SHARED_INTEGERS).contains(&n)) =>
+            {
+                SHARED_REFCOUNT
+            }
+            _ => 1,
+        }
+    }
+
+    /// Approximate heap and inline bytes held by the value, for memory reporting.
+    pub fn approx_size(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + match self {
+                Value::Str(value, _) => value.capacity(),
+                Value::List(items) => items.iter().map(Value::approx_size).sum(),
+                Value::Stream(stream) => stream.approx_size(),
+            }
+    }
+
+    /// A JSON description of the value for `DEBUG DUMP-KEY`, collections are sampled.
+    pub fn describe(&self) -> serde_json::Value {
+        match self {
+            Value::Str(value, _) => json!({
+                "type": self.value_type(),
+                "size": value.len(),
+                "sample": value,
+            }),
+            Value::List(items) => json!({
+                "type": self.value_type(),
+                "size": items.len(),
+                "sample": items.iter().take(SAMPLE_SIZE).map(Value::sample).collect::<Vec<_>>(),
+            }),
+            Value::Stream(stream) => stream.describe(SAMPLE_SIZE),
+        }
+    }
+
+    /// Strings as plain JSON strings, anything else as its full description.
+    pub(crate) fn sample(&self) -> serde_json::Value {
+        match self {
+            Value::Str(value, _) => json!(value),
+            value => value.describe(),
+        }
+    }
+}
+
+/// Passes through missing keys and values of type `want`, anything else is a WRONGTYPE error.
+pub fn expect_type<'a>(
+    value: Option<&'a Value>,
+    want: &str,
+) -> Result<Option<&'a Value>, Resp<'static>> {
+    match value {
+        Some(value) if value.value_type() != want => Err(errors::wrong_type()),
+        value => Ok(value),
+    }
+}
+
+/// Parses `value` as a 64 bit integer the way Redis does, without signs, spaces or leading zeroes
+/// that would not survive formatting it back.
+pub fn parse_integer(value: &str) -> Option<i64> {
+    value.parse().ok().filter(|n: &i64| n.to_string() == value)
+}
+
+/// Adds `delta` to the integer stored at `key` like `INCRBY`. A missing key counts as 0 and is
+/// created without an expiry, dropping any entry left behind by an earlier key of the same name.
+pub fn incr_by(
+    db: &mut InnerDb,
+    expiries: &mut InnerExpiries,
+    key: Resp<'static>,
+    delta: i64,
+) -> Result<i64, Resp<'static>> {
+    let current = match db.get(&key) {
+        Some(Value::Str(value, _)) => parse_integer(value).ok_or_else(errors::not_an_integer)?,
+        Some(_) => return Err(errors::wrong_type()),
+        None => 0,
+    };
+    let value = current
+        .checked_add(delta)
+        .ok_or_else(|| errors::error("ERR increment or decrement would overflow"))?;
+    if !db.contains_key(&key) {
+        expiries.remove(&key);
+    }
+    db.insert(key, Value::Str(value.to_string(), false));
+    Ok(value)
+}
+
+/// Appends `suffix` to the string stored at `key` like `APPEND` and returns its new length, which
+/// leaves the string `raw` encoded. A missing key is created without an expiry, like in `incr_by`.
+/// Strings may not grow past `max_len` bytes, `proto-max-bulk-len` in Redis.
+pub fn append(
+    db: &mut InnerDb,
+    expiries: &mut InnerExpiries,
+    key: Resp<'static>,
+    suffix: &str,
+    max_len: usize,
+) -> Result<usize, Resp<'static>> {
+    let exceeds = |len: usize| len.saturating_add(suffix.len()) > max_len;
+    match db.get_mut(&key) {
+        Some(Value::Str(value, _)) if exceeds(value.len()) => Err(errors::string_too_long()),
+        Some(Value::Str(value, raw)) => {
+            value.push_str(suffix);
+            *raw = true;
+            Ok(value.len())
+        }
+        Some(_) => Err(errors::wrong_type()),
+        None if exceeds(0) => Err(errors::string_too_long()),
+        None => {
+            expiries.remove(&key);
+            db.insert(key, Value::Str(suffix.to_string(), false));
+            Ok(suffix.len())
+        }
+    }
+}
+
+/// One page of `SCAN`: the next cursor, 0 once done, and the matching keys in the page.
+///
+/// Keys are visited by position from the end of the keyspace down, and the cursor is the lowest
+/// position visited so far. Deleting a key moves the last one into its place and new keys are
+/// appended, so neither moves a key from below the cursor to above it: every key present for the
+/// whole scan is returned, though some may be returned twice.
+pub fn scan(
+    db: &InnerDb,
+    expiries: &InnerExpiries,
+    options: &ScanOptions,
+) -> (u64, Vec<Resp<'static>>) {
+    let end = match usize::try_from(options.cursor) {
+        Ok(0) | Err(_) => db.len(),
+        Ok(cursor) => cursor.min(db.len()),
+    };
+    let start = end.saturating_sub(options.count.max(1));
+    let keys = (start..end)
+        .rev()
+        .filter_map(|position| db.get_index(position))
+        .filter(|(key, value)| {
+            let matches_pattern = options.pattern.as_ref().is_none_or(|pattern| {
+                key.expect_bulk_string()
+                    .is_some_and(|key| utils::glob_match(pattern.as_bytes(), key.as_bytes()))
+            });
+            let matches_type = options
+                .value_type
+                .as_ref()
+                .is_none_or(|value_type| value_type.eq_ignore_ascii_case(value.value_type()));
+            let is_expired = expiries.get(*key).is_some_and(|at| expiry::is_due(*at));
+            matches_pattern && matches_type && !is_expired
+        })
+        .map(|(key, _)| key.clone())
+        .collect();
+    (start as u64, keys)
+}
+
+/// The bytes of `value` between the inclusive offsets `start` and `end`, as `GETRANGE` counts them:
+/// negative offsets count from the end and out of range offsets are clamped.
+pub fn string_range(value: &str, start: i64, end: i64) -> String {
+    let len = value.len() as i64;
+    if start < 0 && end < 0 && start > end {
+        return String::new();
+    }
+    let start = if start < 0 { len + start } else { start }.max(0);
+    let end = if end < 0 { len + end } else { end }.max(0).min(len - 1);
+    if start > end {
+        return String::new();
+    }
+    String::from_utf8_lossy(&value.as_bytes()[start as usize..=end as usize]).into_owned()
+}
+
+/// Pushes `elements` one at a time to the head of `list` like `LPUSH`, so they end up in reverse
+/// order, or to its tail like `RPUSH`.
+pub fn push(list: &mut Vec<Value>, elements: &[Resp<'_>], head: bool) {
+    let elements = elements.iter().map(|element| Value::from(element.clone()));
+    if head {
+        list.splice(0..0, elements.rev());
+    } else {
+        list.extend(elements);
+    }
+}
+
+/// A run of bytes `a[a_start..=a_end]` equal to `b[b_start..=b_end]` in a common subsequence.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LcsMatch {
+    pub a: (usize, usize),
+    pub b: (usize, usize),
+}
+
+impl LcsMatch {
+    pub fn len(&self) -> usize {
+        self.a.1 - self.a.0 + 1
+    }
+}
+
+/// Length of the longest common subsequence of `a` and `b`, keeping only two rows of the table.
+pub fn lcs_len(a: &[u8], b: &[u8]) -> usize {
+    let mut previous = vec![0; b.len() + 1];
+    let mut current = vec![0; b.len() + 1];
+    for &x in a {
+        for (j, &y) in b.iter().enumerate() {
+            current[j + 1] = if x == y {
+                previous[j] + 1
+            } else {
+                previous[j + 1].max(current[j])
+            };
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// The longest common subsequence of `a` and `b`, with the contiguous runs it is made of listed
+/// from the end of the strings backwards, as `LCS IDX` reports them. The dynamic programming table
+/// may take at most `max_bytes`, like Redis caps it at `proto-max-bulk-len`.
+pub fn lcs(
+    a: &[u8],
+    b: &[u8],
+    max_bytes: usize,
+) -> Result<(Vec<u8>, Vec<LcsMatch>), Resp<'static>> {
+    let width = b.len() + 1;
+    let cells = (a.len() + 1)
+        .checked_mul(width)
+        .filter(|cells| {
+            cells
+                .checked_mul(std::mem::size_of::<u32>())
+                .is_some_and(|bytes| bytes <= max_bytes)
+        })
+        .ok_or_else(|| {
+            errors::error(
+                "ERR Insufficient memory, transient memory for LCS exceeds proto-max-bulk-len",
+            )
+        })?;
+    // Lengths fit in u32 cells, as the cap keeps both strings far shorter than u32::MAX
+    let mut table = vec![0u32; cells];
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            table[i * width + j] = if a[i - 1] == b[j - 1] {
+                table[(i - 1) * width + j - 1] + 1
+            } else {
+                table[(i - 1) * width + j].max(table[i * width + j - 1])
+            };
+        }
+    }
+
+    let mut subsequence = Vec::with_capacity(table[a.len() * width + b.len()] as usize);
+    let mut matches = vec![];
+    let mut current: Option<LcsMatch> = None;
+    let (mut i, mut j) = (a.len(), b.len());
+    while i > 0 && j > 0 {
+        if a[i - 1] == b[j - 1] {
+            subsequence.push(a[i - 1]);
+            match current.as_mut() {
+                // Runs are walked backwards, so a contiguous match extends the start
+                Some(run) if run.a.0 == i && run.b.0 == j => {
+                    run.a.0 -= 1;
+                    run.b.0 -= 1;
+                }
+                _ => {
+                    matches.extend(current.take());
+                    current = Some(LcsMatch {
+                        a: (i - 1, i - 1),
+                        b: (j - 1, j - 1),
+                    });
+                }
+            }
+            i -= 1;
+            j -= 1;
+        } else {
+            matches.extend(current.take());
+            if table[(i - 1) * width + j] > table[i * width + j - 1] {
+                i -= 1;
+            } else {
+                j -= 1;
+            }
+        }
+    }
+    matches.extend(current);
+    subsequence.reverse();
+    Ok((subsequence, matches))
 }
 
 impl From<Resp<'_>> for Value {
     fn from(resp: Resp<'_>) -> Self {
         match resp {
-            Resp::SimpleString(cow) => Self::Str(cow.into_owned()),
-            Resp::SimpleError(cow) => Self::Str(cow.into_owned()),
-            Resp::Integer(number) => Self::Str(number.to_string()),
-            Resp::BulkString(cow) => Self::Str(cow.into_owned()),
-            Resp::Array(resps) => Self::List(
-                resps
-                    .into_iter()
-                    .map(|resp| From::<Resp<'_>>::from(resp))
+            Resp::SimpleString(cow) => Self::Str(cow.into_owned(), false),
+            Resp::SimpleError(cow) => Self::Str(cow.into_owned(), false),
+            Resp::Integer(number) => Self::Str(number.to_string(), false),
+            Resp::BulkString(cow) | Resp::Verbatim(_, cow) => Self::Str(cow.into_owned(), false),
+            Resp::Null => Self::Str(String::new(), false),
+            Resp::Array(resps) | Resp::Push(resps) => {
+                Self::List(resps.into_iter().map(From::<Resp<'_>>::from).collect())
+            }
+            Resp::Map(map) => Self::List(
+                map.into_iter()
+                    .flat_map(|(k, v)| [k.into(), v.into()])
                     .collect(),
             ),
         }
@@ -49,6 +361,211 @@ impl From<Resp<'_>> for Value {
 
 impl From<RdbString> for Value {
     fn from(value: RdbString) -> Self {
-        Self::Str(value.0)
+        Self::Str(value.0, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    fn key(name: &str) -> Resp<'static> {
+        Resp::BulkString(name.to_string().into())
+    }
+
+    fn options(cursor: u64, count: usize) -> ScanOptions {
+        ScanOptions {
+            cursor,
+            pattern: None,
+            count,
+            value_type: None,
+        }
+    }
+
+    /// Scans until the cursor comes back as 0, calling `between` after every page.
+    fn scan_all(
+        db: &mut InnerDb,
+        options: ScanOptions,
+        mut between: impl FnMut(&mut InnerDb),
+    ) -> Vec<Resp<'static>> {
+        let mut options = options;
+        let mut keys = vec![];
+        loop {
+            let (cursor, page) = scan(db, &InnerExpiries::new(), &options);
+            keys.extend(page);
+            if cursor == 0 {
+                return keys;
+            }
+            between(db);
+            options.cursor = cursor;
+        }
+    }
+
+    #[test]
+    fn scan_filters_by_type() {
+        let mut db = InnerDb::new();
+        for i in 0..20 {
+            db.insert(
+                key(&format!("string:{i}")),
+                Value::Str(i.to_string(), false),
+            );
+            db.insert(
+                key(&format!("list:{i}")),
+                Value::List(vec![Value::Str(i.to_string(), false)]),
+            );
+            db.insert(
+                key(&format!("stream:{i}")),
+                Value::Stream(stream::Stream::new()),
+            );
+        }
+        let mut lists = options(0, 7);
+        lists.value_type = Some("LIST".to_string());
+
+        let keys = scan_all(&mut db, lists, |_| {});
+        let expected: HashSet<_> = (0..20).map(|i| key(&format!("list:{i}"))).collect();
+        assert_eq!(keys.len(), expected.len());
+        assert_eq!(keys.into_iter().collect::<HashSet<_>>(), expected);
+    }
+
+    #[test]
+    fn scan_returns_keys_present_throughout_despite_deletes_and_inserts() {
+        let mut db = InnerDb::new();
+        for i in 0..100 {
+            db.insert(key(&i.to_string()), Value::Str(i.to_string(), false));
+        }
+        let mut round = 0;
+        let keys: HashSet<_> = scan_all(&mut db, options(0, 10), |db| {
+            // Deleting even keys moves later keys around, new keys are appended
+            db.swap_remove(&key(&(round * 2).to_string()));
+            db.insert(
+                key(&format!("new:{round}")),
+                Value::Str(String::new(), false),
+            );
+            round += 1;
+        })
+        .into_iter()
+        .collect();
+
+        for i in (0..100).filter(|i| i % 2 == 1 || *i >= round * 2) {
+            assert!(keys.contains(&key(&i.to_string())), "{i} was not returned");
+        }
+    }
+
+    #[test]
+    fn scan_clamps_stale_cursors() {
+        let mut db = InnerDb::new();
+        db.insert(key("only"), Value::Str(String::new(), false));
+        assert_eq!(
+            scan(&db, &InnerExpiries::new(), &options(50, 10)),
+            (0, vec![key("only")])
+        );
+        assert_eq!(
+            scan(&db, &InnerExpiries::new(), &options(u64::MAX, 10)),
+            (0, vec![key("only")])
+        );
+    }
+
+    fn encoding_of(db: &InnerDb, name: &str) -> &'static str {
+        db.get(&key(name)).unwrap().encoding()
+    }
+
+    #[test]
+    fn string_encodings() {
+        let encoding = |value: &str| Value::Str(value.to_string(), false).encoding();
+        assert_eq!(encoding("12345"), "int");
+        assert_eq!(encoding("-12"), "int");
+        assert_eq!(encoding(&i64::MIN.to_string()), "int");
+        // Out of range or not formatted the way Redis would format it back
+        assert_eq!(encoding("9223372036854775808"), "embstr");
+        assert_eq!(encoding("012"), "embstr");
+        assert_eq!(encoding(""), "embstr");
+        assert_eq!(encoding(&"x".repeat(44)), "embstr");
+        assert_eq!(encoding(&"x".repeat(45)), "raw");
+    }
+
+    #[test]
+    fn append_makes_strings_raw() {
+        let mut db = InnerDb::new();
+        let mut expiries = InnerExpiries::new();
+        db.insert(key("n"), Value::Str("12".to_string(), false));
+        assert_eq!(encoding_of(&db, "n"), "int");
+        append(&mut db, &mut expiries, key("n"), "3", usize::MAX).unwrap();
+        assert_eq!(encoding_of(&db, "n"), "raw");
+
+        // A missing key is created from the appended value as is
+        append(&mut db, &mut expiries, key("new"), "42", usize::MAX).unwrap();
+        assert_eq!(encoding_of(&db, "new"), "int");
+        append(&mut db, &mut expiries, key("short"), "a", usize::MAX).unwrap();
+        assert_eq!(encoding_of(&db, "short"), "embstr");
+        append(&mut db, &mut expiries, key("short"), "b", usize::MAX).unwrap();
+        assert_eq!(encoding_of(&db, "short"), "raw");
+    }
+
+    #[test]
+    fn lcs_of_the_documented_example() {
+        let (a, b) = (b"ohmytext", b"mynewtext");
+        assert_eq!(lcs_len(a, b), 6);
+        let (subsequence, matches) = lcs(a, b, usize::MAX).unwrap();
+        assert_eq!(subsequence, b"mytext");
+        assert_eq!(
+            matches,
+            [
+                LcsMatch {
+                    a: (4, 7),
+                    b: (5, 8)
+                },
+                LcsMatch {
+                    a: (2, 3),
+                    b: (0, 1)
+                },
+            ]
+        );
+        assert_eq!(matches[0].len(), 4);
+    }
+
+    #[test]
+    fn lcs_table_is_capped() {
+        // 9 by 10 cells of 4 bytes
+        assert!(lcs(b"ohmytext", b"mynewtext", 360).is_ok());
+        assert!(lcs(b"ohmytext", b"mynewtext", 359).is_err());
+        assert!(lcs(b"", b"", 4).is_ok());
+    }
+
+    #[test]
+    fn append_is_capped() {
+        let mut db = InnerDb::new();
+        let mut expiries = InnerExpiries::new();
+        assert!(append(&mut db, &mut expiries, key("s"), "abcdef", 5).is_err());
+        assert!(db.is_empty());
+
+        assert_eq!(append(&mut db, &mut expiries, key("s"), "abc", 5), Ok(3));
+        assert_eq!(append(&mut db, &mut expiries, key("s"), "de", 5), Ok(5));
+        assert_eq!(
+            append(&mut db, &mut expiries, key("s"), "f", 5),
+            Err(errors::string_too_long())
+        );
+        assert_eq!(db.get(&key("s")).unwrap().encoding(), "raw");
+        assert_eq!(
+            format!("{:?}", db.get(&key("s"))),
+            format!("{:?}", Some(Value::Str("abcde".to_string(), true)))
+        );
+    }
+
+    #[test]
+    fn created_keys_drop_leftover_expiries() {
+        let mut db = InnerDb::new();
+        let mut expiries = InnerExpiries::new();
+        // What a replica is left with after dropping an expired key it was not told to delete
+        expiries.insert(key("counter"), 1);
+        expiries.insert(key("text"), 1);
+
+        assert_eq!(incr_by(&mut db, &mut expiries, key("counter"), 1), Ok(1));
+        assert_eq!(
+            append(&mut db, &mut expiries, key("text"), "a", usize::MAX),
+            Ok(1)
+        );
+        assert!(expiries.is_empty());
     }
 }
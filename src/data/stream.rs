@@ -1,7 +1,8 @@
-use std::{borrow::Cow, usize};
+use std::borrow::Cow;
 
 use crate::{data::Value, resp::Resp, utils::get_epoch_ms};
 use indexmap::IndexMap;
+use serde_json::json;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -22,7 +23,7 @@ pub enum StreamError {
     ShouldGenerateFullId,
 }
 
-#[derive(Debug, Clone, Copy, Hash)]
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub struct StreamId {
     pub milliseconds: usize, // Should store it either as u128 or u64
     pub sequence_number: usize,
@@ -48,29 +49,23 @@ impl StreamId {
     }
 }
 
-impl PartialEq for StreamId {
-    fn eq(&self, other: &Self) -> bool {
-        self.milliseconds == other.milliseconds && self.sequence_number == other.sequence_number
+impl std::fmt::Display for StreamId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.milliseconds, self.sequence_number)
     }
 }
 
-impl Eq for StreamId {}
-
 impl PartialOrd for StreamId {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        let milliseconds_cmp = self.milliseconds.cmp(&other.milliseconds);
-        let cmp = match milliseconds_cmp {
-            std::cmp::Ordering::Less | std::cmp::Ordering::Greater => milliseconds_cmp,
-            std::cmp::Ordering::Equal => self.sequence_number.cmp(&other.sequence_number),
-        };
-
-        Some(cmp)
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for StreamId {
-    fn cmp(&self, _other: &Self) -> std::cmp::Ordering {
-        todo!()
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.milliseconds
+            .cmp(&other.milliseconds)
+            .then(self.sequence_number.cmp(&other.sequence_number))
     }
 }
 
@@ -85,13 +80,11 @@ impl TryFrom<&Resp<'_>> for StreamId {
         let pair = resp.split_once('-');
         match pair {
             Some(pair) => match pair {
-                (milliseconds, "*") => {
-                    return Err(StreamError::ShouldGenerateSequenceNumber(
-                        milliseconds
-                            .parse()
-                            .map_err(|_| StreamError::MallformedStreamId)?,
-                    ))
-                }
+                (milliseconds, "*") => Err(StreamError::ShouldGenerateSequenceNumber(
+                    milliseconds
+                        .parse()
+                        .map_err(|_| StreamError::MallformedStreamId)?,
+                )),
                 (milliseconds, sequence_number) => {
                     let milliseconds: usize = milliseconds
                         .parse()
@@ -107,15 +100,18 @@ impl TryFrom<&Resp<'_>> for StreamId {
             },
             None => {
                 if resp == "*" {
-                    return Err(StreamError::ShouldGenerateFullId);
+                    Err(StreamError::ShouldGenerateFullId)
                 } else {
-                    return Err(StreamError::MallformedStreamId);
+                    Err(StreamError::MallformedStreamId)
                 }
             }
         }
     }
 }
 
+/// Default of the Redis `stream-node-max-entries` setting.
+const STREAM_NODE_MAX_ENTRIES: usize = 100;
+
 #[derive(Debug, Clone)]
 pub struct Stream {
     inner: IndexMap<StreamId, IndexMap<String, Value>>,
@@ -161,14 +157,9 @@ impl Stream {
                 StreamError::ShouldGenerateFullId => {
                     let milliseconds = get_epoch_ms();
                     let sequence_number = match self.inner.keys().last() {
-                        Some(key) => {
-                            if key.milliseconds == milliseconds {
-                                key.sequence_number + 1
-                            } else {
-                                0
-                            }
-                        }
-                        None => 0, // We can safely return zero here as milliseconds should never be equal to 0
+                        Some(key) if key.milliseconds == milliseconds => key.sequence_number + 1,
+                        // We can safely return zero here as milliseconds should never be equal to 0
+                        _ => 0,
                     };
 
                     StreamId {
@@ -246,4 +237,54 @@ impl Stream {
 
         Ok(Resp::Array(vec))
     }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn last_id(&self) -> Option<StreamId> {
+        self.inner.last().map(|(id, _)| *id)
+    }
+
+    /// Redis packs up to `stream-node-max-entries` entries into each radix tree node.
+    pub fn radix_tree_nodes(&self) -> usize {
+        self.inner.len().div_ceil(STREAM_NODE_MAX_ENTRIES)
+    }
+
+    /// Approximate bytes held by the entries, see `Value::approx_size`.
+    pub fn approx_size(&self) -> usize {
+        self.inner
+            .values()
+            .map(|fields| {
+                std::mem::size_of::<StreamId>()
+                    + fields
+                        .iter()
+                        .map(|(field, value)| field.capacity() + value.approx_size())
+                        .sum::<usize>()
+            })
+            .sum()
+    }
+
+    /// A JSON description with the first and last ids and the first `sample_size` entries.
+    pub fn describe(&self, sample_size: usize) -> serde_json::Value {
+        let entries: Vec<_> = self
+            .inner
+            .iter()
+            .take(sample_size)
+            .map(|(entry_id, fields)| {
+                let fields: serde_json::Map<_, _> = fields
+                    .iter()
+                    .map(|(field, value)| (field.clone(), value.sample()))
+                    .collect();
+                json!({ "id": entry_id.to_string(), "fields": fields })
+            })
+            .collect();
+        json!({
+            "type": "stream",
+            "size": self.inner.len(),
+            "first_id": self.inner.first().map(|(first, _)| first.to_string()),
+            "last_id": self.last_id().map(|last| last.to_string()),
+            "sample": entries,
+        })
+    }
 }
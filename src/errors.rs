@@ -0,0 +1,125 @@
+use std::{borrow::Cow, fmt::Write};
+
+use crate::{command::CommandError, resp::Resp};
+
+/// An error reply with an arbitrary message, which must start with an error prefix like `ERR`.
+pub fn error(message: impl Into<Cow<'static, str>>) -> Resp<'static> {
+    Resp::SimpleError(message.into())
+}
+
+pub fn syntax_error() -> Resp<'static> {
+    error("ERR syntax error")
+}
+
+pub fn protocol_error() -> Resp<'static> {
+    error("ERR Protocol error")
+}
+
+pub fn not_an_integer() -> Resp<'static> {
+    error("ERR value is not an integer or out of range")
+}
+
+pub fn not_a_float() -> Resp<'static> {
+    error("ERR value is not a valid float")
+}
+
+pub fn wrong_type() -> Resp<'static> {
+    error("WRONGTYPE Operation against a key holding the wrong kind of value")
+}
+
+pub fn string_too_long() -> Resp<'static> {
+    error("ERR string exceeds maximum allowed size")
+}
+
+pub fn unknown_subcommand(command: &str, subcommand: &str) -> Resp<'static> {
+    error(format!(
+        "ERR unknown subcommand '{subcommand}'. Try {} HELP.",
+        command.to_uppercase()
+    ))
+}
+
+pub fn invalid_expire_time(command: &str) -> Resp<'static> {
+    error(format!("ERR invalid expire time in '{command}' command"))
+}
+
+pub fn wrong_args(command: &str) -> Resp<'static> {
+    error(format!(
+        "ERR wrong number of arguments for '{}' command",
+        command.to_lowercase()
+    ))
+}
+
+/// Mirrors Redis, which echoes the arguments until roughly 128 bytes of them were written.
+pub fn unknown_command(name: &str, args: &[String]) -> Resp<'static> {
+    let mut message = String::from("ERR unknown command '");
+    message.extend(name.chars().take(128));
+    message.push_str("', with args beginning with: ");
+    let start = message.len();
+    for arg in args {
+        let written = message.len() - start;
+        if written >= 128 {
+            break;
+        }
+        let arg: String = arg.chars().take(128 - written).collect();
+        write!(message, "'{arg}' ").unwrap();
+    }
+    error(message)
+}
+
+pub fn no_auth() -> Resp<'static> {
+    error("NOAUTH Authentication required.")
+}
+
+pub fn wrong_pass() -> Resp<'static> {
+    error("WRONGPASS invalid username-password pair or user is disabled.")
+}
+
+pub fn no_proto() -> Resp<'static> {
+    error("NOPROTO sorry, this protocol version is not supported")
+}
+
+pub fn no_such_client() -> Resp<'static> {
+    error("ERR No such client")
+}
+
+pub fn max_clients() -> Resp<'static> {
+    error("ERR max number of clients reached")
+}
+
+pub fn protected_mode() -> Resp<'static> {
+    error(
+        "DENIED Redis is running in protected mode because protected mode is enabled and no \
+         password is set for the default user. In this mode connections are only accepted from \
+         the loopback interface. If you want to connect from external computers to Redis you \
+         may adopt one of the following solutions: 1) Just disable protected mode sending the \
+         command 'CONFIG SET protected-mode no' from the loopback interface by connecting to \
+         Redis from the same host the server is running, however MAKE SURE Redis is not \
+         publicly accessible from internet if you do so. Use CONFIG REWRITE to make this change \
+         permanent. 2) Alternatively you can just disable the protected mode by editing the \
+         Redis configuration file, and setting the protected mode option to 'no', and then \
+         restarting the server. 3) If you started the server manually just for testing, restart \
+         it with the '--protected-mode no' option. 4) Set up an authentication password for the \
+         default user. NOTE: You only need to do one of the above things in order for the server \
+         to start accepting connections from the outside.",
+    )
+}
+
+/// The reply for a command that could not be parsed.
+pub fn command_error(err: &CommandError) -> Resp<'static> {
+    match err {
+        CommandError::UnsupportedCommand(name, args) => unknown_command(name, args),
+        CommandError::WrongNumberOfArguments(command) => wrong_args(command),
+        CommandError::NotAnInteger => not_an_integer(),
+        CommandError::NotAFloat => not_a_float(),
+        CommandError::InvalidCursor => error("ERR invalid cursor"),
+        CommandError::UnknownSubcommand(command, subcommand) => {
+            unknown_subcommand(command, subcommand)
+        }
+        CommandError::InvalidExpireTime(command) => invalid_expire_time(command),
+        CommandError::NegativeTimeout => error("ERR timeout is negative"),
+        CommandError::LcsLenAndIdx => {
+            error("ERR If you want both the length and indexes, please just use IDX.")
+        }
+        CommandError::IncorrectFormat | CommandError::ProtocolError(_) => syntax_error(),
+    }
+}
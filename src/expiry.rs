@@ -0,0 +1,81 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+
+use tokio::sync::broadcast::Sender as BroadcastSender;
+
+use crate::{command::Command, resp::Resp, utils::get_epoch_ms, Db, Expiries};
+
+/// Whether a deadline in unix milliseconds has passed.
+pub fn is_due(expires_at: i64) -> bool {
+    expires_at <= get_epoch_ms() as i64
+}
+
+/// Deletes `key` if its deadline is still `expires_at` and has passed, and propagates the
+/// deletion to replicas as a `DEL`. Returns whether the key was deleted.
+///
+/// The check happens under the write locks, so a key overwritten or given another deadline
+/// since the caller looked at it is left alone.
+///
+/// Only masters expire keys: replicas keep them until the `DEL` arrives so both sides
+/// agree on the dataset regardless of their clocks.
+pub async fn expire(
+    key: &Resp<'static>,
+    expires_at: i64,
+    db: &Db,
+    expiries: &Expiries,
+    propagation_sender: &BroadcastSender<Command<'static>>,
+    replication_offset: &AtomicUsize,
+) -> bool {
+    let mut db = db.write().await;
+    let mut expiries = expiries.write().await;
+    if expiries.get(key) != Some(&expires_at) || !is_due(expires_at) {
+        return false;
+    }
+    expiries.remove(key);
+    db.swap_remove(key);
+
+    let del = Command::Del(vec![key.clone()]);
+    let resp: Resp<'_> = del.clone().into();
+    replication_offset.fetch_add(resp.len(), Ordering::Release);
+    let _ = propagation_sender.send(del);
+    true
+}
+
+/// Expires `key` at `expires_at` (unix milliseconds), unless it was overwritten or given
+/// another deadline in the meantime.
+///
+/// While `active_expire` is off the key is left for lazy expiration on access, the timer keeps
+/// checking the flag at the 10 Hz rate Redis runs its active expire cycle.
+pub fn spawn_expiration(
+    key: Resp<'static>,
+    expires_at: i64,
+    db: Db,
+    expiries: Expiries,
+    propagation_sender: BroadcastSender<Command<'static>>,
+    replication_offset: Arc<AtomicUsize>,
+    active_expire: Arc<AtomicBool>,
+) {
+    tokio::spawn(async move {
+        let expiring_at = SystemTime::UNIX_EPOCH + Duration::from_millis(expires_at as u64);
+        if let Ok(duration) = expiring_at.duration_since(SystemTime::now()) {
+            tokio::time::sleep(duration).await;
+        }
+        while !active_expire.load(Ordering::Relaxed) {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        expire(
+            &key,
+            expires_at,
+            &db,
+            &expiries,
+            &propagation_sender,
+            &replication_offset,
+        )
+        .await;
+    });
+}
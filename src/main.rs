@@ -1,8 +1,10 @@
 #![allow(unused_imports)]
 use clap::Parser;
+use indexmap::IndexMap;
 use std::{
     borrow::Cow,
     collections::HashMap,
+    fs::OpenOptions,
     future::Future,
     net::SocketAddrV4,
     process::Output,
@@ -12,9 +14,11 @@ use std::{
 use tokio::sync::RwLock;
 use tokio::{io::AsyncReadExt, net::TcpListener};
 use tokio::{io::AsyncWriteExt, net::TcpStream};
+use tracing::{info, warn};
 
 use crate::{
     command::Command,
+    config::Config,
     connection::{Connection, ConnectionError},
     data::Value,
     rdb::Rdb,
@@ -22,27 +26,75 @@ use crate::{
     server::Server,
 };
 
+mod acl;
+mod client;
 mod command;
 mod config;
 mod connection;
 mod data;
+mod errors;
+mod expiry;
+mod pubsub;
 mod rdb;
 mod replica;
 mod resp;
 mod server;
+mod slowlog;
+mod stats;
+mod tls;
+mod tracking;
 mod utils;
 
-pub type InnerDb = HashMap<Resp<'static>, Value>;
+/// Ordered by insertion so `SCAN` cursors can be positions, keys must be removed with
+/// `swap_remove`.
+pub type InnerDb = IndexMap<Resp<'static>, Value>;
 pub type InnerExpiries = HashMap<Resp<'static>, i64>;
 
 pub type Db = Arc<RwLock<InnerDb>>;
 pub type Expiries = Arc<RwLock<InnerExpiries>>;
 
-const REPLICATION_ID: &str = "8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb";
+/// The Redis version this server reports to clients.
+const VERSION: &str = "7.2.0";
 
 #[tokio::main]
 async fn main() {
-    let mut server = Server::new();
+    let config = Config::parse();
+    init_logging(&config);
+    info!(
+        "Redis version={VERSION}, bits={}, pid={}, just started",
+        usize::BITS,
+        std::process::id()
+    );
+    info!("Running mode=standalone, port={}.", config.port);
+    info!(
+        "Server initialized as {}",
+        if config.replicaof.is_some() {
+            "replica"
+        } else {
+            "master"
+        }
+    );
+
+    let mut server = Server::new(config);
     server.initialize().await;
     server.start().await;
 }
+
+/// Logs to `--logfile` when given, falling back to stdout if the file can't be opened.
+fn init_logging(config: &Config) {
+    let subscriber = tracing_subscriber::fmt().with_max_level(config.loglevel.filter());
+    let Some(path) = &config.logfile else {
+        subscriber.init();
+        return;
+    };
+    match OpenOptions::new().create(true).append(true).open(path) {
+        Ok(file) => subscriber
+            .with_ansi(false)
+            .with_writer(std::sync::Mutex::new(file))
+            .init(),
+        Err(err) => {
+            subscriber.init();
+            warn!("Can not open log file {path}: {err}, logging to stdout");
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::{borrow::Cow, collections::HashMap, sync::Mutex};
+
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{resp::Resp, utils::glob_match};
+
+/// Channels live in two separate namespaces: regular ones (`SUBSCRIBE`/`PUBLISH`) and
+/// sharded ones (`SSUBSCRIBE`/`SPUBLISH`). A standalone server has a single shard, so the
+/// only difference is the namespace and the frame types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChannelKind {
+    Global,
+    Shard,
+}
+
+impl ChannelKind {
+    pub fn subscribe_frame(&self) -> &'static str {
+        match self {
+            ChannelKind::Global => "subscribe",
+            ChannelKind::Shard => "ssubscribe",
+        }
+    }
+
+    pub fn unsubscribe_frame(&self) -> &'static str {
+        match self {
+            ChannelKind::Global => "unsubscribe",
+            ChannelKind::Shard => "sunsubscribe",
+        }
+    }
+
+    pub fn message_frame(&self) -> &'static str {
+        match self {
+            ChannelKind::Global => "message",
+            ChannelKind::Shard => "smessage",
+        }
+    }
+}
+
+type Subscribers = HashMap<u64, UnboundedSender<Resp<'static>>>;
+
+/// Registry of channel subscribers, keyed by client id.
+#[derive(Debug, Default)]
+pub struct PubSub {
+    channels: Mutex<HashMap<String, Subscribers>>,
+    shard_channels: Mutex<HashMap<String, Subscribers>>,
+}
+
+impl PubSub {
+    fn registry(&self, kind: ChannelKind) -> &Mutex<HashMap<String, Subscribers>> {
+        match kind {
+            ChannelKind::Global => &self.channels,
+            ChannelKind::Shard => &self.shard_channels,
+        }
+    }
+
+    pub fn subscribe(
+        &self,
+        kind: ChannelKind,
+        channel: &str,
+        id: u64,
+        sender: UnboundedSender<Resp<'static>>,
+    ) {
+        self.registry(kind)
+            .lock()
+            .unwrap()
+            .entry(channel.to_string())
+            .or_default()
+            .insert(id, sender);
+    }
+
+    pub fn unsubscribe(&self, kind: ChannelKind, channel: &str, id: u64) {
+        let mut registry = self.registry(kind).lock().unwrap();
+        if let Some(subscribers) = registry.get_mut(channel) {
+            subscribers.remove(&id);
+            if subscribers.is_empty() {
+                registry.remove(channel);
+            }
+        }
+    }
+
+    /// Sends `message` to every subscriber of `channel`, returning how many received it.
+    pub fn publish(&self, kind: ChannelKind, channel: &str, message: &str) -> usize {
+        let registry = self.registry(kind).lock().unwrap();
+        let Some(subscribers) = registry.get(channel) else {
+            return 0;
+        };
+        let frame = Resp::Array(vec![
+            Resp::bulk_string(kind.message_frame()),
+            Resp::BulkString(Cow::Owned(channel.to_string())),
+            Resp::BulkString(Cow::Owned(message.to_string())),
+        ]);
+        subscribers
+            .values()
+            .filter(|sender| sender.send(frame.clone()).is_ok())
+            .count()
+    }
+
+    /// Channels with at least one subscriber, optionally filtered by a glob pattern.
+    pub fn channels(&self, kind: ChannelKind, pattern: Option<&str>) -> Vec<String> {
+        let mut channels: Vec<_> = self
+            .registry(kind)
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|channel| {
+                pattern.is_none_or(|pattern| glob_match(pattern.as_bytes(), channel.as_bytes()))
+            })
+            .cloned()
+            .collect();
+        channels.sort();
+        channels
+    }
+
+    /// Where to send messages for client `id`, if it is subscribed to `channel`.
+    pub fn subscriber(
+        &self,
+        kind: ChannelKind,
+        channel: &str,
+        id: u64,
+    ) -> Option<UnboundedSender<Resp<'static>>> {
+        self.registry(kind)
+            .lock()
+            .unwrap()
+            .get(channel)?
+            .get(&id)
+            .cloned()
+    }
+
+    pub fn numsub(&self, kind: ChannelKind, channel: &str) -> usize {
+        self.registry(kind)
+            .lock()
+            .unwrap()
+            .get(channel)
+            .map_or(0, |subscribers| subscribers.len())
+    }
+}
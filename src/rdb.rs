@@ -1,6 +1,6 @@
 #![allow(dead_code, unused)]
 
-use crate::{config::Config, resp::RespError, InnerDb, InnerExpiries, Resp};
+use crate::{config::Config, data::Value, resp::RespError, InnerDb, InnerExpiries, Resp};
 use core::str;
 use std::{
     collections::HashMap,
@@ -10,6 +10,7 @@ use std::{
     sync::Arc,
 };
 
+use indexmap::IndexMap;
 use thiserror::Error;
 use tokio::{io::AsyncReadExt, sync::RwLock};
 
@@ -22,6 +23,9 @@ pub const REDIS_VER_VALUE: &str = "6.0.16";
 pub const START_DB_SECTION: u8 = 0xFE;
 pub const DB_SIZE_FLAG: u8 = 0xFB;
 pub const HAS_EXPIRY_FLAG: u8 = 0xFC;
+pub const STRING_TYPE: u8 = 0;
+pub const LIST_TYPE: u8 = 1;
+pub const EOF: u8 = 0xFF;
 pub const METADATA_LEN: usize = 18;
 const METADATA_OFFSET: usize = 9;
 
@@ -48,6 +52,12 @@ pub enum RdbError {
     #[error("Error while parsing data as RESP")]
     RespError(#[from] RespError),
 
+    #[error("Can not save values of type {0}")]
+    UnsupportedValue(&'static str),
+
+    #[error("Can not save expiry {0}, it is before the epoch")]
+    ExpiryOutOfRange(i64),
+
     #[error("Failed to open RDB file")]
     IOError(#[from] tokio::io::Error),
 }
@@ -55,54 +65,94 @@ pub enum RdbError {
 #[derive(Debug, Clone)]
 pub struct RdbString(pub String);
 
+/// Decodes a length prefix, returning `None` for the special `0b11` string encodings.
+fn parse_length(input: &[u8]) -> Option<(usize, &[u8])> {
+    let (first, rest) = input.split_first()?;
+    match first >> 6 {
+        0 => Some(((first & 0b00111111) as usize, rest)),
+        1 => {
+            let (second, rest) = rest.split_first()?;
+            Some((
+                u16::from_be_bytes([first & 0b00111111, *second]) as usize,
+                rest,
+            ))
+        }
+        2 if *first == 0x80 => {
+            let size = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?);
+            Some((size as usize, &rest[4..]))
+        }
+        2 if *first == 0x81 => {
+            let size = u64::from_be_bytes(rest.get(..8)?.try_into().ok()?);
+            Some((usize::try_from(size).ok()?, &rest[8..]))
+        }
+        _ => None,
+    }
+}
+
+fn encode_length(len: usize, buf: &mut Vec<u8>) {
+    if len < 1 << 6 {
+        buf.push(len as u8);
+    } else if len < 1 << 14 {
+        buf.extend((len as u16 | 0x4000).to_be_bytes());
+    } else if let Ok(len) = u32::try_from(len) {
+        buf.push(0x80);
+        buf.extend(len.to_be_bytes());
+    } else {
+        buf.push(0x81);
+        buf.extend((len as u64).to_be_bytes());
+    }
+}
+
+/// Number of bytes `encode_length` writes for `len`.
+fn length_size(len: usize) -> usize {
+    if len < 1 << 6 {
+        1
+    } else if len < 1 << 14 {
+        2
+    } else if u32::try_from(len).is_ok() {
+        5
+    } else {
+        9
+    }
+}
+
 impl RdbString {
     pub fn parse(input: &[u8]) -> Result<(Self, &[u8]), RdbError> {
-        let u8_case = input[1].to_string();
-        let u16_case = u16::from_le_bytes([input[1], input[2]]).to_string();
-        let u32_case = u32::from_le_bytes([input[1], input[2], input[3], input[4]]).to_string();
-        let (value, rest) = match input[0] >> 6 {
-            0 => (
-                str::from_utf8(&input[1..(input[0] & 0b00111111) as usize + 1]),
-                &input[(input[0] & 0b00111111) as usize + 1..],
+        if let Some((len, rest)) = parse_length(input) {
+            let value = rest.get(..len).ok_or(RdbError::RdbDatabaseParserError)?;
+            return Ok((Self(str::from_utf8(value)?.to_string()), &rest[len..]));
+        }
+        let int = |size: usize| {
+            input
+                .get(1..1 + size)
+                .ok_or(RdbError::RdbDatabaseParserError)
+        };
+        let (value, rest) = match input[0] {
+            0xC0 => (
+                i8::from_le_bytes(int(1)?.try_into().unwrap()) as i64,
+                &input[2..],
             ),
-            1 => (
-                str::from_utf8(
-                    (&input[1..u16::from_be_bytes([input[0] & 0b00111111, input[1]]) as usize]),
-                ),
-                &input[u16::from_be_bytes([input[0] & 0b00111111, input[1]]) as usize..],
+            0xC1 => (
+                i16::from_le_bytes(int(2)?.try_into().unwrap()) as i64,
+                &input[3..],
             ),
-            2 => (
-                str::from_utf8(
-                    &input
-                        [5..u32::from_be_bytes([input[1], input[2], input[3], input[4]]) as usize],
-                ),
-                &input[u32::from_be_bytes([input[1], input[2], input[3], input[4]]) as usize..],
+            0xC2 => (
+                i32::from_le_bytes(int(4)?.try_into().unwrap()) as i64,
+                &input[5..],
             ),
-            3 => match (input[0] & 0b00111111) {
-                0xC0 => (Ok(u8_case.as_str()), &input[1..]),
-                0xC1 => (Ok(u16_case.as_str()), &input[2..]),
-                0xC2 => (Ok(u32_case.as_str()), &input[4..]),
-                0xC3 => todo!(),
-                n => {
-                    return Err(RdbError::RdbMetadataParserError);
-                }
-            },
-            _ => unreachable!(),
+            // LZF compressed strings are not supported
+            _ => return Err(RdbError::RdbMetadataParserError),
         };
+        Ok((Self(value.to_string()), rest))
+    }
 
-        Ok((Self(value?.to_string()), rest))
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        encode_length(self.0.len(), buf);
+        buf.extend(self.0.as_bytes());
     }
 
     pub fn len(&self) -> usize {
-        if self.0.len() <= (u8::MAX << 6) as usize {
-            self.0.len() + 1
-        } else if self.0.len() <= (u16::MAX << 14) as usize {
-            self.0.len() + 2
-        } else if self.0.len() <= u32::MAX as usize {
-            self.0.len() + 5
-        } else {
-            0
-        }
+        length_size(self.0.len()) + self.0.len()
     }
 }
 
@@ -143,32 +193,55 @@ impl Rdb {
         }
         Err(RdbError::RdbConfigError)
     }
-    pub async fn encode_db(&self) -> Vec<u8> {
-        let mut buf = vec![START_DB_SECTION, 0, DB_SIZE_FLAG];
-        let kv_size = self.database.read().await.len();
-        let exp_size = self.expiries.read().await.len();
-        write!(buf, "{kv_size}{exp_size}");
-        for (key, value) in self.database.read().await.iter() {
-            buf.push(0); // flag: string, TODO: handle all types
-            buf.extend(key.clone().encode());
-            let resp: Resp<'_> = value.clone().try_into().unwrap();
-            buf.extend(resp.encode());
-            if let Some(expiry) = self.expiries.read().await.get(key) {
+    pub async fn encode(self) -> Result<Vec<u8>, RdbError> {
+        Self::dump(&*self.database.read().await, &*self.expiries.read().await)
+    }
+
+    /// Serializes a dataset into a complete RDB file. Strings and lists of strings are supported,
+    /// streams are not yet.
+    pub fn dump(db: &InnerDb, expiries: &InnerExpiries) -> Result<Vec<u8>, RdbError> {
+        let mut buf: Vec<u8> = RdbHeader::default().into();
+        buf.extend(Vec::<u8>::from(RdbMetadata::default()));
+        buf.extend([START_DB_SECTION, 0, DB_SIZE_FLAG]);
+        encode_length(db.len(), &mut buf);
+        encode_length(
+            expiries.keys().filter(|key| db.contains_key(*key)).count(),
+            &mut buf,
+        );
+        for (key, value) in db {
+            if let Some(&expiry) = expiries.get(key) {
+                let expiry =
+                    u64::try_from(expiry).map_err(|_| RdbError::ExpiryOutOfRange(expiry))?;
                 buf.push(HAS_EXPIRY_FLAG);
-                // TODO handle actual timestamps
-                buf.extend(1713824559637u64.to_le_bytes());
+                buf.extend(expiry.to_le_bytes());
+            }
+            let key = key
+                .expect_bulk_string()
+                .ok_or(RdbError::RdbDatabaseParserError)?;
+            match value {
+                Value::Str(value, _) => {
+                    buf.push(STRING_TYPE);
+                    RdbString(key.to_string()).encode(&mut buf);
+                    RdbString(value.clone()).encode(&mut buf);
+                }
+                Value::List(items) => {
+                    buf.push(LIST_TYPE);
+                    RdbString(key.to_string()).encode(&mut buf);
+                    encode_length(items.len(), &mut buf);
+                    for item in items {
+                        let Value::Str(item, _) = item else {
+                            return Err(RdbError::UnsupportedValue(item.value_type()));
+                        };
+                        RdbString(item.clone()).encode(&mut buf);
+                    }
+                }
+                Value::Stream(_) => return Err(RdbError::UnsupportedValue(value.value_type())),
             }
         }
-        buf
-    }
-
-    pub async fn encode(self) -> Vec<u8> {
-        let mut buf = vec![];
-        let mut db = self.encode_db().await;
-        buf.extend(Into::<Vec<u8>>::into(self.header));
-        buf.extend(Into::<Vec<u8>>::into(self.metadata));
-        buf.extend(db);
-        buf
+        buf.push(EOF);
+        // Checksum of zero, which disables its verification
+        buf.extend([0; 8]);
+        Ok(buf)
     }
 
     pub fn decode(input: &[u8]) -> Result<Self, RdbError> {
@@ -188,7 +261,7 @@ impl Rdb {
     }
 
     pub fn decode_db(input: &[u8]) -> Result<(Db, Expiries), RdbError> {
-        let mut db = HashMap::new();
+        let mut db = IndexMap::new();
         let mut expiries = HashMap::new();
 
         let (byte, mut rst) = input
@@ -199,8 +272,12 @@ impl Rdb {
         assert_eq!(*byte, 0);
         let (byte, rst) = rst.split_first().ok_or(RdbError::RdbDatabaseParserError)?;
         assert_eq!(*byte, DB_SIZE_FLAG);
-        let (db_size, rst) = rst.split_first().ok_or(RdbError::RdbDatabaseParserError)?;
-        let (expiry_size, mut rst) = rst.split_first().ok_or(RdbError::RdbDatabaseParserError)?;
+        let (db_size, rst) = parse_length(rst).ok_or(RdbError::RdbDatabaseParserError)?;
+        let (expiry_size, mut rst) = parse_length(rst).ok_or(RdbError::RdbDatabaseParserError)?;
+        // Sized up front so loading doesn't rehash over and over. Every key takes at least a
+        // few bytes, which bounds the sizes of a corrupt file.
+        db.reserve(db_size.min(rst.len()));
+        expiries.reserve(expiry_size.min(rst.len()));
 
         fn decode_inner<'input>(
             input: &'input [u8],
@@ -212,12 +289,15 @@ impl Rdb {
             let mut pair_type = 0;
             match type_value {
                 0xFC => {
-                    expiry = Some(u64::from_le_bytes(rest[..8].try_into().unwrap()) as i64);
+                    expiry = Some(
+                        i64::try_from(u64::from_le_bytes(rest[..8].try_into().unwrap())).ok()?,
+                    );
                     pair_type = rest[8];
                     rest = &rest[9..];
                 }
                 0xFD => {
-                    expiry = Some(u32::from_le_bytes(rest[..4].try_into().unwrap()) as i64);
+                    // Expiries are kept in milliseconds, this opcode stores seconds
+                    expiry = Some(u32::from_le_bytes(rest[..4].try_into().unwrap()) as i64 * 1000);
                     pair_type = rest[4];
                     rest = &rest[5..];
                 }
@@ -227,15 +307,31 @@ impl Rdb {
                 }
             }
             let (key, rest) = RdbString::parse(rest).ok()?;
-            let (value, rest) = RdbString::parse(rest).ok()?; // TODO: parse value based on type
-            db.insert(key.clone().into(), value.into());
+            let (value, rest) = match pair_type {
+                STRING_TYPE => {
+                    let (value, rest) = RdbString::parse(rest).ok()?;
+                    (value.into(), rest)
+                }
+                LIST_TYPE => {
+                    let (len, mut rest) = parse_length(rest)?;
+                    let mut items = Vec::with_capacity(len.min(rest.len()));
+                    for _ in 0..len {
+                        let (item, next) = RdbString::parse(rest).ok()?;
+                        items.push(item.into());
+                        rest = next;
+                    }
+                    (Value::List(items), rest)
+                }
+                _ => return None,
+            };
+            db.insert(key.clone().into(), value);
             if let Some(expiry) = expiry {
                 expiries.insert(key.into(), expiry);
             }
             Some(rest)
         }
 
-        for i in 0..*db_size {
+        for i in 0..db_size {
             rst = decode_inner(rst, &mut db, &mut expiries)
                 .ok_or(RdbError::RdbDatabaseParserError)?;
         }
@@ -304,7 +400,7 @@ impl TryFrom<&[u8]> for RdbMetadata {
         input = &input[1..];
         let mut attributes = vec![];
         while input[0] != SELECTDB {
-            if let Ok((value, rest)) = RdbString::parse(&input) {
+            if let Ok((value, rest)) = RdbString::parse(input) {
                 attributes.push(value);
                 input = rest;
             } else {
@@ -374,3 +470,87 @@ impl TryFrom<&[u8]> for MetadataAttribute {
         // Ok(Self { name, value })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::data::stream::Stream;
+
+    use super::*;
+
+    fn key(name: &str) -> Resp<'static> {
+        Resp::BulkString(name.to_string().into())
+    }
+
+    fn string(value: &str) -> Value {
+        Value::Str(value.to_string(), false)
+    }
+
+    #[tokio::test]
+    async fn dump_round_trips_strings_lists_and_expiries() {
+        let mut db = InnerDb::new();
+        let mut expiries = InnerExpiries::new();
+        db.insert(key("name"), string("redis"));
+        db.insert(key("counter"), string("12"));
+        db.insert(key("empty"), string(""));
+        db.insert(key("long"), string(&"x".repeat(20_000)));
+        db.insert(
+            key("list"),
+            Value::List(vec![string("a"), string(""), string("c")]),
+        );
+        expiries.insert(key("name"), 1_900_000_000_000);
+        expiries.insert(key("list"), 1);
+
+        let rdb = Rdb::decode(&Rdb::dump(&db, &expiries).unwrap()).unwrap();
+        let loaded = rdb.database.read().await;
+        assert_eq!(loaded.len(), db.len());
+        for (key, value) in &db {
+            assert_eq!(
+                format!("{:?}", loaded.get(key)),
+                format!("{:?}", Some(value)),
+                "{key:?}"
+            );
+        }
+        assert_eq!(*rdb.expiries.read().await, expiries);
+    }
+
+    #[test]
+    fn dump_rejects_streams() {
+        let mut db = InnerDb::new();
+        db.insert(key("events"), Value::Stream(Stream::new()));
+        assert!(matches!(
+            Rdb::dump(&db, &InnerExpiries::new()),
+            Err(RdbError::UnsupportedValue("stream"))
+        ));
+    }
+
+    #[test]
+    fn dump_rejects_expiries_before_the_epoch() {
+        let mut db = InnerDb::new();
+        let mut expiries = InnerExpiries::new();
+        db.insert(key("name"), string("redis"));
+        expiries.insert(key("name"), -1);
+        assert!(matches!(
+            Rdb::dump(&db, &expiries),
+            Err(RdbError::ExpiryOutOfRange(-1))
+        ));
+    }
+
+    #[test]
+    fn lengths_round_trip() {
+        let lengths = [
+            0,
+            63,
+            64,
+            16383,
+            16384,
+            u32::MAX as usize,
+            u32::MAX as usize + 1,
+        ];
+        for len in lengths {
+            let mut buf = vec![];
+            encode_length(len, &mut buf);
+            assert_eq!(buf.len(), length_size(len), "{len}");
+            assert_eq!(parse_length(&buf), Some((len, &[][..])), "{len}");
+        }
+    }
+}
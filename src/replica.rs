@@ -1,22 +1,33 @@
 use std::{
     borrow::Cow,
+    collections::HashMap,
     net::{SocketAddr, ToSocketAddrs},
     pin::Pin,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
     task::{Context, Poll},
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tokio::io::AsyncReadExt;
 use tokio::io::AsyncWriteExt;
+use tracing::warn;
 
 use tokio::{
     io::{self, AsyncRead, AsyncWrite},
     net::TcpStream,
+    sync::{broadcast::Sender as BroadcastSender, Notify, RwLock},
 };
 
 use crate::{
-    command::Command, config::Config, connection::ConnectionError, rdb::Rdb, resp::Resp, Db,
-    Expiries,
+    command::{Command, SetExpiry},
+    config::Config,
+    connection::ConnectionError,
+    data::{append, incr_by, push, Value},
+    rdb::Rdb,
+    resp::{Resp, RespDecoder},
+    utils, Db, Expiries,
 };
 
 #[derive(Debug)]
@@ -25,10 +36,106 @@ pub struct Replica {
     db: Db,
     expiries: Expiries,
     config: Arc<Config>,
-    bytes_processed: usize,
+    /// Shared with the server, so `INFO` can report how far this replica got
+    bytes_processed: Arc<AtomicUsize>,
+    master_link: Arc<Mutex<MasterLinkStatus>>,
     buffer: Vec<u8>,
 }
 
+/// A replica's view of its link to the master, shared with connections for `INFO`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MasterLinkStatus {
+    /// The initial sync completed and the master is streaming commands
+    pub up: bool,
+    pub sync_in_progress: bool,
+    /// When anything was last read from the master
+    pub last_io: Option<Instant>,
+}
+
+/// Where a replica is in its synchronization, named like the `state` field of `INFO`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplicaLinkState {
+    /// The RDB is being sent, the replica hasn't processed the command stream yet
+    SendBulk,
+    /// The replica acknowledged an offset, so it loaded the RDB
+    Online,
+}
+
+impl std::fmt::Display for ReplicaLinkState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplicaLinkState::SendBulk => write!(f, "send_bulk"),
+            ReplicaLinkState::Online => write!(f, "online"),
+        }
+    }
+}
+
+/// What the master knows about one of its replicas.
+#[derive(Debug, Clone, Copy)]
+pub struct ReplicaState {
+    pub state: ReplicaLinkState,
+    /// Port the replica listens on, as announced with `REPLCONF listening-port`
+    pub port: u16,
+    /// Last offset the replica acknowledged
+    pub offset: usize,
+    pub last_ack: Instant,
+}
+
+/// Number of online replicas that acknowledged at least `offset`.
+pub async fn synchronized_replicas(
+    replicas: &RwLock<HashMap<SocketAddr, ReplicaState>>,
+    offset: usize,
+) -> usize {
+    replicas
+        .read()
+        .await
+        .values()
+        .filter(|replica| replica.state == ReplicaLinkState::Online && replica.offset >= offset)
+        .count()
+}
+
+/// Waits until `numreplicas` replicas acknowledged `offset`, asking them for an ACK first if
+/// there aren't enough yet. Gives up after `timeout`, or never without one, and returns how
+/// many replicas are in sync.
+pub async fn wait_for_replicas(
+    replicas: &RwLock<HashMap<SocketAddr, ReplicaState>>,
+    replica_ack: &Notify,
+    propagation_sender: &BroadcastSender<Command<'static>>,
+    offset: usize,
+    numreplicas: usize,
+    timeout: Option<Duration>,
+) -> usize {
+    let mut synchronized = synchronized_replicas(replicas, offset).await;
+    if synchronized >= numreplicas {
+        return synchronized;
+    }
+    let _ = propagation_sender.send(Command::ReplConf(
+        Resp::bulk_string("GETACK"),
+        Resp::bulk_string("*"),
+    ));
+
+    let wait = async {
+        loop {
+            // Register for the next ACK before counting so it can't be missed
+            let acked = replica_ack.notified();
+            tokio::pin!(acked);
+            acked.as_mut().enable();
+            synchronized = synchronized_replicas(replicas, offset).await;
+            if synchronized >= numreplicas {
+                break;
+            }
+            acked.await;
+        }
+    };
+    match timeout {
+        Some(timeout) => {
+            let _ = tokio::time::timeout(timeout, wait).await;
+        }
+        None => wait.await,
+    }
+    synchronized
+}
+
 impl Replica {
     pub fn new(
         addr: String,
@@ -36,6 +143,8 @@ impl Replica {
         db: Db,
         expiries: Expiries,
         config: Arc<Config>,
+        bytes_processed: Arc<AtomicUsize>,
+        master_link: Arc<Mutex<MasterLinkStatus>>,
     ) -> Self {
         let addr: SocketAddr = format!(
             "{}:{}",
@@ -55,12 +164,15 @@ impl Replica {
             db,
             expiries,
             config,
-            bytes_processed: 0,
+            bytes_processed,
+            master_link,
             buffer: Vec::with_capacity(4096),
         }
     }
     pub async fn start(&mut self) -> Result<(), ConnectionError> {
         let mut client = TcpStream::connect(self.addr).await.unwrap();
+        utils::configure_socket(&client, self.config.tcp_keepalive)?;
+        self.update_link(|link| link.sync_in_progress = true);
         let ping: Resp<'_> = Command::Ping.into();
         let _ = client.write_all(&ping.encode()).await;
         let mut buf = Vec::with_capacity(4096);
@@ -98,54 +210,48 @@ impl Replica {
             .unwrap();
         rest = &rest[rdb_length + rdb_length.ilog10() as usize + 4..];
         self.buffer.extend_from_slice(rest);
+        self.update_link(|link| {
+            link.up = true;
+            link.sync_in_progress = false;
+        });
 
         let _ = self.handle(client).await;
+        self.master_link.lock().unwrap().up = false;
 
         Ok(())
     }
 
+    /// Applies `update` to the link status, recording that the master was just heard from.
+    fn update_link(&self, update: impl FnOnce(&mut MasterLinkStatus)) {
+        let mut link = self.master_link.lock().unwrap();
+        link.last_io = Some(Instant::now());
+        update(&mut link);
+    }
+
     pub async fn handle(&mut self, mut tcp: TcpStream) -> Result<(), ConnectionError> {
-        let mut buf = self.buffer.clone();
+        let mut decoder = RespDecoder::default();
+        decoder.extend(&self.buffer);
 
-        let mut failed = false;
-        'main: loop {
-            if buf.is_empty() || failed {
-                let n = tcp.read_buf(&mut buf).await?;
+        loop {
+            let Some(frame) = decoder.next_frame()? else {
+                let n = tcp.read_buf(decoder.buffer_mut()).await?;
                 if n == 0 {
                     break;
                 }
-            }
-            let mut rest = buf.as_slice();
-            let mut consumed = 0;
-            while !rest.is_empty() {
-                match Command::parse(rest) {
-                    Ok((c, new_rest)) => {
-                        let should_account = c.should_account();
-                        // let is_write_command = c.is_write_command();
-                        // if is_write_command {
-                        //     let ack: Resp<'_> = Command::ReplConf(
-                        //         Resp::bulk_string("ACK"),
-                        //         Resp::BulkString(Cow::Owned(self.bytes_processed.to_string())),
-                        //     )
-                        //     .into();
-                        //     let _ = tcp.write_all(&ack.encode()).await;
-                        // }
-                        self.handle_command(c, &mut tcp).await?;
-                        if should_account {
-                            self.bytes_processed += rest.len() - new_rest.len();
-                        }
-                        consumed += rest.len() - new_rest.len();
-                        rest = new_rest;
-                        failed = false;
-                    }
-                    Err(err) => {
-                        eprintln!("err: {}", err);
-                        failed = true;
-                        continue 'main;
+                self.update_link(|_| {});
+                continue;
+            };
+            match Command::parse(&frame) {
+                Ok((c, _)) => {
+                    let should_account = c.should_account();
+                    self.handle_command(c, &mut tcp).await?;
+                    if should_account {
+                        self.bytes_processed
+                            .fetch_add(frame.len(), Ordering::Release);
                     }
                 }
+                Err(err) => warn!("Can not parse command from master: {err}"),
             }
-            buf.drain(..consumed);
         }
 
         Ok(())
@@ -158,39 +264,78 @@ impl Replica {
     ) -> Result<(), ConnectionError> {
         match &command {
             Command::Set(key, value, expiry) => {
+                let key = key.clone().into_owned();
+                self.db
+                    .write()
+                    .await
+                    .insert(key.clone(), value.clone().into_owned().into());
+                // The key is only deleted once the master propagates a DEL
+                match expiry.as_ref().and_then(SetExpiry::expires_at) {
+                    Some(expires_at) => {
+                        self.expiries.write().await.insert(key, expires_at);
+                    }
+                    None => {
+                        self.expiries.write().await.remove(&key);
+                    }
+                }
+            }
+            Command::GetSet(key, value) => {
+                let key = key.clone().into_owned();
                 self.db
                     .write()
                     .await
-                    .insert(key.clone().into_owned(), value.clone().into_owned().into());
-                if let Some(expiry) = expiry {
-                    let expiry = *expiry;
-                    let db = self.db.clone();
-                    self.expiries
-                        .write()
-                        .await
-                        .insert(key.clone().into_owned(), expiry);
+                    .insert(key.clone(), value.clone().into_owned().into());
+                self.expiries.write().await.remove(&key);
+            }
+            Command::Del(keys) => {
+                let mut db = self.db.write().await;
+                let mut expiries = self.expiries.write().await;
+                for key in keys {
                     let key = key.clone().into_owned();
-                    let expiries = self.expiries.clone();
-                    tokio::spawn(async move {
-                        tokio::time::sleep(Duration::from_millis(expiry as u64)).await;
-                        db.write().await.remove(&key);
-                        expiries.write().await.remove(&key);
-                    });
+                    db.swap_remove(&key);
+                    expiries.remove(&key);
                 }
             }
-            Command::ReplConf(key, _value) => match key {
-                Resp::BulkString(cow) => {
-                    if cow.to_string().as_str() == "GETACK" {
-                        let resp: Resp<'_> = Command::ReplConf(
-                            Resp::bulk_string("ACK"),
-                            Resp::BulkString(Cow::Owned(self.bytes_processed.to_string())),
-                        )
-                        .into();
-                        tcp.write_all(&resp.encode()).await?;
-                    }
+            Command::Append(key, value) => {
+                if let Some(suffix) = value.expect_bulk_string() {
+                    let _ = append(
+                        &mut *self.db.write().await,
+                        &mut *self.expiries.write().await,
+                        key.clone().into_owned(),
+                        suffix,
+                        self.config.proto_max_bulk_len,
+                    );
                 }
-                _ => {}
-            },
+            }
+            Command::Incr(key)
+            | Command::Decr(key)
+            | Command::IncrBy(key, _)
+            | Command::DecrBy(key, _) => {
+                if let Some(delta) = command.increment() {
+                    let _ = incr_by(
+                        &mut *self.db.write().await,
+                        &mut *self.expiries.write().await,
+                        key.clone().into_owned(),
+                        delta,
+                    );
+                }
+            }
+            Command::LPushX(key, elements) | Command::RPushX(key, elements) => {
+                let key = key.clone().into_owned();
+                if let Some(Value::List(list)) = self.db.write().await.get_mut(&key) {
+                    push(list, elements, matches!(command, Command::LPushX(_, _)));
+                }
+            }
+            Command::ReplConf(Resp::BulkString(cow), _value) if cow == "GETACK" => {
+                let resp: Resp<'_> = Command::ReplConf(
+                    Resp::bulk_string("ACK"),
+                    Resp::BulkString(Cow::Owned(
+                        self.bytes_processed.load(Ordering::Acquire).to_string(),
+                    )),
+                )
+                .into();
+                tcp.write_all(&resp.encode()).await?;
+            }
             _ => {
                 return Ok(());
                 // As a replica we should not ever receive read commands
@@ -3,20 +3,23 @@ use std::str::{self, from_utf8, Utf8Error};
 use std::{borrow::Cow, io::Write};
 use thiserror::Error;
 
-use crate::command::Command;
+use crate::command::{
+    AclSubcommand, ClientKillFilter, ClientSubcommand, ClusterSubcommand, Command,
+    CommandSubcommand, DebugSubcommand, MemorySubcommand, ObjectSubcommand, PubSubSubcommand,
+    SlowLogSubcommand,
+};
 use crate::config;
 use crate::data::stream::StreamId;
 use crate::data::Value;
+use crate::pubsub::ChannelKind;
 use crate::rdb::RdbString;
 
-pub fn num_digits(mut n: i64) -> usize {
-    if n == 0 {
-        return 1;
-    }
-    if n < 0 {
-        n = -n;
-    }
-    (n.ilog10() + 1) as usize
+/// Number of decimal digits in `n`, not counting the sign.
+pub fn num_digits(n: i64) -> usize {
+    // `unsigned_abs` since negating `i64::MIN` overflows
+    n.unsigned_abs()
+        .checked_ilog10()
+        .map_or(1, |digits| digits as usize + 1)
 }
 
 pub const CTRLF: &[u8] = b"\r\n";
@@ -30,7 +33,15 @@ where
     SimpleError(Cow<'r, S>),
     Integer(i64),
     BulkString(Cow<'r, S>),
+    /// The null bulk string `$-1\r\n`, e.g. the reply for a missing key
+    Null,
     Array(Vec<Resp<'r, S>>),
+    /// RESP3 only, must not be sent to clients speaking RESP2
+    Map(Vec<(Resp<'r, S>, Resp<'r, S>)>),
+    /// RESP3 only, a string tagged with a three character format such as `txt`
+    Verbatim(&'static str, Cow<'r, S>),
+    /// RESP3 only, out-of-band data such as client-side caching invalidations
+    Push(Vec<Resp<'r, S>>),
 }
 
 #[derive(Debug, Error)]
@@ -44,16 +55,56 @@ pub enum RespError {
     #[error("Can not parse data as integer")]
     NotAnInteger(#[from] std::num::ParseIntError),
 
-    #[error("Data is not terminated with CTRLF")]
-    NoCtrlf,
+    #[error("Data is not terminated with CTRLF at byte {0}: {1}")]
+    NoCtrlf(usize, String),
 
-    #[error("There is no enough parts for the provided type")]
-    NotEnoughtParts,
+    #[error("There is no enough parts for the provided type at byte {0}: {1}")]
+    NotEnoughtParts(usize, String),
 
     #[error("Data type {0} can not be serialized with RESP")]
     DataTypeIsNotSupported(String),
 }
 
+impl RespError {
+    fn no_ctrlf(input: &[u8], offset: usize) -> Self {
+        Self::NoCtrlf(offset, snippet(input, offset))
+    }
+
+    fn not_enough_parts(input: &[u8], offset: usize) -> Self {
+        Self::NotEnoughtParts(offset, snippet(input, offset))
+    }
+
+    /// Moves the position of an error raised inside a nested value so it points into the
+    /// enclosing input, which starts `by` bytes earlier.
+    fn shifted(self, by: usize) -> Self {
+        match self {
+            Self::NoCtrlf(offset, snippet) => Self::NoCtrlf(offset + by, snippet),
+            Self::NotEnoughtParts(offset, snippet) => Self::NotEnoughtParts(offset + by, snippet),
+            err => err,
+        }
+    }
+}
+
+/// The header line of the frame at the start of `input` without its type byte, and the bytes
+/// after its CRLF.
+fn split_line(input: &[u8]) -> Result<(&str, &[u8]), RespError> {
+    let Some(end) = input.windows(2).position(|w| w == CTRLF) else {
+        return Err(RespError::no_ctrlf(input, input.len()));
+    };
+    Ok((from_utf8(&input[1..end])?, &input[end + CTRLF.len()..]))
+}
+
+/// Hex dump of up to 16 bytes of `input` around `offset`, for parse error diagnostics.
+fn snippet(input: &[u8], offset: usize) -> String {
+    let start = offset.saturating_sub(8).min(input.len());
+    let end = (offset + 8).min(input.len());
+    input[start..end]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 impl<'input, S> Resp<'input, S>
 where
     S: ToOwned<Owned = String> + ?Sized + 'input,
@@ -64,7 +115,15 @@ where
             Resp::SimpleError(e) => Resp::SimpleError(Cow::Owned(e.into_owned())),
             Resp::Integer(i) => Resp::Integer(i),
             Resp::BulkString(bs) => Resp::BulkString(Cow::Owned(bs.into_owned())),
+            Resp::Null => Resp::Null,
             Resp::Array(array) => Resp::Array(array.into_iter().map(|i| i.into_owned()).collect()),
+            Resp::Map(map) => Resp::Map(
+                map.into_iter()
+                    .map(|(k, v)| (k.into_owned(), v.into_owned()))
+                    .collect(),
+            ),
+            Resp::Verbatim(format, s) => Resp::Verbatim(format, Cow::Owned(s.into_owned())),
+            Resp::Push(push) => Resp::Push(push.into_iter().map(|i| i.into_owned()).collect()),
         }
     }
 }
@@ -72,87 +131,58 @@ where
 impl<'r> Resp<'r> {
     pub fn parse_inner<'i: 'r>(input: &'i [u8]) -> Result<(Self, &'i [u8]), RespError> {
         use Resp::*;
-        use RespError::*;
         let len = input.len();
-        let resp_value = match input[0] {
+        let Some(&kind) = input.first() else {
+            return Err(RespError::not_enough_parts(input, 0));
+        };
+        match kind {
             b'+' => {
-                if let Some(end) = input.iter().position(|b| *b == b'\r') {
-                    return Ok((
-                        SimpleString(Cow::Borrowed(from_utf8(
-                            input.get(1..end).ok_or(NotEnoughtParts)?,
-                        )?)),
-                        &input[end + 2..],
-                    ));
-                } else {
-                    Err(todo!())
-                }
+                let (line, rest) = split_line(input)?;
+                Ok((SimpleString(Cow::Borrowed(line)), rest))
+            }
+            b'-' => {
+                let (line, rest) = split_line(input)?;
+                Ok((SimpleError(Cow::Borrowed(line)), rest))
+            }
+            b':' => {
+                let (line, rest) = split_line(input)?;
+                Ok((Integer(line.parse()?), rest))
             }
-            b'-' => Ok(SimpleError(Cow::Borrowed(from_utf8(
-                input.get(1..len - 2).ok_or(NotEnoughtParts)?,
-            )?))),
-            b':' => Ok(Integer(
-                from_utf8(
-                    input
-                        .get(1..input.iter().position(|b| *b == b'\r').unwrap())
-                        .ok_or(NotEnoughtParts)?,
-                )?
-                .parse::<i64>()?,
-            )),
             b'$' => {
-                let mut parts = &mut input
-                    .get(1..)
-                    .ok_or(NotEnoughtParts)?
-                    .split(|b| b == &0xA)
-                    .map(|line| line.strip_suffix(&[0xD]).unwrap_or(line));
-                let mut length =
-                    from_utf8(parts.next().ok_or(NotEnoughtParts)?)?.parse::<isize>()?;
-                if length == -1 {
-                    length = 0;
-                }
-                let string = from_utf8(parts.next().ok_or(NotEnoughtParts)?)?;
-                // assert_eq!(string.len(), length as usize);
-                if string.len() < length as usize
-                    || (6 + length as usize + length.ilog10() as usize) > input.len()
-                {
-                    return Err(RespError::NotEnoughtParts);
-                }
-                return Ok((
+                let (length, body) = split_line(input)?;
+                let length = length.parse::<isize>()?;
+                if length < 0 {
+                    return Ok((Null, body));
+                }
+                let start = len - body.len();
+                let end = start + length as usize;
+                let string = from_utf8(
+                    input
+                        .get(start..end)
+                        .ok_or_else(|| RespError::not_enough_parts(input, len))?,
+                )?;
+                if input.get(end..end + CTRLF.len()) != Some(CTRLF) {
+                    return Err(RespError::no_ctrlf(input, end));
+                }
+                Ok((
                     BulkString(Cow::Borrowed(string)),
-                    &input[6 + length as usize + length.ilog10() as usize..],
-                ));
+                    &input[end + CTRLF.len()..],
+                ))
             }
             b'*' => {
-                let Some(position) = input.iter().position(|b| b == &0xA) else {
-                    return Err(NotEnoughtParts);
-                };
-                let (length_string, mut rest) = input.split_at(position + 1);
-                let length = from_utf8(
-                    length_string
-                        .get(1..length_string.len() - 2)
-                        .ok_or(NotEnoughtParts)?,
-                )?
-                .parse::<isize>()?;
+                let (length, mut rest) = split_line(input)?;
+                let length = length.parse::<isize>()?;
                 let mut array = vec![];
-                for i in 0..length {
-                    let (value, new_rest) = Self::parse_inner(rest)?;
+                for _ in 0..length {
+                    let (value, new_rest) =
+                        Self::parse_inner(rest).map_err(|err| err.shifted(len - rest.len()))?;
                     array.push(value);
                     rest = new_rest;
                 }
-                return Ok((Self::Array(array), rest));
+                Ok((Array(array), rest))
             }
-            c => Err(UnsuportedType(c as char)),
-        };
-
-        let (_, mut rest) = input.split_at(input.iter().position(|b| b == &0xA).unwrap() + 1);
-        if matches!(resp_value, Ok(BulkString(ref s))  if !s.is_empty()) {
-            rest = rest
-                .split_at(rest.iter().position(|b| b == &0xA).unwrap() + 1)
-                .1;
-        } else if matches!(resp_value, Ok(Array(_))) {
-            rest = b"";
+            c => Err(RespError::UnsuportedType(c as char)),
         }
-
-        resp_value.map(|r| (r, rest))
     }
 
     pub fn len(&self) -> usize {
@@ -163,16 +193,32 @@ impl<'r> Resp<'r> {
             Resp::BulkString(s) => {
                 1 + num_digits(s.len() as i64) + CTRLF.len() + s.len() + CTRLF.len()
             }
-            Resp::Array(vec) => {
+            Resp::Null => "$-1".len() + CTRLF.len(),
+            Resp::Array(vec) | Resp::Push(vec) => {
                 1 + num_digits(vec.len() as i64)
                     + CTRLF.len()
                     + vec.iter().map(|i| i.len()).sum::<usize>()
             }
+            Resp::Map(map) => {
+                1 + num_digits(map.len() as i64)
+                    + CTRLF.len()
+                    + map.iter().map(|(k, v)| k.len() + v.len()).sum::<usize>()
+            }
+            Resp::Verbatim(format, s) => {
+                let length = format.len() + 1 + s.len();
+                1 + num_digits(length as i64) + CTRLF.len() + length + CTRLF.len()
+            }
         }
     }
 
     pub fn encode(&self) -> Vec<u8> {
-        let mut buf = vec![];
+        let mut buf = Vec::with_capacity(self.len());
+        self.encode_into(&mut buf);
+        buf
+    }
+
+    /// Appends the encoded value to `buf`, so callers can reuse one buffer across replies.
+    pub fn encode_into(&self, buf: &mut Vec<u8>) {
         match self {
             Resp::SimpleString(s) => {
                 buf.push(b'+');
@@ -186,28 +232,48 @@ impl<'r> Resp<'r> {
             }
             Resp::Integer(i) => {
                 buf.push(b':');
-                buf.extend(format!("{i}").as_bytes());
+                write!(buf, "{i}");
                 buf.extend(CTRLF);
             }
             Resp::BulkString(b) => {
                 buf.push(b'$');
-                write!(buf, "{}", if !b.is_empty() { b.len() as isize } else { -1 });
+                write!(buf, "{}", b.len());
                 buf.extend(CTRLF);
                 buf.extend(b.as_bytes());
-                if !b.is_empty() {
-                    buf.extend(CTRLF);
-                }
+                buf.extend(CTRLF);
             }
-            Resp::Array(vec) => {
-                buf.push(b'*');
+            Resp::Null => buf.extend(b"$-1\r\n"),
+            Resp::Array(vec) | Resp::Push(vec) => {
+                buf.push(if matches!(self, Resp::Push(_)) {
+                    b'>'
+                } else {
+                    b'*'
+                });
                 write!(buf, "{}", vec.len());
                 buf.extend(CTRLF);
                 for i in vec {
-                    buf.extend(i.encode());
+                    i.encode_into(buf);
+                }
+            }
+            Resp::Map(map) => {
+                buf.push(b'%');
+                write!(buf, "{}", map.len());
+                buf.extend(CTRLF);
+                for (k, v) in map {
+                    k.encode_into(buf);
+                    v.encode_into(buf);
                 }
             }
+            Resp::Verbatim(format, s) => {
+                buf.push(b'=');
+                write!(buf, "{}", format.len() + 1 + s.len());
+                buf.extend(CTRLF);
+                buf.extend(format.as_bytes());
+                buf.push(b':');
+                buf.extend(s.as_bytes());
+                buf.extend(CTRLF);
+            }
         }
-        buf
     }
 
     pub fn parse<'i: 'r>(input: &'i [u8]) -> Result<Self, RespError> {
@@ -228,6 +294,21 @@ impl<'r> Resp<'r> {
         Self::Array(input)
     }
 
+    /// A RESP3 map, connections flatten it for RESP2 clients.
+    pub fn map(pairs: Vec<(Resp<'r>, Resp<'r>)>) -> Self {
+        Self::Map(pairs)
+    }
+
+    /// A two element array, e.g. a range or a key-value pair.
+    pub fn pair(a: Resp<'r>, b: Resp<'r>) -> Self {
+        Self::Array(vec![a, b])
+    }
+
+    /// The flat `[key, value, key, value...]` array RESP2 uses in place of a map.
+    pub fn flat_map(pairs: Vec<(Resp<'r>, Resp<'r>)>) -> Self {
+        Self::Array(pairs.into_iter().flat_map(|(k, v)| [k, v]).collect())
+    }
+
     pub fn expect_integer(&self) -> Option<i64> {
         match self {
             Resp::Integer(i) => Some(*i),
@@ -249,6 +330,110 @@ impl<'r> Resp<'r> {
             _ => None,
         }
     }
+
+    /// Replaces maps, at any depth, with the flat key-value arrays RESP2 uses instead.
+    pub fn flatten_maps(self) -> Self {
+        match self {
+            Resp::Map(map) => Resp::flat_map(
+                map.into_iter()
+                    .map(|(k, v)| (k.flatten_maps(), v.flatten_maps()))
+                    .collect(),
+            ),
+            Resp::Array(array) => Resp::Array(array.into_iter().map(Resp::flatten_maps).collect()),
+            Resp::Push(push) => Resp::Push(push.into_iter().map(Resp::flatten_maps).collect()),
+            resp => resp,
+        }
+    }
+}
+
+/// Accumulates bytes read from a socket and splits them into complete RESP frames, so
+/// callers never have to deal with frames split across reads.
+#[derive(Debug, Default)]
+pub struct RespDecoder {
+    buf: Vec<u8>,
+}
+
+impl RespDecoder {
+    pub fn extend(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// The buffer to read into, e.g. with `read_buf`.
+    pub fn buffer_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buf
+    }
+
+    /// Removes and returns the next complete frame, or `None` if more data is needed.
+    pub fn next_frame(&mut self) -> Result<Option<Vec<u8>>, RespError> {
+        let Some(len) = frame_len(&self.buf)? else {
+            return Ok(None);
+        };
+        Ok(Some(self.buf.drain(..len).collect()))
+    }
+
+    /// Like `next_frame`, but also accepts inline commands such as `PING\r\n` sent by telnet
+    /// or health checkers, converted to a RESP array. Empty lines are skipped.
+    pub fn next_request(&mut self) -> Result<Option<Vec<u8>>, RespError> {
+        loop {
+            match self.buf.first() {
+                None => return Ok(None),
+                Some(b'*') => return self.next_frame(),
+                Some(_) => {}
+            }
+            let Some(line_end) = self.buf.iter().position(|&b| b == b'\n') else {
+                return Ok(None);
+            };
+            let line: Vec<u8> = self.buf.drain(..=line_end).collect();
+            let args: Vec<&[u8]> = line
+                .split(u8::is_ascii_whitespace)
+                .filter(|arg| !arg.is_empty())
+                .collect();
+            if args.is_empty() {
+                continue;
+            }
+            let mut frame = format!("*{}\r\n", args.len()).into_bytes();
+            for arg in args {
+                frame.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+                frame.extend_from_slice(arg);
+                frame.extend_from_slice(CTRLF);
+            }
+            return Ok(Some(frame));
+        }
+    }
+}
+
+/// Length of the frame at the start of `input`, or `None` if it isn't fully buffered yet.
+fn frame_len(input: &[u8]) -> Result<Option<usize>, RespError> {
+    let Some(&kind) = input.first() else {
+        return Ok(None);
+    };
+    let Some(line_end) = input.windows(2).position(|w| w == CTRLF) else {
+        return Ok(None);
+    };
+    let header_len = line_end + CTRLF.len();
+    match kind {
+        b'+' | b'-' | b':' => Ok(Some(header_len)),
+        b'$' => {
+            let length = from_utf8(&input[1..line_end])?.parse::<isize>()?;
+            if length < 0 {
+                return Ok(Some(header_len));
+            }
+            let len = header_len + length as usize + CTRLF.len();
+            Ok((input.len() >= len).then_some(len))
+        }
+        b'*' => {
+            let length = from_utf8(&input[1..line_end])?.parse::<isize>()?;
+            let mut len = header_len;
+            for _ in 0..length.max(0) {
+                match frame_len(&input[len..])? {
+                    Some(element_len) => len += element_len,
+                    None => return Ok(None),
+                }
+            }
+            Ok(Some(len))
+        }
+        c => Err(RespError::UnsuportedType(c as char)),
+    }
 }
 
 impl<'r> std::fmt::Debug for Resp<'r> {
@@ -258,14 +443,31 @@ impl<'r> std::fmt::Debug for Resp<'r> {
             Self::SimpleError(e) => write!(f, "-\"{e}\""),
             Self::Integer(i) => write!(f, "{i}"),
             Self::BulkString(bs) => write!(f, "${} {}", bs.len(), bs),
-            Self::Array(array) => {
+            Self::Null => write!(f, "$-1"),
+            Self::Array(array) | Self::Push(array) => {
+                if matches!(self, Self::Push(_)) {
+                    write!(f, ">")?;
+                }
                 write!(f, "[")?;
-                write!(f, "{:?}", array[0]);
-                for i in array.iter().skip(1) {
-                    write!(f, ", {:?}", i)?;
+                for (i, item) in array.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:?}", item)?;
                 }
                 write!(f, "]")
             }
+            Self::Map(map) => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in map.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:?}: {:?}", k, v)?;
+                }
+                write!(f, "}}")
+            }
+            Self::Verbatim(format, s) => write!(f, "={format}:{s}"),
         }
     }
 }
@@ -277,10 +479,14 @@ where
     fn clone(&self) -> Self {
         match self {
             Resp::SimpleString(cow) => Resp::SimpleString(cow.clone()),
-            Resp::SimpleError(cow) => Resp::SimpleString(cow.clone()),
+            Resp::SimpleError(cow) => Resp::SimpleError(cow.clone()),
             Resp::Integer(i) => Resp::Integer(*i),
             Resp::BulkString(cow) => Resp::BulkString(cow.clone()),
+            Resp::Null => Resp::Null,
             Resp::Array(vec) => Resp::Array(vec.clone()),
+            Resp::Push(vec) => Resp::Push(vec.clone()),
+            Resp::Map(map) => Resp::Map(map.clone()),
+            Resp::Verbatim(format, cow) => Resp::Verbatim(format, cow.clone()),
         }
     }
 }
@@ -291,9 +497,11 @@ impl<'input> From<RdbString> for Resp<'input> {
     }
 }
 
+/// The command as a client sends it, an array of bulk strings, which is also how it is
+/// propagated to replicas.
 impl<'c> From<Command<'c>> for Resp<'c> {
     fn from(command: Command<'c>) -> Self {
-        let mut array = vec![Resp::BulkString(Cow::Owned(command.name()))];
+        let mut array = vec![Resp::bulk_string(command.name())];
         match command {
             Command::Ping => {}
             Command::Echo(msg) => {
@@ -305,10 +513,49 @@ impl<'c> From<Command<'c>> for Resp<'c> {
             Command::Set(key, value, expiry) => {
                 array.push(key);
                 array.push(value);
-                if let Some(exp) = expiry {
-                    array.push(Resp::bulk_string("EX"));
-                    array.push(Resp::Integer(exp))
+                if let Some(expiry) = expiry {
+                    let (option, value) = expiry.option();
+                    array.push(Resp::bulk_string(option));
+                    array.push(Resp::BulkString(Cow::Owned(value.to_string())));
+                }
+            }
+            Command::GetRange(key, start, end) | Command::Substr(key, start, end) => {
+                array.push(key);
+                array.push(Resp::BulkString(Cow::Owned(start.to_string())));
+                array.push(Resp::BulkString(Cow::Owned(end.to_string())));
+            }
+            Command::GetSet(key, value) | Command::Append(key, value) => {
+                array.push(key);
+                array.push(value);
+            }
+            Command::Incr(key) | Command::Decr(key) => array.push(key),
+            Command::IncrBy(key, by) | Command::DecrBy(key, by) => {
+                array.push(key);
+                array.push(Resp::BulkString(Cow::Owned(by.to_string())));
+            }
+            Command::Lcs(key1, key2, options) => {
+                array.push(key1);
+                array.push(key2);
+                if options.len {
+                    array.push(Resp::bulk_string("LEN"));
+                }
+                if options.idx {
+                    array.push(Resp::bulk_string("IDX"));
+                }
+                if options.min_match_len > 0 {
+                    array.push(Resp::bulk_string("MINMATCHLEN"));
+                    array.push(Resp::BulkString(Cow::Owned(
+                        options.min_match_len.to_string(),
+                    )));
                 }
+                if options.with_match_len {
+                    array.push(Resp::bulk_string("WITHMATCHLEN"));
+                }
+            }
+            Command::Del(keys) | Command::Exists(keys) => array.extend(keys),
+            Command::LPushX(key, elements) | Command::RPushX(key, elements) => {
+                array.push(key);
+                array.extend(elements);
             }
             Command::ConfigGet(config_item) => {
                 array.push(Resp::BulkString(Cow::Owned(format!("{:?}", config_item))))
@@ -316,12 +563,38 @@ impl<'c> From<Command<'c>> for Resp<'c> {
             Command::Keys(resp) => {
                 array.push(resp);
             }
+            Command::Scan(options) => {
+                array.push(Resp::BulkString(Cow::Owned(options.cursor.to_string())));
+                if let Some(pattern) = options.pattern {
+                    array.push(Resp::bulk_string("MATCH"));
+                    array.push(Resp::BulkString(Cow::Owned(pattern)));
+                }
+                array.push(Resp::bulk_string("COUNT"));
+                array.push(Resp::BulkString(Cow::Owned(options.count.to_string())));
+                if let Some(value_type) = options.value_type {
+                    array.push(Resp::bulk_string("TYPE"));
+                    array.push(Resp::BulkString(Cow::Owned(value_type)));
+                }
+            }
             Command::Info(resp) => {
                 if let Some(info) = resp {
                     array.push(info);
                 }
             }
-            Command::Save => {}
+            Command::Save | Command::Lolwut | Command::Failover => {}
+            Command::Shutdown(options) => {
+                match options.save {
+                    Some(false) => array.push(Resp::bulk_string("NOSAVE")),
+                    Some(true) => array.push(Resp::bulk_string("SAVE")),
+                    None => {}
+                }
+                if options.now {
+                    array.push(Resp::bulk_string("NOW"));
+                }
+                if options.force {
+                    array.push(Resp::bulk_string("FORCE"));
+                }
+            }
             Command::ReplConf(key, value) => {
                 array.push(key);
                 array.push(value);
@@ -331,13 +604,16 @@ impl<'c> From<Command<'c>> for Resp<'c> {
                 array.push(master_offset);
             }
             Command::Wait(numofreplicas, timeout) => {
-                array.push(numofreplicas);
-                array.push(timeout);
+                array.push(Resp::BulkString(Cow::Owned(numofreplicas.to_string())));
+                array.push(Resp::BulkString(Cow::Owned(timeout.to_string())));
             }
             Command::Select(index) => array.push(index),
             Command::Type(key) => array.push(key),
-            Command::XAdd(key, id, list) => {
+            Command::XAdd(key, id, list, nomkstream) => {
                 array.push(key);
+                if nomkstream {
+                    array.push(Resp::bulk_string("NOMKSTREAM"));
+                }
                 array.push(id);
                 array.extend(list);
             }
@@ -351,6 +627,197 @@ impl<'c> From<Command<'c>> for Resp<'c> {
                 array.extend(streams);
                 array.extend(ids);
             }
+            Command::Auth(username, password) => {
+                if let Some(username) = username {
+                    array.push(username);
+                }
+                array.push(password);
+            }
+            Command::Quit
+            | Command::Reset
+            | Command::ReadOnly
+            | Command::ReadWrite
+            | Command::Asking
+            | Command::Monitor => {}
+            Command::Hello(protover, auth) => {
+                if let Some(protover) = protover {
+                    array.push(Resp::BulkString(Cow::Owned(protover.to_string())));
+                }
+                if let Some((username, password)) = auth {
+                    array.push(Resp::bulk_string("AUTH"));
+                    array.push(username);
+                    array.push(password);
+                }
+            }
+            Command::Acl(subcommand) => match subcommand {
+                AclSubcommand::SetUser(username, rules) => {
+                    array.push(Resp::bulk_string("SETUSER"));
+                    array.push(username);
+                    array.extend(rules);
+                }
+                AclSubcommand::GetUser(username) => {
+                    array.push(Resp::bulk_string("GETUSER"));
+                    array.push(username);
+                }
+                AclSubcommand::List => array.push(Resp::bulk_string("LIST")),
+                AclSubcommand::WhoAmI => array.push(Resp::bulk_string("WHOAMI")),
+                AclSubcommand::Cat => array.push(Resp::bulk_string("CAT")),
+            },
+            Command::Client(subcommand) => match subcommand {
+                ClientSubcommand::Id => array.push(Resp::bulk_string("ID")),
+                ClientSubcommand::KillAddr(addr) => {
+                    array.push(Resp::bulk_string("KILL"));
+                    array.push(Resp::BulkString(Cow::Owned(addr)));
+                }
+                ClientSubcommand::Kill(filters) => {
+                    array.push(Resp::bulk_string("KILL"));
+                    for filter in filters {
+                        let (name, value) = match filter {
+                            ClientKillFilter::Id(id) => ("ID", id.to_string()),
+                            ClientKillFilter::Addr(addr) => ("ADDR", addr),
+                            ClientKillFilter::SkipMe(skip) => {
+                                ("SKIPME", if skip { "yes" } else { "no" }.to_string())
+                            }
+                        };
+                        array.push(Resp::bulk_string(name));
+                        array.push(Resp::BulkString(Cow::Owned(value)));
+                    }
+                }
+                ClientSubcommand::NoEvict(on) => {
+                    array.push(Resp::bulk_string("NO-EVICT"));
+                    array.push(Resp::bulk_string(if on { "on" } else { "off" }));
+                }
+                ClientSubcommand::Tracking(None) => {
+                    array.push(Resp::bulk_string("TRACKING"));
+                    array.push(Resp::bulk_string("off"));
+                }
+                ClientSubcommand::Tracking(Some(options)) => {
+                    array.push(Resp::bulk_string("TRACKING"));
+                    array.push(Resp::bulk_string("on"));
+                    if let Some(id) = options.redirect {
+                        array.push(Resp::bulk_string("REDIRECT"));
+                        array.push(Resp::BulkString(Cow::Owned(id.to_string())));
+                    }
+                    if options.bcast {
+                        array.push(Resp::bulk_string("BCAST"));
+                    }
+                    for prefix in options.prefixes {
+                        array.push(Resp::bulk_string("PREFIX"));
+                        array.push(Resp::BulkString(Cow::Owned(prefix)));
+                    }
+                }
+                ClientSubcommand::NoTouch(on) => {
+                    array.push(Resp::bulk_string("NO-TOUCH"));
+                    array.push(Resp::bulk_string(if on { "on" } else { "off" }));
+                }
+            },
+            Command::Object(ObjectSubcommand::Encoding(key)) => {
+                array.push(Resp::bulk_string("ENCODING"));
+                array.push(key);
+            }
+            Command::Object(ObjectSubcommand::RefCount(key)) => {
+                array.push(Resp::bulk_string("REFCOUNT"));
+                array.push(key);
+            }
+            Command::Memory(MemorySubcommand::Doctor) => array.push(Resp::bulk_string("DOCTOR")),
+            Command::Memory(MemorySubcommand::Stats) => array.push(Resp::bulk_string("STATS")),
+            Command::Cluster(subcommand) => array.push(Resp::bulk_string(match subcommand {
+                ClusterSubcommand::Info => "INFO",
+                ClusterSubcommand::MyId => "MYID",
+                ClusterSubcommand::Slots => "SLOTS",
+                ClusterSubcommand::Shards => "SHARDS",
+            })),
+            Command::SlowLog(subcommand) => match subcommand {
+                SlowLogSubcommand::Get(count) => {
+                    array.push(Resp::bulk_string("GET"));
+                    if let Some(count) = count {
+                        array.push(Resp::BulkString(Cow::Owned(count.to_string())));
+                    }
+                }
+                SlowLogSubcommand::Len => array.push(Resp::bulk_string("LEN")),
+                SlowLogSubcommand::Reset => array.push(Resp::bulk_string("RESET")),
+            },
+            Command::Commands(CommandSubcommand::Info(names)) => {
+                array.push(Resp::bulk_string("INFO"));
+                array.extend(
+                    names
+                        .into_iter()
+                        .map(|name| Resp::BulkString(Cow::Owned(name))),
+                );
+            }
+            Command::Commands(CommandSubcommand::Count) => array.push(Resp::bulk_string("COUNT")),
+            Command::Commands(CommandSubcommand::Docs(names)) => {
+                array.push(Resp::bulk_string("DOCS"));
+                array.extend(
+                    names
+                        .into_iter()
+                        .map(|name| Resp::BulkString(Cow::Owned(name))),
+                );
+            }
+            Command::Debug(DebugSubcommand::ChangeReplId) => {
+                array.push(Resp::bulk_string("CHANGE-REPL-ID"))
+            }
+            Command::Debug(DebugSubcommand::Reload) => array.push(Resp::bulk_string("RELOAD")),
+            Command::Debug(DebugSubcommand::FlushAll) => array.push(Resp::bulk_string("FLUSHALL")),
+            Command::Debug(DebugSubcommand::Jmap) => array.push(Resp::bulk_string("JMAP")),
+            Command::Debug(DebugSubcommand::ReplicationOffset) => {
+                array.push(Resp::bulk_string("REPLICATION-OFFSET"))
+            }
+            Command::Debug(DebugSubcommand::StringMatchLen(pattern, string)) => {
+                array.push(Resp::bulk_string("STRINGMATCH-LEN"));
+                array.push(Resp::BulkString(Cow::Owned(pattern)));
+                array.push(Resp::BulkString(Cow::Owned(string)));
+            }
+            Command::Debug(DebugSubcommand::Sleep(duration)) => {
+                array.push(Resp::bulk_string("SLEEP"));
+                array.push(Resp::BulkString(Cow::Owned(
+                    duration.as_secs_f64().to_string(),
+                )));
+            }
+            Command::Debug(DebugSubcommand::Object(key)) => {
+                array.push(Resp::bulk_string("OBJECT"));
+                array.push(Resp::BulkString(Cow::Owned(key)));
+            }
+            Command::Debug(DebugSubcommand::DumpKey(key)) => {
+                array.push(Resp::bulk_string("DUMP-KEY"));
+                array.push(Resp::BulkString(Cow::Owned(key)));
+            }
+            Command::Debug(DebugSubcommand::SetActiveExpire(on)) => {
+                array.push(Resp::bulk_string("SET-ACTIVE-EXPIRE"));
+                array.push(Resp::bulk_string(if on { "1" } else { "0" }));
+            }
+            Command::Subscribe(_, channels) | Command::Unsubscribe(_, channels) => array.extend(
+                channels
+                    .into_iter()
+                    .map(|channel| Resp::BulkString(Cow::Owned(channel))),
+            ),
+            Command::Publish(_, channel, message) => {
+                array.push(Resp::BulkString(Cow::Owned(channel)));
+                array.push(Resp::BulkString(Cow::Owned(message)));
+            }
+            Command::PubSub(subcommand) => match subcommand {
+                PubSubSubcommand::Channels(kind, pattern) => {
+                    array.push(Resp::bulk_string(match kind {
+                        ChannelKind::Global => "CHANNELS",
+                        ChannelKind::Shard => "SHARDCHANNELS",
+                    }));
+                    if let Some(pattern) = pattern {
+                        array.push(Resp::BulkString(Cow::Owned(pattern)));
+                    }
+                }
+                PubSubSubcommand::NumSub(kind, channels) => {
+                    array.push(Resp::bulk_string(match kind {
+                        ChannelKind::Global => "NUMSUB",
+                        ChannelKind::Shard => "SHARDNUMSUB",
+                    }));
+                    array.extend(
+                        channels
+                            .into_iter()
+                            .map(|channel| Resp::BulkString(Cow::Owned(channel))),
+                    );
+                }
+                PubSubSubcommand::NumPat => array.push(Resp::bulk_string("NUMPAT")),
+            },
         }
 
         Resp::Array(array)
@@ -361,11 +828,11 @@ impl<'r> TryFrom<Value> for Resp<'r> {
     type Error = RespError;
     fn try_from(value: Value) -> Result<Self, Self::Error> {
         match value {
-            Value::Str(str) => Ok(Resp::BulkString(Cow::Owned(str))),
+            Value::Str(str, _) => Ok(Resp::BulkString(Cow::Owned(str))),
             Value::List(values) => Ok(Resp::Array(
                 values
                     .into_iter()
-                    .flat_map(|value| TryFrom::<Value>::try_from(value))
+                    .flat_map(TryFrom::<Value>::try_from)
                     .collect(),
             )),
             v => Err(RespError::DataTypeIsNotSupported(
@@ -383,3 +850,263 @@ impl<'r> From<StreamId> for Resp<'r> {
         )))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::command::SetExpiry;
+
+    use super::*;
+
+    #[test]
+    fn num_digits_of_extremes() {
+        assert_eq!(num_digits(0), 1);
+        assert_eq!(num_digits(9), 1);
+        assert_eq!(num_digits(10), 2);
+        assert_eq!(num_digits(-10), 2);
+        assert_eq!(num_digits(i64::MAX), 19);
+        assert_eq!(num_digits(i64::MIN), 19);
+    }
+
+    #[test]
+    fn integer_len_matches_encoding() {
+        for i in [0, 7, -7, 10, -10, i64::MAX, i64::MIN, i64::MIN + 1] {
+            let resp = Resp::Integer(i);
+            assert_eq!(resp.len(), resp.encode().len(), "{i}");
+        }
+    }
+
+    fn strings() -> Vec<Cow<'static, str>> {
+        ["", "a", "OK", "hello world", "ключ", &"x".repeat(1000)]
+            .into_iter()
+            .map(|s| Cow::Owned(s.to_string()))
+            .collect()
+    }
+
+    fn scalars() -> Vec<Resp<'static>> {
+        let mut values = Vec::new();
+        for s in strings() {
+            values.push(Resp::SimpleString(s.clone()));
+            values.push(Resp::SimpleError(s.clone()));
+            values.push(Resp::BulkString(s.clone()));
+            values.push(Resp::Verbatim("txt", s));
+        }
+        for i in [0, 1, -1, 9, -9, 10, -10, 999, -1000, i64::MAX, i64::MIN] {
+            values.push(Resp::Integer(i));
+        }
+        values.push(Resp::Null);
+        values
+    }
+
+    /// Wraps `values` in every container kind, including empty ones.
+    fn nested(values: &[Resp<'static>]) -> Vec<Resp<'static>> {
+        let mut containers = vec![
+            Resp::Array(vec![]),
+            Resp::Push(vec![]),
+            Resp::Map(vec![]),
+            Resp::Array(values.to_vec()),
+            Resp::Push(values.to_vec()),
+            Resp::Map(
+                values
+                    .iter()
+                    .cloned()
+                    .zip(values.iter().rev().cloned())
+                    .collect(),
+            ),
+        ];
+        for (i, value) in values.iter().enumerate() {
+            containers.push(Resp::Array(vec![value.clone()]));
+            containers.push(Resp::Map(vec![(
+                value.clone(),
+                values[(i + 1) % values.len()].clone(),
+            )]));
+        }
+        containers
+    }
+
+    #[test]
+    fn len_matches_encoding_for_every_variant() {
+        let scalars = scalars();
+        let once = nested(&scalars);
+        let twice = nested(&once);
+        for value in scalars.iter().chain(&once).chain(&twice) {
+            assert_eq!(value.len(), value.encode().len(), "{value:?}");
+        }
+    }
+
+    #[test]
+    fn empty_and_null_bulk_strings_are_distinct() {
+        assert_eq!(Resp::bulk_string("").encode(), b"$0\r\n\r\n");
+        assert_eq!(Resp::Null.encode(), b"$-1\r\n");
+        assert_eq!(Resp::parse(b"$0\r\n\r\n").unwrap(), Resp::bulk_string(""));
+        assert_eq!(Resp::parse(b"$-1\r\n").unwrap(), Resp::Null);
+        let (value, rest) = Resp::parse_inner(b"*2\r\n$-1\r\n$0\r\n\r\n+OK\r\n").unwrap();
+        assert_eq!(value, Resp::Array(vec![Resp::Null, Resp::bulk_string("")]));
+        assert_eq!(rest, b"+OK\r\n");
+    }
+
+    #[test]
+    fn commands_encode_arguments_as_bulk_strings() {
+        let key = || Resp::bulk_string("key");
+        let cases = [
+            (
+                Command::Set(
+                    key(),
+                    Resp::bulk_string("v"),
+                    Some(SetExpiry::PxAt(1700000000000)),
+                ),
+                "*5\r\n$3\r\nSET\r\n$3\r\nkey\r\n$1\r\nv\r\n$4\r\nPXAT\r\n$13\r\n1700000000000\r\n",
+            ),
+            (
+                Command::GetRange(key(), 0, -1),
+                "*4\r\n$8\r\nGETRANGE\r\n$3\r\nkey\r\n$1\r\n0\r\n$2\r\n-1\r\n",
+            ),
+            (
+                Command::IncrBy(key(), -5),
+                "*3\r\n$6\r\nINCRBY\r\n$3\r\nkey\r\n$2\r\n-5\r\n",
+            ),
+            (
+                Command::Wait(1, 500),
+                "*3\r\n$4\r\nWAIT\r\n$1\r\n1\r\n$3\r\n500\r\n",
+            ),
+        ];
+        for (command, encoded) in cases {
+            assert_eq!(
+                String::from_utf8(Resp::from(command).encode()).unwrap(),
+                encoded
+            );
+        }
+    }
+
+    #[test]
+    fn parse_leaves_the_next_pipelined_command() {
+        let set = b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n";
+        let input = [&set[..], &set[..]].concat();
+
+        let (first, rest) = Resp::parse_inner(&input).unwrap();
+        assert_eq!(rest, set);
+        let (second, rest) = Resp::parse_inner(rest).unwrap();
+        assert!(rest.is_empty());
+
+        let expected = Resp::Array(vec![
+            Resp::bulk_string("SET"),
+            Resp::bulk_string("key"),
+            Resp::bulk_string("value"),
+        ]);
+        assert_eq!(first, expected);
+        assert_eq!(second, expected);
+    }
+
+    /// Parses `frame` followed by `trailer` and checks exactly the trailer is left over.
+    fn parse_with_trailer(frame: &[u8], trailer: &[u8]) -> Resp<'static> {
+        let input = [frame, trailer].concat();
+        let (value, rest) = Resp::parse_inner(&input).unwrap();
+        assert_eq!(rest, trailer, "{value:?}");
+        value.into_owned()
+    }
+
+    #[test]
+    fn parse_returns_the_bytes_after_arrays() {
+        let trailers: [&[u8]; 3] = [b"", b"+OK\r\n", b"garbage\r\n\x00"];
+        for trailer in trailers {
+            assert_eq!(
+                parse_with_trailer(b"*3\r\n:1\r\n:-20\r\n:300\r\n", trailer),
+                Resp::Array(vec![
+                    Resp::Integer(1),
+                    Resp::Integer(-20),
+                    Resp::Integer(300)
+                ])
+            );
+            assert_eq!(
+                parse_with_trailer(b"*3\r\n$2\r\nab\r\n$0\r\n\r\n$5\r\nc\r\nde\r\n", trailer),
+                Resp::Array(vec![
+                    Resp::bulk_string("ab"),
+                    Resp::bulk_string(""),
+                    Resp::bulk_string("c\r\nde"),
+                ])
+            );
+            assert_eq!(
+                parse_with_trailer(
+                    b"*4\r\n*2\r\n:1\r\n$1\r\na\r\n*0\r\n+x\r\n*1\r\n*1\r\n$1\r\nb\r\n",
+                    trailer
+                ),
+                Resp::Array(vec![
+                    Resp::Array(vec![Resp::Integer(1), Resp::bulk_string("a")]),
+                    Resp::Array(vec![]),
+                    Resp::simple_string("x"),
+                    Resp::Array(vec![Resp::Array(vec![Resp::bulk_string("b")])]),
+                ])
+            );
+        }
+    }
+
+    #[test]
+    fn decoder_waits_for_complete_frames_byte_by_byte() {
+        let frames: [&[u8]; 3] = [
+            b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$4\r\nv\r\nv\r\n",
+            b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n",
+            b"*1\r\n$4\r\nPING\r\n",
+        ];
+        let mut decoder = RespDecoder::default();
+        for frame in frames {
+            let (last, head) = frame.split_last().unwrap();
+            for byte in head {
+                decoder.extend(&[*byte]);
+                assert_eq!(decoder.next_frame().unwrap(), None);
+            }
+            decoder.extend(&[*last]);
+            assert_eq!(decoder.next_frame().unwrap().as_deref(), Some(frame));
+        }
+        assert_eq!(decoder.next_frame().unwrap(), None);
+    }
+
+    #[test]
+    fn decoder_converts_inline_commands() {
+        let mut decoder = RespDecoder::default();
+        for byte in b"\r\nSET  k v\r\n*1\r\n$4\r\nPING\r\nPING\n" {
+            decoder.extend(&[*byte]);
+        }
+        let requests: Vec<Vec<u8>> =
+            std::iter::from_fn(|| decoder.next_request().unwrap()).collect();
+        assert_eq!(
+            requests,
+            [
+                &b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n"[..],
+                b"*1\r\n$4\r\nPING\r\n",
+                b"*1\r\n$4\r\nPING\r\n",
+            ]
+        );
+
+        let mut decoder = RespDecoder::default();
+        decoder.extend(b"PIN");
+        assert_eq!(decoder.next_request().unwrap(), None);
+        decoder.extend(b"G\r\n");
+        assert_eq!(
+            decoder.next_request().unwrap().as_deref(),
+            Some(&b"*1\r\n$4\r\nPING\r\n"[..])
+        );
+    }
+
+    #[test]
+    fn parse_errors_report_the_offset_and_nearby_bytes() {
+        let truncated = b"*2\r\n$3\r\nGET\r\n$5\r\nab";
+        let err = Resp::parse_inner(truncated).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "There is no enough parts for the provided type at byte 19: 24 35 0d 0a 61 62"
+        );
+
+        let unterminated = b"$3\r\nabcXY";
+        let err = Resp::parse_inner(unterminated).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Data is not terminated with CTRLF at byte 7: 24 33 0d 0a 61 62 63 58 59"
+        );
+
+        let no_line_end = b"+OK";
+        let err = Resp::parse_inner(no_line_end).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Data is not terminated with CTRLF at byte 3: 2b 4f 4b"
+        );
+    }
+}
@@ -1,22 +1,42 @@
-use clap::Parser;
-use std::borrow::Cow;
+use indexmap::IndexMap;
 use std::net::{SocketAddr, SocketAddrV4};
-use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use std::{
     collections::HashMap,
-    sync::Arc,
-    time::{Duration, SystemTime},
+    sync::{Arc, Mutex},
 };
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpListener;
-use tokio::sync::broadcast::{self, Receiver as BroadcastReceiver, Sender as BroadcastSender};
-use tokio::{net::TcpStream, sync::RwLock};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::broadcast::{
+    self, error::RecvError, Receiver as BroadcastReceiver, Sender as BroadcastSender,
+};
+use tokio::{
+    net::TcpStream,
+    sync::{Notify, RwLock},
+};
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, info, info_span, warn, Instrument};
 
+use crate::acl::{Acl, User, DEFAULT_USER};
+use crate::client::Clients;
 use crate::command::CommandError;
-use crate::connection::ConnectionError;
-use crate::replica::Replica;
-use crate::{command::Command, config::Config, connection::Connection, rdb::Rdb, resp::Resp};
-use crate::{Db, Expiries, REPLICATION_ID};
+use crate::connection::{ConnectionError, ConnectionStream};
+use crate::pubsub::PubSub;
+use crate::replica::{self, MasterLinkStatus, Replica, ReplicaLinkState, ReplicaState};
+use crate::slowlog::SlowLog;
+use crate::stats::CommandStats;
+use crate::tracking::Tracking;
+use crate::{
+    command::Command,
+    config::Config,
+    connection::Connection,
+    rdb::Rdb,
+    resp::{Resp, RespDecoder},
+};
+use crate::{errors, expiry, tls, utils};
+use crate::{Db, Expiries};
 
 #[derive(Debug)]
 pub struct Server {
@@ -24,46 +44,78 @@ pub struct Server {
     address: SocketAddrV4,
     db: Db,
     expiries: Expiries,
-    master_replication_id: String,
+    /// Random per instance, rotated by `DEBUG CHANGE-REPL-ID`
+    master_replication_id: Arc<std::sync::RwLock<String>>,
+    node_id: Arc<str>,
     is_replica: bool,
     propagation_sender: BroadcastSender<Command<'static>>,
     propagation_receiver: BroadcastReceiver<Command<'static>>,
     number_of_replicas: Arc<AtomicUsize>,
-    replica_offsets: Arc<RwLock<HashMap<SocketAddr, usize>>>,
+    replicas: Arc<RwLock<HashMap<SocketAddr, ReplicaState>>>,
     replication_offset: Arc<AtomicUsize>,
+    /// Cleared by `DEBUG SET-ACTIVE-EXPIRE 0`, leaving only lazy expiration on access.
+    active_expire: Arc<AtomicBool>,
+    replica_ack: Arc<Notify>,
+    acl: Arc<RwLock<Acl>>,
+    clients: Arc<Clients>,
+    slowlog: Arc<Mutex<SlowLog>>,
+    command_stats: Arc<CommandStats>,
+    monitor_sender: BroadcastSender<String>,
+    pubsub: Arc<PubSub>,
+    tracking: Arc<Tracking>,
+    master_link: Arc<Mutex<MasterLinkStatus>>,
 }
 
 impl Server {
-    pub fn new() -> Self {
-        let config = Arc::new(Config::parse());
-        let address = SocketAddrV4::new([127, 0, 0, 1].try_into().unwrap(), config.port);
-        let db: Db = Arc::new(RwLock::new(HashMap::new()));
+    pub fn new(config: Config) -> Self {
+        let config = Arc::new(config);
+        let address = SocketAddrV4::new(config.bind, config.port);
+        let db: Db = Arc::new(RwLock::new(IndexMap::new()));
         let expiries: Expiries = Arc::new(RwLock::new(HashMap::new()));
 
-        let master_replication_id = REPLICATION_ID.to_string();
+        let master_replication_id =
+            Arc::new(std::sync::RwLock::new(utils::generate_replication_id()));
         let is_replica = config.replicaof.is_some();
         let (propagation_sender, propagation_receiver) = broadcast::channel(32);
+        let (monitor_sender, _) = broadcast::channel(1024);
         let number_of_replicas = Arc::new(AtomicUsize::new(0));
-        let replica_offsets = Arc::new(RwLock::new(HashMap::new()));
+        let replicas = Arc::new(RwLock::new(HashMap::new()));
         let replication_offset = Arc::new(AtomicUsize::new(0));
+        let acl = Arc::new(RwLock::new(Acl::new(config.requirepass.as_deref())));
+        let slowlog = Arc::new(Mutex::new(SlowLog::new(
+            config.slowlog_log_slower_than,
+            config.slowlog_max_len,
+        )));
         Self {
             config,
             address,
             db,
             expiries,
             master_replication_id,
+            node_id: utils::generate_replication_id().into(),
             is_replica,
             propagation_sender,
             propagation_receiver,
             number_of_replicas,
-            replica_offsets,
+            replicas,
             replication_offset,
+            active_expire: Arc::new(AtomicBool::new(true)),
+            replica_ack: Arc::new(Notify::new()),
+            acl,
+            clients: Arc::new(Clients::default()),
+            slowlog,
+            command_stats: Arc::new(CommandStats::default()),
+            monitor_sender,
+            pubsub: Arc::new(PubSub::default()),
+            tracking: Arc::new(Tracking::default()),
+            master_link: Arc::default(),
         }
     }
 
     pub async fn initialize(&mut self) {
         self.initialize_rdb().await;
         self.initialize_expiration_handlers().await;
+        self.initialize_tracking();
         if self.is_replica {
             self.initialize_replication_slave().await;
         }
@@ -75,33 +127,74 @@ impl Server {
                 Ok(rdb) => {
                     self.db = rdb.database;
                     self.expiries = rdb.expiries;
+                    self.drop_expired_keys().await;
                 }
                 Err(err) => {
-                    println!("Rdb error: {err}");
+                    warn!("Rdb error: {err}");
                 }
             }
         }
     }
 
+    /// Removes keys whose deadline passed while the server was down, so they are never served.
+    async fn drop_expired_keys(&self) {
+        let mut db = self.db.write().await;
+        self.expiries.write().await.retain(|key, expires_at| {
+            let is_due = expiry::is_due(*expires_at);
+            if is_due {
+                db.swap_remove(key);
+            }
+            !is_due
+        });
+    }
+
     pub async fn initialize_expiration_handlers(&mut self) {
+        // Replicas wait for the master to propagate a DEL instead
+        if self.is_replica {
+            return;
+        }
+
         let expiries_map = self.expiries.read().await;
         let entries = expiries_map.clone().into_iter();
 
         for (key, expiry) in entries {
-            let expiries = self.expiries.clone();
-            let db = self.db.clone();
-            tokio::spawn(async move {
-                let expiring_at = SystemTime::UNIX_EPOCH + Duration::from_millis(expiry as u64);
-                let duration = expiring_at.duration_since(SystemTime::now());
+            expiry::spawn_expiration(
+                key,
+                expiry,
+                self.db.clone(),
+                self.expiries.clone(),
+                self.propagation_sender.clone(),
+                self.replication_offset.clone(),
+                self.active_expire.clone(),
+            );
+        }
+    }
 
-                if let Ok(duration) = duration {
-                    tokio::time::sleep(duration).await;
+    /// Invalidates keys for `CLIENT TRACKING` clients as writes are propagated, which covers
+    /// both client writes and expirations.
+    fn initialize_tracking(&self) {
+        let mut writes = self.propagation_sender.subscribe();
+        let tracking = self.tracking.clone();
+        let pubsub = self.pubsub.clone();
+        tokio::spawn(async move {
+            loop {
+                match writes.recv().await {
+                    Ok(command) => {
+                        let keys: Vec<_> = command
+                            .keys()
+                            .into_iter()
+                            .filter_map(|key| key.expect_bulk_string())
+                            .map(|key| key.as_ref())
+                            .collect();
+                        tracking.invalidate(&keys, &pubsub);
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Tracking missed {skipped} writes, clients may see stale keys");
+                    }
+                    Err(RecvError::Closed) => break,
                 }
-
-                db.write().await.remove(&key);
-                expiries.write().await.remove(&key);
-            });
-        }
+            }
+        });
     }
 
     pub async fn initialize_replication_slave(&mut self) {
@@ -113,8 +206,18 @@ impl Server {
             let config = self.config.clone();
             let db = self.db.clone();
             let expiries = self.expiries.clone();
+            let replication_offset = self.replication_offset.clone();
+            let master_link = self.master_link.clone();
             tokio::spawn(async move {
-                let mut replica = Replica::new(addr, port, db, expiries, config);
+                let mut replica = Replica::new(
+                    addr,
+                    port,
+                    db,
+                    expiries,
+                    config,
+                    replication_offset,
+                    master_link,
+                );
                 let _ = replica.start().await;
             });
         }
@@ -123,129 +226,298 @@ impl Server {
     pub async fn start(self) {
         let listener = TcpListener::bind(&self.address)
             .await
-            .expect(&format!("Can not listen to port {}", self.config.port));
-        println!("Listening on port: {}", self.config.port);
+            .unwrap_or_else(|_| panic!("Can not listen to port {}", self.config.port));
+        info!("Listening on port: {}", self.config.port);
+        let server = Arc::new(self);
+        if let Some(tls_port) = server.config.tls_port {
+            let acceptor = tls::acceptor(&server.config)
+                .unwrap_or_else(|err| panic!("Can not configure TLS: {err}"));
+            tokio::spawn(server.clone().start_tls(tls_port, acceptor));
+        }
+        tokio::spawn(server.clone().handle_signals());
         loop {
-            let db = self.db.clone();
-            let expiries = self.expiries.clone();
-            let propagation_sender = self.propagation_sender.clone();
-            let number_of_replicas = self.number_of_replicas.clone();
-            let replica_offsets = self.replica_offsets.clone();
-            let server_replication_offset = self.replication_offset.clone();
-            let mut connection = Connection::new(
-                listener.accept().await.unwrap(),
-                db,
-                expiries,
-                self.config.clone(),
-                self.master_replication_id.clone(),
-                propagation_sender,
-                number_of_replicas,
-                replica_offsets,
-                server_replication_offset,
-            );
-            let mut propagation_receiver = self.propagation_receiver.resubscribe();
+            let (tcp, addr) = listener.accept().await.unwrap();
+            if let Err(err) = utils::configure_socket(&tcp, server.config.tcp_keepalive) {
+                warn!("Can not configure socket of {addr}: {err}");
+            }
+            server.serve(ConnectionStream::Tcp(tcp), addr).await;
+        }
+    }
+
+    /// Shuts down on SIGINT or SIGTERM, the same way `SHUTDOWN` without arguments does.
+    async fn handle_signals(self: Arc<Self>) {
+        let mut sigterm = signal(SignalKind::terminate()).expect("Can not listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => warn!("Received SIGINT scheduling shutdown..."),
+            _ = sigterm.recv() => warn!("Received SIGTERM scheduling shutdown..."),
+        }
+        drain_replicas(
+            &self.config,
+            &self.replicas,
+            &self.replica_ack,
+            &self.propagation_sender,
+            self.replication_offset.load(Ordering::Acquire),
+        )
+        .await;
+        info!("Redis is now ready to exit, bye bye...");
+        std::process::exit(0);
+    }
+
+    async fn start_tls(self: Arc<Self>, port: u16, acceptor: TlsAcceptor) {
+        let address = SocketAddrV4::new(*self.address.ip(), port);
+        let listener = TcpListener::bind(&address)
+            .await
+            .unwrap_or_else(|_| panic!("Can not listen to TLS port {port}"));
+        info!("Listening on TLS port: {port}");
+        loop {
+            let Ok((tcp, addr)) = listener.accept().await else {
+                continue;
+            };
+            if let Err(err) = utils::configure_socket(&tcp, self.config.tcp_keepalive) {
+                warn!("Can not configure socket of {addr}: {err}");
+            }
+            let acceptor = acceptor.clone();
+            let server = self.clone();
+            // Handshake in a separate task so a slow or broken client can't stall the accept loop
+            tokio::spawn(async move {
+                match acceptor.accept(tcp).await {
+                    Ok(tls) => {
+                        server
+                            .serve(ConnectionStream::Tls(Box::new(tls)), addr)
+                            .await
+                    }
+                    Err(err) => warn!("TLS handshake with {addr} failed: {err}"),
+                }
+            });
+        }
+    }
+
+    async fn serve(&self, mut stream: ConnectionStream, addr: SocketAddr) {
+        if self.clients.len() >= self.config.maxclients {
+            tokio::spawn(async move {
+                let _ = stream.write_all(&errors::max_clients().encode()).await;
+                let _ = stream.shutdown().await;
+            });
+            return;
+        }
+        if is_protected(&self.config, &*self.acl.read().await, addr) {
             tokio::spawn(async move {
+                let _ = stream.write_all(&errors::protected_mode().encode()).await;
+                let _ = stream.shutdown().await;
+            });
+            return;
+        }
+
+        let db = self.db.clone();
+        let expiries = self.expiries.clone();
+        let propagation_sender = self.propagation_sender.clone();
+        let number_of_replicas = self.number_of_replicas.clone();
+        let replicas = self.replicas.clone();
+        let server_replication_offset = self.replication_offset.clone();
+        let acl = self.acl.clone();
+        let clients = self.clients.clone();
+        let slowlog = self.slowlog.clone();
+        let command_stats = self.command_stats.clone();
+        let mut connection = Connection::new(
+            (stream, addr),
+            db,
+            expiries,
+            self.config.clone(),
+            self.master_replication_id.clone(),
+            self.node_id.clone(),
+            propagation_sender,
+            number_of_replicas,
+            replicas,
+            server_replication_offset,
+            self.active_expire.clone(),
+            self.replica_ack.clone(),
+            acl,
+            clients,
+            slowlog,
+            command_stats,
+            self.monitor_sender.clone(),
+            self.pubsub.clone(),
+            self.tracking.clone(),
+            self.master_link.clone(),
+        );
+        let mut propagation_receiver = self.propagation_receiver.resubscribe();
+        let span = info_span!("client", id = connection.id, %addr);
+        tokio::spawn(
+            async move {
                 connection.handle().await?;
                 if connection.is_promoted_to_replica {
-                    println!("connection is promoted to replica");
+                    info!("connection is promoted to replica");
                     connection
                         .number_of_replicas
                         .fetch_add(1, std::sync::atomic::Ordering::Release);
+                    connection.replicas.write().await.insert(
+                        connection.addr,
+                        ReplicaState {
+                            state: ReplicaLinkState::SendBulk,
+                            port: connection.replica_listening_port.unwrap_or(addr.port()),
+                            offset: 0,
+                            last_ack: Instant::now(),
+                        },
+                    );
                     tokio::spawn(async move {
-                        let mut buf = Vec::with_capacity(4096);
-                        let mut read_failed = false;
-                        loop {
-                            tokio::select! {
-                                Ok(command) = propagation_receiver.recv() => {
-                                    let resp: Resp<'_> = command.into();
-                                    println!(
-                                        "Propagating command {:?} to replica {}",
-                                        &resp,
-                                        &connection.addr.port()
-                                    );
-                                    let _ = connection.write_all(&resp.encode()).await;
-                                },
-                                Ok(n) = handle_replica_connection(&mut connection, &mut buf, &mut read_failed) => {
-                                    if n == 0 {
-                                        break;
-                                    }
+                    let mut decoder = RespDecoder::default();
+                    let kill = connection.kill.clone();
+                    loop {
+                        tokio::select! {
+                            _ = kill.notified() => break,
+                            Ok(command) = propagation_receiver.recv() => {
+                                let resp: Resp<'_> = command.into();
+                                debug!("Propagating command {:?} to replica", &resp);
+                                let _ = connection.write_resp(&resp).await;
+                            },
+                            Ok(n) = handle_replica_connection(&mut connection, &mut decoder) => {
+                                if n == 0 {
+                                    break;
                                 }
                             }
                         }
-                        connection
-                            .number_of_replicas
-                            .fetch_sub(1, std::sync::atomic::Ordering::Release);
-                    });
+                    }
+                    connection
+                        .number_of_replicas
+                        .fetch_sub(1, std::sync::atomic::Ordering::Release);
+                    // A stale offset would keep counting towards WAIT, which has to recount now
+                    connection.replicas.write().await.remove(&connection.addr);
+                    connection.replica_ack.notify_waiters();
+                }.in_current_span());
                 }
 
                 Result::<(), ConnectionError>::Ok(())
-            });
-        }
+            }
+            .instrument(span),
+        );
     }
 }
 
-pub async fn handle_replica_connection<'c>(
+/// Protected mode turns away clients from other hosts while the default user needs no password.
+fn is_protected(config: &Config, acl: &Acl, addr: SocketAddr) -> bool {
+    config.protected_mode
+        && acl.get(DEFAULT_USER).is_some_and(User::is_nopass)
+        && !addr.ip().is_loopback()
+}
+
+pub async fn handle_replica_connection(
     connection: &mut Connection,
-    buf: &mut Vec<u8>,
-    failed: &mut bool,
+    decoder: &mut RespDecoder,
 ) -> Result<usize, ConnectionError> {
-    if buf.is_empty() || *failed {
-        let n = connection.read_buf(buf).await?;
-        if n == 0 {
-            return Ok(0);
-        }
+    let n = connection.read_buf(decoder.buffer_mut()).await?;
+    if n == 0 {
+        return Ok(0);
     }
-    let mut consumed = 0;
-    let mut rest = buf.as_slice();
-    while !rest.is_empty() {
-        match Command::parse(rest) {
-            Ok((c, new_rest)) => {
-                handle_command_from_replica(c, connection).await?;
-                consumed += rest.len() - new_rest.len();
-                rest = new_rest;
-            }
-            Err(err) => {
-                eprintln!("{}", err);
-                *failed = true;
-                break;
-            }
+    while let Some(frame) = decoder.next_frame()? {
+        match Command::parse(&frame) {
+            Ok((c, _)) => handle_command_from_replica(c, connection).await?,
+            Err(err) => warn!("Can not parse command from replica: {err}"),
         }
     }
-    buf.drain(..consumed);
 
-    Ok(consumed)
+    Ok(n)
 }
 
 pub async fn handle_command_from_replica<'c>(
     command: Command<'c>,
     connection: &Connection,
 ) -> Result<(), ConnectionError> {
-    match &command {
-        Command::ReplConf(key, value) => {
-            if let Some(key) = key.expect_bulk_string() {
-                if key.to_string().as_bytes() == b"ACK" {
-                    if let Some(value) = value.expect_bulk_string() {
-                        if let Ok(offset) = value.parse::<usize>() {
-                            println!(
-                                "Replica {} sent offset {}, master offset: {}",
-                                connection.addr.port(),
-                                offset,
-                                connection
-                                    .server_replication_offset
-                                    .load(std::sync::atomic::Ordering::Acquire)
-                            );
+    if let Command::ReplConf(key, value) = &command {
+        if let Some(key) = key.expect_bulk_string() {
+            if key.to_string().as_bytes() == b"ACK" {
+                if let Some(value) = value.expect_bulk_string() {
+                    if let Ok(offset) = value.parse::<usize>() {
+                        debug!(
+                            "Replica sent offset {}, master offset: {}",
+                            offset,
                             connection
-                                .replica_offsets
-                                .write()
-                                .await
-                                .insert(connection.addr.clone(), offset);
+                                .server_replication_offset
+                                .load(std::sync::atomic::Ordering::Acquire)
+                        );
+                        if let Some(replica) =
+                            connection.replicas.write().await.get_mut(&connection.addr)
+                        {
+                            replica.state = ReplicaLinkState::Online;
+                            replica.offset = offset;
+                            replica.last_ack = Instant::now();
                         }
+                        connection.replica_ack.notify_waiters();
                     }
                 }
             }
         }
-        _ => {}
     }
 
     Ok(())
 }
+
+/// Gives replicas up to `shutdown-timeout` seconds to acknowledge `offset` before the master
+/// exits, so writes already acknowledged to clients aren't lost with it.
+pub async fn drain_replicas(
+    config: &Config,
+    replicas: &RwLock<HashMap<SocketAddr, ReplicaState>>,
+    replica_ack: &Notify,
+    propagation_sender: &BroadcastSender<Command<'static>>,
+    offset: usize,
+) {
+    let connected = replicas.read().await.len();
+    if config.replicaof.is_some() || connected == 0 || offset == 0 || config.shutdown_timeout == 0 {
+        return;
+    }
+    info!("Waiting for replicas before shutting down.");
+    let synchronized = replica::wait_for_replicas(
+        replicas,
+        replica_ack,
+        propagation_sender,
+        offset,
+        connected,
+        Some(Duration::from_secs(config.shutdown_timeout)),
+    )
+    .await;
+    if synchronized < connected {
+        warn!(
+            "{} replicas did not acknowledge offset {offset} before shutting down",
+            connected - synchronized
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[test]
+    fn instances_get_distinct_replication_ids() {
+        let config = Config::parse_from(["redis-server"]);
+        let first = Server::new(config.clone());
+        let second = Server::new(config);
+
+        let first_id = first.master_replication_id.read().unwrap().clone();
+        let second_id = second.master_replication_id.read().unwrap().clone();
+        assert_eq!(first_id.len(), 40);
+        assert!(first_id.bytes().all(|byte| byte.is_ascii_hexdigit()));
+        assert_ne!(first_id, second_id);
+        assert_ne!(first.node_id, second.node_id);
+    }
+
+    #[test]
+    fn protected_mode_turns_away_remote_clients_without_a_password() {
+        let config = Config::parse_from(["redis-server"]);
+        let loopback: SocketAddr = "127.0.0.1:50000".parse().unwrap();
+        let remote: SocketAddr = "192.0.2.10:50000".parse().unwrap();
+
+        let mut acl = Acl::new(None);
+        assert!(is_protected(&config, &acl, remote));
+        assert!(!is_protected(&config, &acl, loopback));
+
+        // A password set at runtime lifts the protection, like requirepass does
+        acl.set_user(DEFAULT_USER, &[">secret"]).unwrap();
+        assert!(!is_protected(&config, &acl, remote));
+        assert!(!is_protected(&config, &Acl::new(Some("secret")), remote));
+
+        let unprotected = Config::parse_from(["redis-server", "--protected-mode", "no"]);
+        assert!(!is_protected(&unprotected, &Acl::new(None), remote));
+    }
+}
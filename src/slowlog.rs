@@ -0,0 +1,121 @@
+use std::{borrow::Cow, collections::VecDeque, net::SocketAddr, time::Duration};
+
+use crate::{command::Command, resp::Resp, utils::get_epoch_ms};
+
+/// Longest argv kept per entry, longer commands are summarized in the last argument.
+const MAX_ARGC: usize = 32;
+/// Longest argument kept per entry, longer arguments are truncated.
+const MAX_ARG_LEN: usize = 128;
+
+#[derive(Debug, Clone)]
+pub struct SlowLogEntry {
+    id: u64,
+    timestamp: u64,
+    duration: Duration,
+    argv: Vec<String>,
+    addr: SocketAddr,
+}
+
+#[derive(Debug)]
+pub struct SlowLog {
+    /// Threshold in microseconds, negative disables the log and zero records every command.
+    log_slower_than: i64,
+    max_len: usize,
+    next_id: u64,
+    entries: VecDeque<SlowLogEntry>,
+}
+
+impl SlowLog {
+    pub fn new(log_slower_than: i64, max_len: usize) -> Self {
+        Self {
+            log_slower_than,
+            max_len,
+            next_id: 0,
+            entries: VecDeque::with_capacity(max_len),
+        }
+    }
+
+    pub fn record(&mut self, duration: Duration, command: &Command<'_>, addr: SocketAddr) {
+        if self.log_slower_than < 0 || duration.as_micros() < self.log_slower_than as u128 {
+            return;
+        }
+
+        let args = command.argv();
+        let mut argv: Vec<String> = args
+            .iter()
+            .take(if args.len() > MAX_ARGC {
+                MAX_ARGC - 1
+            } else {
+                MAX_ARGC
+            })
+            .cloned()
+            .map(truncate_arg)
+            .collect();
+        if args.len() > MAX_ARGC {
+            argv.push(format!(
+                "... ({} more arguments)",
+                args.len() - (MAX_ARGC - 1)
+            ));
+        }
+
+        self.entries.push_front(SlowLogEntry {
+            id: self.next_id,
+            timestamp: (get_epoch_ms() / 1000) as u64,
+            duration,
+            argv,
+            addr,
+        });
+        self.next_id += 1;
+        self.entries.truncate(self.max_len);
+    }
+
+    /// The `count` most recent entries, or all of them when `count` is negative.
+    pub fn get(&self, count: i64) -> Resp<'static> {
+        let count = usize::try_from(count).unwrap_or(self.entries.len());
+        Resp::Array(
+            self.entries
+                .iter()
+                .take(count)
+                .map(|entry| {
+                    Resp::Array(vec![
+                        Resp::Integer(entry.id as i64),
+                        Resp::Integer(entry.timestamp as i64),
+                        Resp::Integer(entry.duration.as_micros() as i64),
+                        Resp::Array(
+                            entry
+                                .argv
+                                .iter()
+                                .map(|arg| Resp::BulkString(Cow::Owned(arg.clone())))
+                                .collect(),
+                        ),
+                        Resp::BulkString(Cow::Owned(entry.addr.to_string())),
+                        // Client names are not supported yet
+                        Resp::BulkString(Cow::Borrowed("")),
+                    ])
+                })
+                .collect(),
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn reset(&mut self) {
+        self.entries.clear();
+    }
+}
+
+fn truncate_arg(mut arg: String) -> String {
+    if arg.len() <= MAX_ARG_LEN {
+        return arg;
+    }
+    let mut end = MAX_ARG_LEN;
+    while !arg.is_char_boundary(end) {
+        end -= 1;
+    }
+    let more = arg.len() - end;
+    arg.truncate(end);
+    arg.push_str(&format!("... ({more} more bytes)"));
+    arg
+}
@@ -0,0 +1,64 @@
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use crate::command::spec;
+
+#[derive(Debug, Default)]
+struct CommandStat {
+    calls: AtomicU64,
+    usec: AtomicU64,
+}
+
+/// Per-command call counts and cumulative execution time, reported by `INFO commandstats`.
+///
+/// There is a slot for every entry of `spec::COMMANDS`, so recording a call finds the slot
+/// without allocating and only bumps its atomics.
+#[derive(Debug)]
+pub struct CommandStats {
+    stats: Box<[CommandStat]>,
+}
+
+impl Default for CommandStats {
+    fn default() -> Self {
+        Self {
+            stats: spec::COMMANDS
+                .iter()
+                .map(|_| CommandStat::default())
+                .collect(),
+        }
+    }
+}
+
+impl CommandStats {
+    pub fn record(&self, name: &str, duration: Duration) {
+        let Some(stat) = spec::index(name).map(|index| &self.stats[index]) else {
+            return;
+        };
+        stat.calls.fetch_add(1, Ordering::Relaxed);
+        stat.usec
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// The `# Commandstats` INFO section, one `cmdstat_<name>` line per command called so far.
+    pub fn info(&self) -> String {
+        let mut info = String::from("# Commandstats\r\n");
+        for (spec, stat) in spec::COMMANDS.iter().zip(self.stats.iter()) {
+            let calls = stat.calls.load(Ordering::Relaxed);
+            if calls == 0 {
+                continue;
+            }
+            let usec = stat.usec.load(Ordering::Relaxed);
+            let usec_per_call = usec as f64 / calls as f64;
+            write!(
+                info,
+                "cmdstat_{}:calls={calls},usec={usec},usec_per_call={usec_per_call:.2}\r\n",
+                spec.name
+            )
+            .unwrap();
+        }
+        info
+    }
+}
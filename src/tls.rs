@@ -0,0 +1,41 @@
+use std::sync::Arc;
+
+use thiserror::Error;
+use tokio_rustls::{
+    rustls::{
+        self,
+        pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+        ServerConfig,
+    },
+    TlsAcceptor,
+};
+
+use crate::config::Config;
+
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("--tls-cert-file and --tls-key-file are required when --tls-port is set")]
+    MissingCertificate,
+
+    #[error("Can not read PEM file: {0}")]
+    Pem(#[from] rustls::pki_types::pem::Error),
+
+    #[error("Invalid TLS configuration: {0}")]
+    Rustls(#[from] rustls::Error),
+}
+
+pub fn acceptor(config: &Config) -> Result<TlsAcceptor, TlsError> {
+    let (Some(cert_file), Some(key_file)) = (&config.tls_cert_file, &config.tls_key_file) else {
+        return Err(TlsError::MissingCertificate);
+    };
+
+    let certs = CertificateDer::pem_file_iter(cert_file)?.collect::<Result<Vec<_>, _>>()?;
+    let key = PrivateKeyDer::from_pem_file(key_file)?;
+    let server_config =
+        ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)?;
+
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
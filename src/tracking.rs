@@ -0,0 +1,124 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{
+    pubsub::{ChannelKind, PubSub},
+    resp::Resp,
+};
+
+/// The channel RESP2 clients subscribe to in order to receive redirected invalidations.
+pub const INVALIDATE_CHANNEL: &str = "__redis__:invalidate";
+
+/// Where the invalidation messages of a tracking client are delivered.
+#[derive(Debug, Clone)]
+pub enum TrackingTarget {
+    /// RESP3 `invalidate` push messages on the client's own connection
+    Push(UnboundedSender<Resp<'static>>),
+    /// Pub/Sub messages on `__redis__:invalidate` to this client, if it is subscribed
+    Redirect(u64),
+}
+
+#[derive(Debug)]
+struct TrackingClient {
+    target: TrackingTarget,
+    /// Broadcasting mode, invalidating every key matching `prefixes` rather than the keys read
+    bcast: bool,
+    prefixes: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+struct TrackingState {
+    clients: HashMap<u64, TrackingClient>,
+    /// Keys read by clients in the default mode. Each read is invalidated once.
+    keys: HashMap<String, HashSet<u64>>,
+}
+
+/// Registry of clients with `CLIENT TRACKING` enabled, keyed by client id.
+#[derive(Debug, Default)]
+pub struct Tracking {
+    state: Mutex<TrackingState>,
+}
+
+impl Tracking {
+    pub fn enable(&self, id: u64, target: TrackingTarget, bcast: bool, prefixes: Vec<String>) {
+        self.state.lock().unwrap().clients.insert(
+            id,
+            TrackingClient {
+                target,
+                bcast,
+                prefixes,
+            },
+        );
+    }
+
+    pub fn disable(&self, id: u64) {
+        let mut state = self.state.lock().unwrap();
+        if state.clients.remove(&id).is_some() {
+            state.keys.retain(|_, ids| {
+                ids.remove(&id);
+                !ids.is_empty()
+            });
+        }
+    }
+
+    /// Remembers that `id` read `keys`, unless it isn't tracking them by reads.
+    pub fn track<'k>(&self, id: u64, keys: impl IntoIterator<Item = &'k str>) {
+        let mut state = self.state.lock().unwrap();
+        if state.clients.get(&id).is_none_or(|client| client.bcast) {
+            return;
+        }
+        for key in keys {
+            state.keys.entry(key.to_string()).or_default().insert(id);
+        }
+    }
+
+    /// Sends invalidation messages for modified `keys` to the clients tracking them.
+    pub fn invalidate(&self, keys: &[&str], pubsub: &PubSub) {
+        let mut state = self.state.lock().unwrap();
+        let mut invalidated: HashMap<u64, Vec<&str>> = HashMap::new();
+        for &key in keys {
+            for id in state.keys.remove(key).unwrap_or_default() {
+                invalidated.entry(id).or_default().push(key);
+            }
+            for (id, client) in &state.clients {
+                let matches = client.bcast
+                    && (client.prefixes.is_empty()
+                        || client.prefixes.iter().any(|prefix| key.starts_with(prefix)));
+                if matches {
+                    invalidated.entry(*id).or_default().push(key);
+                }
+            }
+        }
+        for (id, keys) in invalidated {
+            let Some(client) = state.clients.get(&id) else {
+                continue;
+            };
+            let keys = Resp::Array(
+                keys.into_iter()
+                    .map(|key| Resp::BulkString(Cow::Owned(key.to_string())))
+                    .collect(),
+            );
+            match &client.target {
+                TrackingTarget::Push(sender) => {
+                    let _ = sender.send(Resp::Push(vec![Resp::bulk_string("invalidate"), keys]));
+                }
+                TrackingTarget::Redirect(target) => {
+                    if let Some(sender) =
+                        pubsub.subscriber(ChannelKind::Global, INVALIDATE_CHANNEL, *target)
+                    {
+                        let _ = sender.send(Resp::Array(vec![
+                            Resp::bulk_string(ChannelKind::Global.message_frame()),
+                            Resp::bulk_string(INVALIDATE_CHANNEL),
+                            keys,
+                        ]));
+                    }
+                }
+            }
+        }
+    }
+}
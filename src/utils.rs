@@ -1,4 +1,10 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::{
+    io,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use socket2::{SockRef, TcpKeepalive};
+use tokio::net::TcpStream;
 
 pub fn get_epoch_ms() -> usize {
     SystemTime::now()
@@ -6,3 +12,155 @@ pub fn get_epoch_ms() -> usize {
         .unwrap()
         .as_millis() as usize
 }
+
+/// A random 40 character hex id, like the replication ids Redis generates at startup.
+pub fn generate_replication_id() -> String {
+    let mut bytes = [0u8; 20];
+    getrandom::getrandom(&mut bytes).expect("Can not generate a random replication id");
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Disables Nagle's algorithm so pipelined replies go out immediately, and enables TCP
+/// keepalive to detect dead peers unless `keepalive_secs` is 0.
+pub fn configure_socket(tcp: &TcpStream, keepalive_secs: u64) -> io::Result<()> {
+    tcp.set_nodelay(true)?;
+    if keepalive_secs > 0 {
+        let keepalive = TcpKeepalive::new().with_time(Duration::from_secs(keepalive_secs));
+        SockRef::from(tcp).set_tcp_keepalive(&keepalive)?;
+    }
+    Ok(())
+}
+
+/// Redis-style glob matching supporting `*`, `?`, `[...]` classes and `\` escapes.
+pub fn glob_match(pattern: &[u8], string: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    let mut backtrack = None;
+    while s < string.len() {
+        if p < pattern.len() {
+            match pattern[p] {
+                b'*' => {
+                    backtrack = Some((p + 1, s));
+                    p += 1;
+                    continue;
+                }
+                b'?' => {
+                    p += 1;
+                    s += 1;
+                    continue;
+                }
+                b'[' => {
+                    let (matched, len) = match_class(&pattern[p..], string[s]);
+                    if matched {
+                        p += len;
+                        s += 1;
+                        continue;
+                    }
+                }
+                b'\\' if p + 1 < pattern.len() => {
+                    if pattern[p + 1] == string[s] {
+                        p += 2;
+                        s += 1;
+                        continue;
+                    }
+                }
+                c => {
+                    if c == string[s] {
+                        p += 1;
+                        s += 1;
+                        continue;
+                    }
+                }
+            }
+        }
+        // Mismatch: let the last `*` swallow one more byte, or give up
+        match backtrack {
+            Some((star_p, star_s)) => {
+                p = star_p;
+                s = star_s + 1;
+                backtrack = Some((star_p, s));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|b| *b == b'*')
+}
+
+/// Matches `c` against the `[...]` class at the start of `pattern`, returning whether
+/// it matched and how many pattern bytes the class spans. An unterminated class runs
+/// to the end of the pattern.
+fn match_class(pattern: &[u8], c: u8) -> (bool, usize) {
+    let mut i = 1;
+    let negate = pattern.get(i) == Some(&b'^');
+    if negate {
+        i += 1;
+    }
+    let mut matched = false;
+    while i < pattern.len() && pattern[i] != b']' {
+        if pattern[i] == b'\\' && i + 1 < pattern.len() {
+            matched |= pattern[i + 1] == c;
+            i += 2;
+        } else if i + 2 < pattern.len() && pattern[i + 1] == b'-' && pattern[i + 2] != b']' {
+            let (start, end) = (pattern[i], pattern[i + 2]);
+            let (start, end) = (start.min(end), start.max(end));
+            matched |= (start..=end).contains(&c);
+            i += 3;
+        } else {
+            matched |= pattern[i] == c;
+            i += 1;
+        }
+    }
+    (matched != negate, (i + 1).min(pattern.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(pattern: &str, matching: &[&str], not_matching: &[&str]) {
+        for string in matching {
+            assert!(
+                glob_match(pattern.as_bytes(), string.as_bytes()),
+                "{pattern:?} should match {string:?}"
+            );
+        }
+        for string in not_matching {
+            assert!(
+                !glob_match(pattern.as_bytes(), string.as_bytes()),
+                "{pattern:?} should not match {string:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn glob_wildcards() {
+        check("h?llo", &["hello", "hallo"], &["hllo", "heello"]);
+        check("h*llo", &["hllo", "heeeello"], &["hell", "ello"]);
+        check("*", &["", "anything"], &[]);
+        check("a*b*c", &["abc", "aXbYbZc"], &["acb", "abcd"]);
+    }
+
+    #[test]
+    fn glob_character_classes() {
+        check("h[ae]llo", &["hello", "hallo"], &["hillo", "hllo"]);
+        check("h[a-c]llo", &["hallo", "hbllo", "hcllo"], &["hdllo"]);
+        // Reversed ranges match like their sorted counterparts, as in Redis
+        check("h[c-a]llo", &["hbllo"], &["hdllo"]);
+        check("h[^e]llo", &["hallo", "hbllo"], &["hello"]);
+        check("h[^a-c]llo", &["hdllo"], &["hallo", "hcllo"]);
+        check("[\\]]", &["]"], &["\\"]);
+        check("x[-]y", &["x-y"], &["xay"]);
+    }
+
+    #[test]
+    fn glob_escapes() {
+        check("h\\*llo", &["h*llo"], &["hello"]);
+        check("h\\?llo", &["h?llo"], &["hello"]);
+        check("\\[a]", &["[a]"], &["a"]);
+        check("a\\\\b", &["a\\b"], &["ab"]);
+    }
+
+    #[test]
+    fn glob_empty_pattern_only_matches_the_empty_string() {
+        check("", &[""], &["a", " "]);
+    }
+}
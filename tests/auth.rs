@@ -0,0 +1,48 @@
+mod common;
+
+use common::{Reply, Server};
+
+fn noauth() -> Reply {
+    Reply::Error("NOAUTH Authentication required.".to_string())
+}
+
+#[test]
+fn commands_before_auth_are_rejected() {
+    let server = Server::start(7401, &["--requirepass", "secret"]);
+    let mut client = server.client();
+
+    assert_eq!(client.call(&["PING"]), noauth());
+    assert_eq!(client.call(&["SET", "key", "value"]), noauth());
+    assert_eq!(client.call(&["GET", "key"]), noauth());
+
+    assert_eq!(
+        client.call(&["AUTH", "wrong"]),
+        Reply::Error("WRONGPASS invalid username-password pair or user is disabled.".to_string())
+    );
+    assert_eq!(client.call(&["GET", "key"]), noauth());
+
+    assert_eq!(client.call(&["AUTH", "secret"]), Reply::ok());
+    assert_eq!(client.call(&["PING"]), Reply::Simple("PONG".to_string()));
+    assert_eq!(client.call(&["GET", "key"]), Reply::Bulk(None));
+}
+
+#[test]
+fn hello_can_authenticate() {
+    let server = Server::start(7402, &["--requirepass", "secret"]);
+    let mut client = server.client();
+
+    assert_eq!(client.call(&["GET", "key"]), noauth());
+    let Reply::Array(fields) = client.call(&["HELLO", "2", "AUTH", "default", "secret"]) else {
+        panic!("HELLO should reply with its fields");
+    };
+    assert_eq!(fields[0], Reply::bulk("server"));
+    assert_eq!(client.call(&["GET", "key"]), Reply::Bulk(None));
+}
+
+#[test]
+fn no_password_needs_no_auth() {
+    let server = Server::start(7403, &[]);
+    let mut client = server.client();
+
+    assert_eq!(client.call(&["PING"]), Reply::Simple("PONG".to_string()));
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{Reply, Server};
+
+#[test]
+fn client_kill_closes_another_connection() {
+    let server = Server::start(7514, &[]);
+    let mut killer = server.client();
+    let mut victim = server.client();
+    let Reply::Integer(victim_id) = victim.call(&["CLIENT", "ID"]) else {
+        panic!("CLIENT ID should reply with an integer");
+    };
+    let victim_id = victim_id.to_string();
+
+    assert_eq!(
+        killer.call(&["CLIENT", "KILL", "ID", &victim_id]),
+        Reply::Integer(1)
+    );
+    assert!(victim.is_closed(Duration::from_secs(1)));
+    // The killer is unaffected, and the victim is gone for good
+    assert_eq!(
+        killer.call(&["CLIENT", "KILL", "ID", &victim_id]),
+        Reply::Integer(0)
+    );
+    assert_eq!(killer.call(&["PING"]), Reply::Simple("PONG".to_string()));
+}
+
+#[test]
+fn client_kill_skips_the_caller_by_default() {
+    let server = Server::start(7515, &[]);
+    let mut client = server.client();
+    let Reply::Integer(id) = client.call(&["CLIENT", "ID"]) else {
+        panic!("CLIENT ID should reply with an integer");
+    };
+    let id = id.to_string();
+
+    assert_eq!(
+        client.call(&["CLIENT", "KILL", "ID", &id]),
+        Reply::Integer(0)
+    );
+    assert_eq!(
+        client.call(&["CLIENT", "KILL", "ID", &id, "SKIPME", "no"]),
+        Reply::Integer(1)
+    );
+    assert!(client.is_closed(Duration::from_secs(1)));
+}
+
+#[test]
+fn client_no_touch_is_rejected() {
+    let server = Server::start(7516, &[]);
+    let mut client = server.client();
+
+    assert_eq!(
+        client.call(&["CLIENT", "NO-TOUCH", "ON"]),
+        Reply::Error("ERR CLIENT NO-TOUCH is not supported, keys have no access time".to_string())
+    );
+    assert_eq!(client.call(&["CLIENT", "NO-TOUCH", "OFF"]), Reply::ok());
+    assert_eq!(client.call(&["CLIENT", "NO-EVICT", "ON"]), Reply::ok());
+}
+
+#[test]
+fn idle_clients_are_closed_after_the_timeout() {
+    let server = Server::start(7521, &["--timeout", "1"]);
+    let mut idle = server.client();
+    let mut active = server.client();
+
+    for _ in 0..6 {
+        std::thread::sleep(Duration::from_millis(300));
+        assert_eq!(active.call(&["PING"]), Reply::Simple("PONG".to_string()));
+    }
+    assert!(idle.is_closed(Duration::from_secs(2)));
+    assert_eq!(active.call(&["PING"]), Reply::Simple("PONG".to_string()));
+}
+
+#[test]
+fn broadcast_tracking_pushes_invalidations() {
+    let server = Server::start(7543, &[]);
+    let mut tracker = server.client();
+    let mut writer = server.client();
+
+    assert!(matches!(tracker.call(&["HELLO", "3"]), Reply::Array(_)));
+    assert_eq!(
+        tracker.call(&["CLIENT", "TRACKING", "ON", "BCAST", "PREFIX", "user:"]),
+        Reply::ok()
+    );
+
+    writer.call(&["SET", "other", "value"]);
+    writer.call(&["SET", "user:1", "value"]);
+    assert_eq!(
+        tracker.read(),
+        Reply::Array(vec![
+            Reply::bulk("invalidate"),
+            Reply::Array(vec![Reply::bulk("user:1")])
+        ])
+    );
+    assert!(!tracker.has_pending(Duration::from_millis(200)));
+}
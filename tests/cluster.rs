@@ -0,0 +1,29 @@
+mod common;
+
+use common::{Reply, Server};
+
+#[test]
+fn cluster_client_commands_are_accepted() {
+    let server = Server::start(7524, &[]);
+    let mut client = server.client();
+
+    for command in ["ASKING", "READONLY", "READWRITE"] {
+        assert_eq!(client.call(&[command]), Reply::ok(), "{command}");
+    }
+    assert_eq!(client.call(&["SET", "key", "value"]), Reply::bulk("OK"));
+    assert_eq!(client.call(&["GET", "key"]), Reply::bulk("value"));
+}
+
+#[test]
+fn cluster_info_reports_cluster_mode_disabled() {
+    let server = Server::start(7540, &[]);
+    let mut client = server.client();
+
+    let Reply::Bulk(Some(info)) = client.call(&["CLUSTER", "INFO"]) else {
+        panic!("CLUSTER INFO should reply with a bulk string");
+    };
+    assert!(
+        info.lines().any(|line| line == "cluster_enabled:0"),
+        "{info}"
+    );
+}
@@ -0,0 +1,126 @@
+mod common;
+
+use common::{Reply, Server};
+
+/// A `COMMAND INFO` entry: name, arity, flags, first key, last key and step.
+fn info(name: &str, arity: i64, flags: &[&str], keys: (i64, i64, i64)) -> Reply {
+    Reply::Array(vec![
+        Reply::bulk(name),
+        Reply::Integer(arity),
+        Reply::Array(
+            flags
+                .iter()
+                .map(|flag| Reply::Simple(flag.to_string()))
+                .collect(),
+        ),
+        Reply::Integer(keys.0),
+        Reply::Integer(keys.1),
+        Reply::Integer(keys.2),
+    ])
+}
+
+/// The value of `name` in a map reply, which RESP2 flattens to `[key, value, ...]`.
+fn field<'a>(map: &'a Reply, name: &str) -> &'a Reply {
+    let Reply::Array(items) = map else {
+        panic!("{map:?} is not a map");
+    };
+    items
+        .chunks(2)
+        .find(|pair| pair[0] == Reply::bulk(name))
+        .map(|pair| &pair[1])
+        .unwrap_or_else(|| panic!("{map:?} has no {name}"))
+}
+
+#[test]
+fn command_info_matches_redis() {
+    let server = Server::start(7525, &[]);
+    let mut client = server.client();
+
+    // As reported by Redis 7
+    let expected = [
+        info("get", 2, &["readonly", "fast"], (1, 1, 1)),
+        info("set", -3, &["write", "denyoom"], (1, 1, 1)),
+        info("del", -2, &["write"], (1, -1, 1)),
+        info("exists", -2, &["readonly", "fast"], (1, -1, 1)),
+        info("incrby", 3, &["write", "denyoom", "fast"], (1, 1, 1)),
+        info("lcs", -3, &["readonly"], (1, 2, 1)),
+        info("ping", -1, &["fast"], (0, 0, 0)),
+        info("xadd", -5, &["write", "denyoom", "fast"], (1, 1, 1)),
+    ];
+    let Reply::Array(entries) = client.call(&[
+        "COMMAND",
+        "INFO",
+        "get",
+        "SET",
+        "del",
+        "exists",
+        "incrby",
+        "lcs",
+        "ping",
+        "xadd",
+        "nosuchcommand",
+    ]) else {
+        panic!("COMMAND INFO should reply with an array");
+    };
+    assert_eq!(entries.len(), expected.len() + 1);
+    for (entry, expected) in entries.iter().zip(&expected) {
+        assert_eq!(entry, expected);
+    }
+    assert_eq!(entries[expected.len()], Reply::Bulk(None));
+}
+
+#[test]
+fn command_docs_describe_set() {
+    let server = Server::start(7528, &[]);
+    let mut client = server.client();
+
+    let docs = client.call(&["COMMAND", "DOCS", "set"]);
+    let set = field(&docs, "set");
+    assert_eq!(field(set, "since"), &Reply::bulk("1.0.0"));
+    assert_eq!(field(set, "group"), &Reply::bulk("string"));
+    assert!(matches!(field(set, "summary"), Reply::Bulk(Some(summary)) if !summary.is_empty()));
+
+    let Reply::Array(arguments) = field(set, "arguments") else {
+        panic!("SET arguments should be an array");
+    };
+    let names: Vec<_> = arguments.iter().map(|arg| field(arg, "name")).collect();
+    assert_eq!(
+        names,
+        [
+            &Reply::bulk("key"),
+            &Reply::bulk("value"),
+            &Reply::bulk("expiration")
+        ]
+    );
+    assert_eq!(field(&arguments[0], "type"), &Reply::bulk("key"));
+    assert_eq!(field(&arguments[1], "type"), &Reply::bulk("string"));
+
+    let expiration = &arguments[2];
+    assert_eq!(field(expiration, "type"), &Reply::bulk("oneof"));
+    assert_eq!(
+        field(expiration, "flags"),
+        &Reply::Array(vec![Reply::Simple("optional".to_string())])
+    );
+    let Reply::Array(options) = field(expiration, "arguments") else {
+        panic!("expiration should have nested arguments");
+    };
+    let tokens: Vec<_> = options
+        .iter()
+        .map(|option| (field(option, "token"), field(option, "type")))
+        .collect();
+    assert_eq!(
+        tokens,
+        [
+            (&Reply::bulk("EX"), &Reply::bulk("integer")),
+            (&Reply::bulk("PX"), &Reply::bulk("integer")),
+            (&Reply::bulk("EXAT"), &Reply::bulk("unix-time")),
+            (&Reply::bulk("PXAT"), &Reply::bulk("unix-time")),
+        ]
+    );
+
+    // Unknown commands are left out
+    assert_eq!(
+        client.call(&["COMMAND", "DOCS", "nosuchcommand"]),
+        Reply::Array(vec![])
+    );
+}
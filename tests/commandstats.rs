@@ -0,0 +1,35 @@
+mod common;
+
+use common::{Reply, Server};
+
+fn cmdstat(info: &Reply, command: &str) -> Option<String> {
+    let Reply::Bulk(Some(info)) = info else {
+        panic!("INFO should reply with a bulk string, got {info:?}");
+    };
+    let prefix = format!("cmdstat_{command}:");
+    info.lines()
+        .find_map(|line| line.strip_prefix(&prefix))
+        .map(str::to_string)
+}
+
+#[test]
+fn counts_every_call() {
+    const N: usize = 25;
+    let server = Server::start(7411, &[]);
+    let mut client = server.client();
+
+    assert_eq!(
+        cmdstat(&client.call(&["INFO", "commandstats"]), "get"),
+        None
+    );
+    client.call(&["SET", "key", "value"]);
+    for _ in 0..N {
+        assert_eq!(client.call(&["GET", "key"]), Reply::bulk("value"));
+    }
+
+    let info = client.call(&["INFO", "commandstats"]);
+    let get = cmdstat(&info, "get").expect("GET should be counted");
+    assert!(get.starts_with(&format!("calls={N},")), "{get}");
+    let set = cmdstat(&info, "set").expect("SET should be counted");
+    assert!(set.starts_with("calls=1,"), "{set}");
+}
@@ -0,0 +1,300 @@
+//! Runs the server binary on a spare port and talks RESP to it, for the integration tests.
+//!
+//! Every test picks its own port so the tests can run in parallel.
+
+#![allow(dead_code)]
+
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// A server process, killed when dropped.
+pub struct Server {
+    child: Child,
+    pub port: u16,
+}
+
+impl Server {
+    /// Starts the server on `port` with the extra `args` and waits until it accepts connections.
+    pub fn start(port: u16, args: &[&str]) -> Self {
+        let child = Command::new(env!("CARGO_BIN_EXE_redis-starter-rust"))
+            .arg("--port")
+            .arg(port.to_string())
+            .args(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Can not start the server");
+        let server = Self { child, port };
+        let started = Instant::now();
+        while TcpStream::connect(("127.0.0.1", port)).is_err() {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "Server did not start listening on port {port}"
+            );
+            thread::sleep(Duration::from_millis(20));
+        }
+        server
+    }
+
+    pub fn client(&self) -> Client {
+        Client::connect(self.port)
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// A decoded reply. Maps and pushes come back as flat arrays, verbatim strings as bulk strings.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<String>),
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    pub fn bulk(value: &str) -> Self {
+        Reply::Bulk(Some(value.to_string()))
+    }
+
+    pub fn ok() -> Self {
+        Reply::Simple("OK".to_string())
+    }
+}
+
+pub struct Client {
+    reader: BufReader<TcpStream>,
+}
+
+impl Client {
+    pub fn connect(port: u16) -> Self {
+        Self::from(TcpStream::connect(("127.0.0.1", port)).expect("Can not connect"))
+    }
+
+    pub fn send(&mut self, args: &[&str]) {
+        self.reader.get_mut().write_all(&encode(args)).unwrap();
+    }
+
+    /// Writes `bytes` as they are, e.g. inline commands or malformed input.
+    pub fn send_raw(&mut self, bytes: &[u8]) {
+        self.reader.get_mut().write_all(bytes).unwrap();
+    }
+
+    pub fn call(&mut self, args: &[&str]) -> Reply {
+        self.send(args);
+        self.read()
+    }
+
+    pub fn read(&mut self) -> Reply {
+        let line = self.line();
+        let (kind, rest) = line.split_at(1);
+        match kind {
+            "+" => Reply::Simple(rest.to_string()),
+            "-" => Reply::Error(rest.to_string()),
+            ":" => Reply::Integer(rest.parse().unwrap()),
+            "$" | "=" => {
+                let len: i64 = rest.parse().unwrap();
+                if len < 0 {
+                    return Reply::Bulk(None);
+                }
+                let mut body = vec![0; len as usize + 2];
+                self.reader.read_exact(&mut body).unwrap();
+                body.truncate(len as usize);
+                let body = String::from_utf8(body).unwrap();
+                match kind {
+                    // Verbatim strings start with their format, e.g. `txt:`
+                    "=" => Reply::bulk(&body[4..]),
+                    _ => Reply::Bulk(Some(body)),
+                }
+            }
+            "*" | ">" => {
+                let len: i64 = rest.parse().unwrap();
+                Reply::Array((0..len).map(|_| self.read()).collect())
+            }
+            "%" => {
+                let len: i64 = rest.parse().unwrap();
+                Reply::Array((0..len * 2).map(|_| self.read()).collect())
+            }
+            _ => panic!("Unexpected reply {line:?}"),
+        }
+    }
+
+    /// An integer field of `MEMORY STATS`, e.g. `keys.count`.
+    pub fn memory_stat(&mut self, field: &str) -> i64 {
+        let Reply::Array(stats) = self.call(&["MEMORY", "STATS"]) else {
+            panic!("MEMORY STATS should reply with its fields");
+        };
+        let position = stats
+            .iter()
+            .position(|name| *name == Reply::bulk(field))
+            .unwrap_or_else(|| panic!("MEMORY STATS has no {field}"));
+        match stats[position + 1] {
+            Reply::Integer(value) => value,
+            ref other => panic!("Unexpected {field} {other:?}"),
+        }
+    }
+
+    /// Reads the RDB payload a master sends after `FULLRESYNC`, which has no trailing CRLF.
+    pub fn read_rdb(&mut self) -> Vec<u8> {
+        let line = self.line();
+        let len: usize = line.strip_prefix('$').unwrap().parse().unwrap();
+        let mut rdb = vec![0; len];
+        self.reader.read_exact(&mut rdb).unwrap();
+        rdb
+    }
+
+    /// Whether the server closed the connection, waiting at most `timeout` for it to do so.
+    pub fn is_closed(&mut self, timeout: Duration) -> bool {
+        self.reader
+            .get_ref()
+            .set_read_timeout(Some(timeout))
+            .unwrap();
+        match self.reader.fill_buf() {
+            Ok(buf) => buf.is_empty(),
+            // Read timeouts surface as either kind depending on the platform
+            Err(err) => !matches!(
+                err.kind(),
+                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+            ),
+        }
+    }
+
+    /// Whether something arrives within `timeout`, leaving it to be read.
+    pub fn has_pending(&mut self, timeout: Duration) -> bool {
+        self.reader
+            .get_ref()
+            .set_read_timeout(Some(timeout))
+            .unwrap();
+        let pending = matches!(self.reader.fill_buf(), Ok([_, ..]));
+        self.reader
+            .get_ref()
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        pending
+    }
+
+    /// Reads one line of a reply without its CRLF, e.g. the `%1` header of a map.
+    pub fn line(&mut self) -> String {
+        let mut line = String::new();
+        self.reader.read_line(&mut line).unwrap();
+        assert!(line.ends_with("\r\n"), "Incomplete reply {line:?}");
+        line.truncate(line.len() - 2);
+        line
+    }
+}
+
+impl From<TcpStream> for Client {
+    fn from(stream: TcpStream) -> Self {
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        Self {
+            reader: BufReader::new(stream),
+        }
+    }
+}
+
+/// Connects to `server` as a replica, returning once the initial RDB transfer is done.
+pub fn replica(server: &Server) -> Client {
+    let mut replica = server.client();
+    let Reply::Simple(fullresync) = replica.call(&["PSYNC", "?", "-1"]) else {
+        panic!("PSYNC should reply with FULLRESYNC");
+    };
+    assert!(fullresync.starts_with("FULLRESYNC "), "{fullresync}");
+    replica.read_rdb();
+    replica
+}
+
+pub fn encode(args: &[&str]) -> Vec<u8> {
+    let mut buf = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        buf.extend(format!("${}\r\n{arg}\r\n", arg.len()).as_bytes());
+    }
+    buf
+}
+
+/// Polls `check` until it holds, failing the test after a few seconds.
+pub fn eventually(mut check: impl FnMut() -> bool) {
+    let started = Instant::now();
+    while !check() {
+        assert!(
+            started.elapsed() < Duration::from_secs(5),
+            "Condition not met in time"
+        );
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+/// A value in an RDB fixture.
+pub enum RdbValue<'a> {
+    Str(&'a str),
+    List(&'a [&'a str]),
+}
+
+/// Writes an RDB file with `entries`, each with an optional expiry in unix milliseconds, to a
+/// fresh directory named after `name` and returns the directory, to be passed as `--dir` along
+/// with `--dbfilename dump.rdb`. Only short strings are supported.
+pub fn rdb_fixture(name: &str, entries: &[(&str, RdbValue, Option<i64>)]) -> String {
+    fn string(buf: &mut Vec<u8>, value: &str) {
+        assert!(value.len() < 64, "{value:?} needs a longer length encoding");
+        buf.push(value.len() as u8);
+        buf.extend(value.as_bytes());
+    }
+
+    let mut rdb = b"REDIS0011\xFA\xFE\x00\xFB".to_vec();
+    rdb.push(entries.len() as u8);
+    rdb.push(
+        entries
+            .iter()
+            .filter(|(_, _, expiry)| expiry.is_some())
+            .count() as u8,
+    );
+    for (key, value, expiry) in entries {
+        if let Some(expiry) = expiry {
+            rdb.push(0xFC);
+            rdb.extend((*expiry as u64).to_le_bytes());
+        }
+        match value {
+            RdbValue::Str(value) => {
+                rdb.push(0);
+                string(&mut rdb, key);
+                string(&mut rdb, value);
+            }
+            RdbValue::List(items) => {
+                rdb.push(1);
+                string(&mut rdb, key);
+                rdb.push(items.len() as u8);
+                for item in *items {
+                    string(&mut rdb, item);
+                }
+            }
+        }
+    }
+    rdb.push(0xFF);
+    rdb.extend([0; 8]);
+
+    let dir = std::env::temp_dir().join(format!("redis-tests-{name}-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("dump.rdb"), rdb).unwrap();
+    dir.to_str().unwrap().to_string()
+}
+
+/// Milliseconds since the unix epoch.
+pub fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64
+}
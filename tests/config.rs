@@ -0,0 +1,43 @@
+mod common;
+
+use common::{Reply, Server};
+
+#[test]
+fn unknown_config_subcommands_are_errors() {
+    let server = Server::start(7509, &[]);
+    let mut client = server.client();
+
+    assert_eq!(
+        client.call(&["CONFIG", "SET", "foo", "bar"]),
+        Reply::Error("ERR unknown subcommand 'SET'. Try CONFIG HELP.".to_string())
+    );
+    assert_eq!(
+        client.call(&["CONFIG", "nosuch"]),
+        Reply::Error("ERR unknown subcommand 'nosuch'. Try CONFIG HELP.".to_string())
+    );
+    assert_eq!(
+        client.call(&["CONFIG", "GET", "dir", "dbfilename"]),
+        Reply::Error("ERR wrong number of arguments for 'config|get' command".to_string())
+    );
+    assert_eq!(client.call(&["PING"]), Reply::Simple("PONG".to_string()));
+}
+
+#[test]
+fn config_get_leaves_out_unset_parameters() {
+    let server = Server::start(7510, &[]);
+    let mut client = server.client();
+    assert_eq!(client.call(&["CONFIG", "GET", "dir"]), Reply::Array(vec![]));
+
+    let dir = std::env::temp_dir();
+    let dir = dir.to_str().unwrap();
+    let server = Server::start(7511, &["--dir", dir, "--dbfilename", "dump.rdb"]);
+    let mut client = server.client();
+    assert_eq!(
+        client.call(&["config", "get", "DIR"]),
+        Reply::Array(vec![Reply::bulk("dir"), Reply::bulk(dir)])
+    );
+    assert_eq!(
+        client.call(&["CONFIG", "GET", "dbfilename"]),
+        Reply::Array(vec![Reply::bulk("dbfilename"), Reply::bulk("dump.rdb")])
+    );
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{eventually, Reply, Server};
+
+#[test]
+fn reload_keeps_values_and_expiries() {
+    let server = Server::start(7431, &[]);
+    let mut client = server.client();
+
+    client.call(&["SET", "name", "redis"]);
+    client.call(&["SET", "counter", "12"]);
+    client.call(&["SET", "session", "token", "PX", "500"]);
+    assert_eq!(client.call(&["DEBUG", "RELOAD"]), Reply::ok());
+
+    assert_eq!(client.call(&["GET", "name"]), Reply::bulk("redis"));
+    assert_eq!(client.call(&["GET", "counter"]), Reply::bulk("12"));
+    assert_eq!(client.call(&["GET", "session"]), Reply::bulk("token"));
+    // The expiry survived the reload, so the key still goes away
+    eventually(|| client.call(&["GET", "session"]) == Reply::Bulk(None));
+    assert_eq!(client.call(&["GET", "name"]), Reply::bulk("redis"));
+}
+
+#[test]
+fn reload_refuses_streams() {
+    let server = Server::start(7432, &[]);
+    let mut client = server.client();
+
+    client.call(&["XADD", "events", "1-1", "field", "value"]);
+    assert_eq!(
+        client.call(&["DEBUG", "RELOAD"]),
+        Reply::Error(
+            "ERR Error trying to reload the dataset: Can not save values of type stream"
+                .to_string()
+        )
+    );
+    assert_eq!(
+        client.call(&["TYPE", "events"]),
+        Reply::Simple("stream".to_string())
+    );
+}
+
+#[test]
+fn object_describes_streams() {
+    let server = Server::start(7433, &[]);
+    let mut client = server.client();
+
+    for id in ["1-1", "1-2", "5-0"] {
+        client.call(&["XADD", "events", id, "field", "value"]);
+    }
+    let Reply::Simple(object) = client.call(&["DEBUG", "OBJECT", "events"]) else {
+        panic!("DEBUG OBJECT should reply with a status line");
+    };
+    let fields: Vec<_> = object.split(' ').collect();
+    assert!(fields.contains(&"entries:3"), "{object}");
+    assert!(fields.contains(&"last_id:5-0"), "{object}");
+    assert!(fields.contains(&"radix_tree_nodes:1"), "{object}");
+
+    client.call(&["SET", "name", "redis"]);
+    assert_eq!(
+        client.call(&["DEBUG", "OBJECT", "name"]),
+        Reply::Error(
+            "WRONGTYPE Operation against a key holding the wrong kind of value".to_string()
+        )
+    );
+    assert_eq!(
+        client.call(&["DEBUG", "OBJECT", "missing"]),
+        Reply::Error("ERR no such key".to_string())
+    );
+}
+
+#[test]
+fn stringmatch_len_reports_glob_matches() {
+    let server = Server::start(7508, &[]);
+    let mut client = server.client();
+
+    for (pattern, string, matched) in [
+        ("h[a-c]llo", "hbllo", 1),
+        ("h[^e]llo", "hello", 0),
+        ("h\\*llo", "h*llo", 1),
+        ("user:*", "user:1000", 1),
+        ("", "", 1),
+        ("", "a", 0),
+    ] {
+        assert_eq!(
+            client.call(&["DEBUG", "STRINGMATCH-LEN", pattern, string]),
+            Reply::Integer(matched),
+            "{pattern:?} against {string:?}"
+        );
+    }
+}
+
+#[test]
+fn debug_sleep_only_blocks_its_own_connection() {
+    let server = Server::start(7530, &[]);
+    let mut sleeper = server.client();
+    let mut client = server.client();
+
+    let started = Instant::now();
+    sleeper.send(&["DEBUG", "SLEEP", "1"]);
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(client.call(&["PING"]), Reply::Simple("PONG".to_string()));
+    assert!(started.elapsed() < Duration::from_millis(500));
+
+    assert_eq!(sleeper.read(), Reply::ok());
+    assert!(started.elapsed() >= Duration::from_secs(1));
+}
+
+#[test]
+fn debug_flushall_leaves_a_clean_state() {
+    let server = Server::start(7547, &[]);
+    let mut client = server.client();
+
+    for _ in 0..2 {
+        assert_eq!(client.call(&["KEYS", "*"]), Reply::Array(vec![]));
+        client.call(&["SET", "key", "value", "PX", "300"]);
+        client.call(&["SET", "counter", "1"]);
+        client.call(&["XADD", "stream", "1-1", "field", "value"]);
+        assert_eq!(client.memory_stat("keys.count"), 3);
+        assert_eq!(client.call(&["DEBUG", "FLUSHALL"]), Reply::ok());
+        assert_eq!(client.memory_stat("keys.count"), 0);
+    }
+
+    // No expiry is left behind to delete a key recreated under the same name
+    client.call(&["SET", "key", "value"]);
+    std::thread::sleep(Duration::from_millis(400));
+    assert_eq!(client.call(&["GET", "key"]), Reply::bulk("value"));
+    assert_eq!(
+        client.call(&["XADD", "stream", "1-1", "field", "value"]),
+        Reply::bulk("1-1")
+    );
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use common::{Reply, Server};
+
+fn pair(start: i64, end: i64) -> Reply {
+    Reply::Array(vec![Reply::Integer(start), Reply::Integer(end)])
+}
+
+#[test]
+fn documented_examples() {
+    let server = Server::start(7471, &[]);
+    let mut client = server.client();
+    client.call(&["SET", "key1", "ohmytext"]);
+    client.call(&["SET", "key2", "mynewtext"]);
+
+    assert_eq!(client.call(&["LCS", "key1", "key2"]), Reply::bulk("mytext"));
+    assert_eq!(
+        client.call(&["LCS", "key1", "key2", "LEN"]),
+        Reply::Integer(6)
+    );
+    assert_eq!(
+        client.call(&["LCS", "key1", "key2", "IDX"]),
+        Reply::Array(vec![
+            Reply::bulk("matches"),
+            Reply::Array(vec![
+                Reply::Array(vec![pair(4, 7), pair(5, 8)]),
+                Reply::Array(vec![pair(2, 3), pair(0, 1)]),
+            ]),
+            Reply::bulk("len"),
+            Reply::Integer(6),
+        ])
+    );
+    assert_eq!(
+        client.call(&[
+            "LCS",
+            "key1",
+            "key2",
+            "IDX",
+            "MINMATCHLEN",
+            "4",
+            "WITHMATCHLEN"
+        ]),
+        Reply::Array(vec![
+            Reply::bulk("matches"),
+            Reply::Array(vec![Reply::Array(vec![
+                pair(4, 7),
+                pair(5, 8),
+                Reply::Integer(4)
+            ])]),
+            Reply::bulk("len"),
+            Reply::Integer(6),
+        ])
+    );
+}
+
+#[test]
+fn transient_memory_is_capped() {
+    let server = Server::start(7472, &["--proto-max-bulk-len", "1000"]);
+    let mut client = server.client();
+    client.call(&["SET", "a", &"a".repeat(20)]);
+    client.call(&["SET", "b", &"b".repeat(20)]);
+
+    assert_eq!(
+        client.call(&["LCS", "a", "b"]),
+        Reply::Error(
+            "ERR Insufficient memory, transient memory for LCS exceeds proto-max-bulk-len"
+                .to_string()
+        )
+    );
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use common::{rdb_fixture, RdbValue, Reply, Server};
+
+/// The elements of the list at `key`, from `DEBUG DUMP-KEY`.
+fn elements(client: &mut common::Client, key: &str) -> Vec<String> {
+    let Reply::Bulk(Some(dump)) = client.call(&["DEBUG", "DUMP-KEY", key]) else {
+        panic!("{key} should exist");
+    };
+    let dump: serde_json::Value = serde_json::from_str(&dump).unwrap();
+    assert_eq!(dump["type"], "list");
+    serde_json::from_value(dump["sample"].clone()).unwrap()
+}
+
+#[test]
+fn pushx_only_pushes_onto_existing_lists() {
+    let dir = rdb_fixture("pushx", &[("list", RdbValue::List(&["a", "b"]), None)]);
+    let server = Server::start(7512, &["--dir", &dir, "--dbfilename", "dump.rdb"]);
+    let mut client = server.client();
+
+    assert_eq!(
+        client.call(&["LPUSHX", "list", "x", "y"]),
+        Reply::Integer(4)
+    );
+    assert_eq!(client.call(&["RPUSHX", "list", "z"]), Reply::Integer(5));
+    assert_eq!(elements(&mut client, "list"), ["y", "x", "a", "b", "z"]);
+
+    assert_eq!(client.call(&["LPUSHX", "missing", "x"]), Reply::Integer(0));
+    assert_eq!(client.call(&["RPUSHX", "missing", "x"]), Reply::Integer(0));
+    assert_eq!(client.call(&["EXISTS", "missing"]), Reply::Integer(0));
+}
+
+#[test]
+fn pushx_rejects_other_types() {
+    let server = Server::start(7513, &[]);
+    let mut client = server.client();
+    let wrong_type = Reply::Error(
+        "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
+    );
+
+    client.call(&["SET", "string", "value"]);
+    assert_eq!(client.call(&["LPUSHX", "string", "x"]), wrong_type);
+    assert_eq!(client.call(&["RPUSHX", "string", "x"]), wrong_type);
+    assert_eq!(client.call(&["GET", "string"]), Reply::bulk("value"));
+}
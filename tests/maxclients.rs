@@ -0,0 +1,37 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{eventually, Reply, Server};
+
+fn connected_clients(client: &mut common::Client) -> i64 {
+    client.memory_stat("clients.normal")
+}
+
+#[test]
+fn connections_beyond_maxclients_are_rejected() {
+    let server = Server::start(7421, &["--maxclients", "3"]);
+    let mut first = server.client();
+    // The connection used to wait for the server to start may not be unregistered yet
+    eventually(|| connected_clients(&mut first) == 1);
+
+    let mut clients = vec![first];
+    for _ in 1..3 {
+        let mut client = server.client();
+        assert_eq!(client.call(&["PING"]), Reply::Simple("PONG".to_string()));
+        clients.push(client);
+    }
+
+    let mut rejected = server.client();
+    assert_eq!(
+        rejected.read(),
+        Reply::Error("ERR max number of clients reached".to_string())
+    );
+    assert!(rejected.is_closed(Duration::from_secs(5)));
+
+    // Room frees up once a client disconnects
+    drop(clients.pop());
+    eventually(|| connected_clients(&mut clients[0]) == 2);
+    let mut client = server.client();
+    assert_eq!(client.call(&["PING"]), Reply::Simple("PONG".to_string()));
+}
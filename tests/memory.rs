@@ -0,0 +1,18 @@
+mod common;
+
+use common::Server;
+
+#[test]
+fn memory_stats_counts_keys() {
+    let server = Server::start(7531, &[]);
+    let mut client = server.client();
+    assert_eq!(client.memory_stat("keys.count"), 0);
+
+    for key in ["a", "b", "c", "d", "e"] {
+        client.call(&["SET", key, "value"]);
+    }
+    client.call(&["SET", "a", "overwritten"]);
+    client.call(&["DEL", "b"]);
+    client.call(&["XADD", "stream", "*", "field", "value"]);
+    assert_eq!(client.memory_stat("keys.count"), 5);
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use common::{Client, Reply, Server};
+
+fn encoding(client: &mut Client, key: &str) -> Reply {
+    client.call(&["OBJECT", "ENCODING", key])
+}
+
+#[test]
+fn encoding_follows_writes() {
+    let server = Server::start(7441, &[]);
+    let mut client = server.client();
+
+    client.call(&["SET", "n", "12345"]);
+    client.call(&["SET", "embstr", &"x".repeat(44)]);
+    client.call(&["SET", "raw", &"x".repeat(45)]);
+    assert_eq!(encoding(&mut client, "n"), Reply::bulk("int"));
+    assert_eq!(encoding(&mut client, "embstr"), Reply::bulk("embstr"));
+    assert_eq!(encoding(&mut client, "raw"), Reply::bulk("raw"));
+
+    client.call(&["APPEND", "n", "6"]);
+    assert_eq!(encoding(&mut client, "n"), Reply::bulk("raw"));
+    client.call(&["SET", "n", "12"]);
+    assert_eq!(encoding(&mut client, "n"), Reply::bulk("int"));
+    client.call(&["INCR", "n"]);
+    assert_eq!(encoding(&mut client, "n"), Reply::bulk("int"));
+}
+
+#[test]
+fn small_integers_report_the_shared_refcount() {
+    let server = Server::start(7442, &[]);
+    let mut client = server.client();
+    let shared = Reply::Integer(i32::MAX as i64);
+
+    client.call(&["SET", "a", "100"]);
+    client.call(&["SET", "b", "100"]);
+    assert_eq!(client.call(&["OBJECT", "REFCOUNT", "a"]), shared);
+    assert_eq!(client.call(&["OBJECT", "REFCOUNT", "b"]), shared);
+
+    client.call(&["SET", "large", "10000"]);
+    client.call(&["SET", "text", "hello"]);
+    assert_eq!(
+        client.call(&["OBJECT", "REFCOUNT", "large"]),
+        Reply::Integer(1)
+    );
+    assert_eq!(
+        client.call(&["OBJECT", "REFCOUNT", "text"]),
+        Reply::Integer(1)
+    );
+}
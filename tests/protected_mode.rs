@@ -0,0 +1,62 @@
+mod common;
+
+use std::{
+    net::{IpAddr, TcpStream, UdpSocket},
+    time::Duration,
+};
+
+use common::{Client, Reply, Server};
+
+/// An address of this host other than loopback, if it has one.
+fn external_address() -> Option<IpAddr> {
+    // Connecting a UDP socket only picks the route, nothing is sent
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("192.0.2.254:9").ok()?;
+    let ip = socket.local_addr().ok()?.ip();
+    (!ip.is_loopback() && !ip.is_unspecified()).then_some(ip)
+}
+
+fn connect(ip: IpAddr, port: u16) -> Client {
+    Client::from(TcpStream::connect((ip, port)).expect("Can not connect"))
+}
+
+fn is_denied(reply: Reply) -> bool {
+    matches!(reply, Reply::Error(message) if message.starts_with("DENIED Redis is running in protected mode"))
+}
+
+#[test]
+fn only_loopback_clients_are_served_without_a_password() {
+    let Some(external) = external_address() else {
+        eprintln!("No external interface to connect from, skipping");
+        return;
+    };
+    let server = Server::start(7451, &["--bind", "0.0.0.0"]);
+
+    let mut local = server.client();
+    assert_eq!(local.call(&["PING"]), Reply::Simple("PONG".to_string()));
+
+    let mut remote = connect(external, server.port);
+    assert!(is_denied(remote.read()));
+    assert!(remote.is_closed(Duration::from_secs(5)));
+
+    // Setting a password for the default user lifts the protection
+    assert_eq!(
+        local.call(&["ACL", "SETUSER", "default", ">secret"]),
+        Reply::ok()
+    );
+    let mut remote = connect(external, server.port);
+    assert_eq!(remote.call(&["AUTH", "secret"]), Reply::ok());
+    assert_eq!(remote.call(&["PING"]), Reply::Simple("PONG".to_string()));
+}
+
+#[test]
+fn protected_mode_can_be_disabled() {
+    let Some(external) = external_address() else {
+        eprintln!("No external interface to connect from, skipping");
+        return;
+    };
+    let server = Server::start(7452, &["--bind", "0.0.0.0", "--protected-mode", "no"]);
+
+    let mut remote = connect(external, server.port);
+    assert_eq!(remote.call(&["PING"]), Reply::Simple("PONG".to_string()));
+}
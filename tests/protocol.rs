@@ -0,0 +1,38 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{Reply, Server};
+
+#[test]
+fn inline_commands_and_empty_lines() {
+    let server = Server::start(7538, &[]);
+    let mut client = server.client();
+    let pong = Reply::Simple("PONG".to_string());
+
+    client.send_raw(b"\r\n");
+    assert!(!client.has_pending(Duration::from_millis(200)));
+    client.send_raw(b"PING\r\n");
+    assert_eq!(client.read(), pong);
+    client.send_raw(b"\r\n\r\nECHO hello\r\n");
+    assert_eq!(client.read(), Reply::bulk("hello"));
+    assert_eq!(client.call(&["PING"]), pong);
+}
+
+#[test]
+fn maps_are_flattened_for_resp2() {
+    let server = Server::start(7548, &["--dir", "/tmp"]);
+    let mut client = server.client();
+
+    client.call(&["HELLO", "3"]);
+    client.send(&["CONFIG", "GET", "dir"]);
+    assert_eq!(client.line(), "%1");
+    assert_eq!(client.read(), Reply::bulk("dir"));
+    assert_eq!(client.read(), Reply::bulk("/tmp"));
+
+    client.call(&["HELLO", "2"]);
+    client.send(&["CONFIG", "GET", "dir"]);
+    assert_eq!(client.line(), "*2");
+    assert_eq!(client.read(), Reply::bulk("dir"));
+    assert_eq!(client.read(), Reply::bulk("/tmp"));
+}
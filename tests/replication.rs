@@ -0,0 +1,295 @@
+mod common;
+
+use std::{net::TcpStream, thread, time::Duration};
+
+use common::{encode, eventually, now_ms, rdb_fixture, replica, RdbValue, Reply, Server};
+
+/// The master's replication offset, from `DEBUG REPLICATION-OFFSET`.
+fn master_offset(client: &mut common::Client) -> usize {
+    let Reply::Array(offsets) = client.call(&["DEBUG", "REPLICATION-OFFSET"]) else {
+        panic!("DEBUG REPLICATION-OFFSET should reply with an array");
+    };
+    let Reply::Integer(offset) = offsets[0] else {
+        panic!(
+            "The master offset should be an integer, got {:?}",
+            offsets[0]
+        );
+    };
+    offset as usize
+}
+
+#[test]
+fn set_with_relative_expiry_propagates_as_pxat() {
+    let server = Server::start(7461, &[]);
+    let mut replica = replica(&server);
+    let mut client = server.client();
+
+    let before = now_ms();
+    client.call(&["SET", "key", "value", "EX", "100"]);
+    let after = now_ms();
+
+    let Reply::Array(propagated) = replica.read() else {
+        panic!("Writes should be propagated as arrays");
+    };
+    assert_eq!(
+        propagated[..4],
+        [
+            Reply::bulk("SET"),
+            Reply::bulk("key"),
+            Reply::bulk("value"),
+            Reply::bulk("PXAT"),
+        ]
+    );
+    let Reply::Bulk(Some(expires_at)) = &propagated[4] else {
+        panic!(
+            "PXAT should be followed by a bulk string, got {:?}",
+            propagated[4]
+        );
+    };
+    let expires_at: i64 = expires_at.parse().unwrap();
+    assert!((before + 100_000..=after + 100_000).contains(&expires_at));
+}
+
+#[test]
+fn incrby_propagates_its_increment_as_a_bulk_string() {
+    let server = Server::start(7462, &[]);
+    let mut replica = replica(&server);
+    let mut client = server.client();
+
+    assert_eq!(
+        client.call(&["INCRBY", "counter", "-5"]),
+        Reply::Integer(-5)
+    );
+    assert_eq!(
+        replica.read(),
+        Reply::Array(vec![
+            Reply::bulk("INCRBY"),
+            Reply::bulk("counter"),
+            Reply::bulk("-5"),
+        ])
+    );
+}
+
+#[test]
+fn replicas_keep_clock_expired_keys_until_the_master_deletes_them() {
+    let master = Server::start(7504, &[]);
+    let replica = Server::start(7505, &["--replicaof", "127.0.0.1 7504"]);
+    let mut master_client = master.client();
+    let mut client = replica.client();
+    assert_eq!(
+        master_client.call(&["DEBUG", "SET-ACTIVE-EXPIRE", "0"]),
+        Reply::ok()
+    );
+
+    master_client.call(&["SET", "key", "value", "PX", "200"]);
+    eventually(|| client.call(&["GET", "key"]) == Reply::bulk("value"));
+    thread::sleep(Duration::from_millis(300));
+    // Reads hide the key once the replica's clock passes the deadline, but it stays stored
+    assert_eq!(client.call(&["GET", "key"]), Reply::Bulk(None));
+    assert_eq!(client.memory_stat("keys.count"), 1);
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(client.memory_stat("keys.count"), 1);
+
+    assert_eq!(
+        master_client.call(&["DEBUG", "SET-ACTIVE-EXPIRE", "1"]),
+        Reply::ok()
+    );
+    eventually(|| client.memory_stat("keys.count") == 0);
+}
+
+#[test]
+fn expired_keys_are_deleted_on_replicas_once_active_expire_resumes() {
+    let master = Server::start(7506, &[]);
+    let mut fake_replica = replica(&master);
+    let real_replica = Server::start(7507, &["--replicaof", "127.0.0.1 7506"]);
+    let mut client = master.client();
+    let mut replica_client = real_replica.client();
+    assert_eq!(
+        client.call(&["DEBUG", "SET-ACTIVE-EXPIRE", "0"]),
+        Reply::ok()
+    );
+
+    client.call(&["SET", "key", "value", "PX", "100"]);
+    fake_replica.read();
+    eventually(|| replica_client.memory_stat("keys.count") == 1);
+    thread::sleep(Duration::from_millis(300));
+    assert!(!fake_replica.has_pending(Duration::from_millis(200)));
+    assert_eq!(replica_client.memory_stat("keys.count"), 1);
+
+    assert_eq!(
+        client.call(&["DEBUG", "SET-ACTIVE-EXPIRE", "1"]),
+        Reply::ok()
+    );
+    assert_eq!(
+        fake_replica.read(),
+        Reply::Array(vec![Reply::bulk("DEL"), Reply::bulk("key")])
+    );
+    eventually(|| replica_client.memory_stat("keys.count") == 0);
+}
+
+#[test]
+fn keys_dropped_at_startup_are_not_propagated() {
+    let dir = rdb_fixture(
+        "startup-drop",
+        &[
+            ("stale", RdbValue::Str("old"), Some(now_ms() - 1000)),
+            ("kept", RdbValue::Str("value"), None),
+        ],
+    );
+    let server = Server::start(7517, &["--dir", &dir, "--dbfilename", "dump.rdb"]);
+    let mut fake_replica = replica(&server);
+    let mut client = server.client();
+    assert_eq!(client.call(&["EXISTS", "stale"]), Reply::Integer(0));
+    assert_eq!(client.call(&["GET", "stale"]), Reply::Bulk(None));
+    assert_eq!(client.call(&["EXISTS", "kept"]), Reply::Integer(1));
+    assert!(!fake_replica.has_pending(Duration::from_millis(300)));
+}
+
+#[test]
+fn shutdown_waits_for_replicas_to_acknowledge_the_final_offset() {
+    let server = Server::start(7518, &["--shutdown-timeout", "5"]);
+    let mut fake_replica = replica(&server);
+    let mut client = server.client();
+
+    client.call(&["SET", "key", "value"]);
+    assert_eq!(
+        fake_replica.read(),
+        Reply::Array(vec![
+            Reply::bulk("SET"),
+            Reply::bulk("key"),
+            Reply::bulk("value")
+        ])
+    );
+    let offset = master_offset(&mut client);
+    // Everything the master wrote was propagated to the replica
+    assert_eq!(offset, encode(&["SET", "key", "value"]).len());
+
+    client.send(&["SHUTDOWN"]);
+    assert_eq!(
+        fake_replica.read(),
+        Reply::Array(vec![
+            Reply::bulk("REPLCONF"),
+            Reply::bulk("GETACK"),
+            Reply::bulk("*")
+        ])
+    );
+    thread::sleep(Duration::from_millis(300));
+    assert!(
+        TcpStream::connect(("127.0.0.1", server.port)).is_ok(),
+        "The master exited before the replica acknowledged the final offset"
+    );
+
+    fake_replica.send(&["REPLCONF", "ACK", &offset.to_string()]);
+    eventually(|| TcpStream::connect(("127.0.0.1", server.port)).is_err());
+}
+
+/// The `slave0:` line of `INFO replication`, if there is one.
+fn first_replica_info(client: &mut common::Client) -> Option<String> {
+    let Reply::Bulk(Some(info)) = client.call(&["INFO", "replication"]) else {
+        panic!("INFO should reply with a bulk string");
+    };
+    info.lines()
+        .find_map(|line| line.strip_prefix("slave0:"))
+        .map(str::to_string)
+}
+
+#[test]
+fn info_replication_reports_replica_offsets() {
+    let server = Server::start(7532, &[]);
+    let mut client = server.client();
+    assert_eq!(first_replica_info(&mut client), None);
+
+    let mut fake_replica = replica(&server);
+    client.call(&["SET", "key", "value"]);
+    fake_replica.read();
+    let offset = encode(&["SET", "key", "value"]).len().to_string();
+    fake_replica.send(&["REPLCONF", "ACK", &offset]);
+
+    eventually(|| {
+        first_replica_info(&mut client).is_some_and(|info| {
+            info.split(',')
+                .any(|field| field == format!("offset={offset}"))
+                && info.split(',').any(|field| field == "state=online")
+        })
+    });
+}
+
+#[test]
+fn writes_that_change_nothing_are_not_propagated() {
+    let server = Server::start(7539, &[]);
+    let mut fake_replica = replica(&server);
+    let mut client = server.client();
+
+    assert_eq!(client.call(&["DEL", "missing"]), Reply::Integer(0));
+    assert_eq!(client.call(&["LPUSHX", "missing", "a"]), Reply::Integer(0));
+    assert_eq!(client.call(&["RPUSHX", "missing", "a"]), Reply::Integer(0));
+    assert!(!fake_replica.has_pending(Duration::from_millis(200)));
+
+    client.call(&["SET", "key", "value"]);
+    assert_eq!(client.call(&["DEL", "key", "missing"]), Reply::Integer(1));
+    assert_eq!(
+        fake_replica.read(),
+        Reply::Array(vec![
+            Reply::bulk("SET"),
+            Reply::bulk("key"),
+            Reply::bulk("value")
+        ])
+    );
+    assert!(
+        matches!(fake_replica.read(), Reply::Array(command) if command[0] == Reply::bulk("DEL"))
+    );
+}
+
+#[test]
+fn failover_is_refused() {
+    let server = Server::start(7541, &[]);
+    let mut client = server.client();
+    assert_eq!(
+        client.call(&["FAILOVER"]),
+        Reply::Error("ERR FAILOVER requires connected replicas.".to_string())
+    );
+
+    let real_replica = Server::start(7542, &["--replicaof", "127.0.0.1 7541"]);
+    let _fake_replica = replica(&server);
+    assert_eq!(
+        client.call(&["FAILOVER"]),
+        Reply::Error("ERR FAILOVER is not supported by this server.".to_string())
+    );
+    assert_eq!(
+        real_replica.client().call(&["FAILOVER"]),
+        Reply::Error("ERR FAILOVER is not valid when server is a replica.".to_string())
+    );
+}
+
+#[test]
+fn debug_replication_offset_grows_by_the_propagated_bytes() {
+    let server = Server::start(7544, &[]);
+    let mut client = server.client();
+    let before = master_offset(&mut client);
+    client.call(&["SET", "key", "value"]);
+    assert_eq!(
+        master_offset(&mut client),
+        before + encode(&["SET", "key", "value"]).len()
+    );
+    // Reads don't move the offset
+    client.call(&["GET", "key"]);
+    assert_eq!(
+        master_offset(&mut client),
+        before + encode(&["SET", "key", "value"]).len()
+    );
+}
+
+#[test]
+fn replicas_report_their_master_link_as_up() {
+    let _master = Server::start(7545, &[]);
+    let real_replica = Server::start(7546, &["--replicaof", "127.0.0.1 7545"]);
+    let mut client = real_replica.client();
+
+    eventually(|| {
+        let Reply::Bulk(Some(info)) = client.call(&["INFO", "replication"]) else {
+            panic!("INFO should reply with a bulk string");
+        };
+        info.lines().any(|line| line == "role:slave")
+            && info.lines().any(|line| line == "master_link_status:up")
+    });
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use common::{Reply, Server};
+
+#[test]
+fn xadd_nomkstream_does_not_create_streams() {
+    let server = Server::start(7526, &[]);
+    let mut client = server.client();
+
+    assert_eq!(
+        client.call(&["XADD", "missing", "NOMKSTREAM", "*", "field", "value"]),
+        Reply::Bulk(None)
+    );
+    assert_eq!(client.call(&["EXISTS", "missing"]), Reply::Integer(0));
+    assert_eq!(
+        client.call(&["TYPE", "missing"]),
+        Reply::Simple("none".to_string())
+    );
+
+    assert_eq!(
+        client.call(&["XADD", "stream", "1-1", "field", "value"]),
+        Reply::bulk("1-1")
+    );
+    assert_eq!(
+        client.call(&["XADD", "stream", "NOMKSTREAM", "1-2", "field", "value"]),
+        Reply::bulk("1-2")
+    );
+}
+
+#[test]
+fn stream_commands_reject_other_types() {
+    let server = Server::start(7533, &[]);
+    let mut client = server.client();
+    let wrong_type = Reply::Error(
+        "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
+    );
+
+    client.call(&["SET", "string", "value"]);
+    assert_eq!(
+        client.call(&["XADD", "string", "*", "field", "value"]),
+        wrong_type
+    );
+    assert_eq!(client.call(&["XRANGE", "string", "-", "+"]), wrong_type);
+    assert_eq!(
+        client.call(&["XREAD", "STREAMS", "string", "0-0"]),
+        wrong_type
+    );
+    assert_eq!(client.call(&["GET", "string"]), Reply::bulk("value"));
+}
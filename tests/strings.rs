@@ -0,0 +1,188 @@
+mod common;
+
+use std::{thread, time::Duration};
+
+use common::{eventually, now_ms, rdb_fixture, RdbValue, Reply, Server};
+
+#[test]
+fn recreated_keys_do_not_inherit_stale_expiries() {
+    let master = Server::start(7481, &[]);
+    let replica = Server::start(7483, &["--replicaof", "127.0.0.1 7481"]);
+    let mut master_client = master.client();
+    let mut client = replica.client();
+    // Otherwise the master deletes the expired key on the replica before the writes below
+    assert_eq!(
+        master_client.call(&["DEBUG", "SET-ACTIVE-EXPIRE", "0"]),
+        Reply::ok()
+    );
+
+    let recreate: [&[&str]; 4] = [
+        &["INCR", "key"],
+        &["DECR", "key"],
+        &["INCRBY", "key", "5"],
+        &["APPEND", "key", "value"],
+    ];
+    for command in recreate {
+        master_client.call(&["SET", "key", "1", "PX", "300"]);
+        eventually(|| client.call(&["GET", "key"]) == Reply::bulk("1"));
+        // Replicas keep expired keys until the master deletes them, and writes replace them
+        thread::sleep(Duration::from_millis(400));
+        let reply = client.call(command);
+        assert!(
+            matches!(reply, Reply::Integer(_)),
+            "{command:?} replied {reply:?}"
+        );
+        // A leftover expiry is due already, so the new value would read as expired
+        assert!(
+            matches!(client.call(&["GET", "key"]), Reply::Bulk(Some(_))),
+            "{command:?} recreated key inherited the old expiry"
+        );
+        master_client.call(&["DEL", "key"]);
+        eventually(|| client.call(&["GET", "key"]) == Reply::Bulk(None));
+    }
+}
+
+#[test]
+fn append_is_capped_at_proto_max_bulk_len() {
+    let server = Server::start(7482, &["--proto-max-bulk-len", "10"]);
+    let mut client = server.client();
+    let too_long = Reply::Error("ERR string exceeds maximum allowed size".to_string());
+
+    assert_eq!(client.call(&["APPEND", "key", "12345678901"]), too_long);
+    assert_eq!(client.call(&["GET", "key"]), Reply::Bulk(None));
+    assert_eq!(client.call(&["APPEND", "key", "123456"]), Reply::Integer(6));
+    assert_eq!(client.call(&["APPEND", "key", "7890"]), Reply::Integer(10));
+    assert_eq!(client.call(&["APPEND", "key", "1"]), too_long);
+    assert_eq!(client.call(&["GET", "key"]), Reply::bulk("1234567890"));
+}
+
+#[test]
+fn set_px_expires_after_the_given_milliseconds() {
+    let server = Server::start(7501, &[]);
+    let mut client = server.client();
+
+    assert_eq!(
+        client.call(&["SET", "key", "value", "PX", "100"]),
+        Reply::bulk("OK")
+    );
+    assert_eq!(client.call(&["GET", "key"]), Reply::bulk("value"));
+    thread::sleep(Duration::from_millis(150));
+    assert_eq!(client.call(&["GET", "key"]), Reply::Bulk(None));
+}
+
+#[test]
+fn set_rejects_invalid_expire_times() {
+    let server = Server::start(7502, &[]);
+    let mut client = server.client();
+    let invalid = Reply::Error("ERR invalid expire time in 'set' command".to_string());
+
+    for (option, value) in [
+        ("EX", "0"),
+        ("PX", "-5"),
+        ("EX", "9223372036854775807"),
+        ("PX", "9223372036854775807"),
+        ("EXAT", "9223372036854776"),
+    ] {
+        assert_eq!(
+            client.call(&["SET", "key", "value", option, value]),
+            invalid,
+            "{option} {value}"
+        );
+    }
+    assert_eq!(client.call(&["GET", "key"]), Reply::Bulk(None));
+}
+
+#[test]
+fn keys_loaded_past_their_expiry_are_gone_at_startup() {
+    let dir = rdb_fixture(
+        "past-expiry",
+        &[
+            ("stale", RdbValue::Str("old"), Some(now_ms() - 1000)),
+            ("fresh", RdbValue::Str("new"), Some(now_ms() + 60_000)),
+        ],
+    );
+    let server = Server::start(7503, &["--dir", &dir, "--dbfilename", "dump.rdb"]);
+    let mut client = server.client();
+
+    assert_eq!(client.call(&["EXISTS", "stale"]), Reply::Integer(0));
+    assert_eq!(
+        client.call(&["KEYS", "*"]),
+        Reply::Array(vec![Reply::bulk("fresh")])
+    );
+}
+
+#[test]
+fn empty_strings_are_not_nil() {
+    let server = Server::start(7519, &[]);
+    let mut client = server.client();
+
+    assert_eq!(client.call(&["SET", "empty", ""]), Reply::bulk("OK"));
+    assert_eq!(client.call(&["GET", "empty"]), Reply::bulk(""));
+    assert_eq!(client.call(&["GET", "missing"]), Reply::Bulk(None));
+    assert_eq!(client.call(&["GETSET", "empty", "value"]), Reply::bulk(""));
+    assert_eq!(
+        client.call(&["GETSET", "missing", "value"]),
+        Reply::Bulk(None)
+    );
+    assert_eq!(
+        client.call(&["GETRANGE", "nosuchkey", "0", "-1"]),
+        Reply::bulk("")
+    );
+}
+
+#[test]
+fn getset_returns_the_old_value_and_clears_the_expiry() {
+    let server = Server::start(7535, &[]);
+    let mut client = server.client();
+
+    client.call(&["SET", "key", "old", "PX", "200"]);
+    assert_eq!(client.call(&["GETSET", "key", "new"]), Reply::bulk("old"));
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!(client.call(&["GET", "key"]), Reply::bulk("new"));
+}
+
+#[test]
+fn substr_is_an_alias_of_getrange() {
+    let server = Server::start(7536, &[]);
+    let mut client = server.client();
+    client.call(&["SET", "key", "This is a string"]);
+
+    for (start, end) in [
+        ("0", "3"),
+        ("-3", "-1"),
+        ("0", "-1"),
+        ("10", "100"),
+        ("5", "2"),
+        ("-100", "3"),
+    ] {
+        let getrange = client.call(&["GETRANGE", "key", start, end]);
+        assert_eq!(
+            client.call(&["SUBSTR", "key", start, end]),
+            getrange,
+            "{start} {end}"
+        );
+    }
+    assert_eq!(
+        client.call(&["SUBSTR", "key", "0", "3"]),
+        Reply::bulk("This")
+    );
+    assert_eq!(
+        client.call(&["SUBSTR", "missing", "0", "3"]),
+        client.call(&["GETRANGE", "missing", "0", "3"])
+    );
+}
+
+#[test]
+fn exists_counts_duplicates_and_skips_expired_keys() {
+    let server = Server::start(7549, &[]);
+    let mut client = server.client();
+
+    client.call(&["SET", "a", "1"]);
+    assert_eq!(client.call(&["EXISTS", "a", "a", "b"]), Reply::Integer(2));
+
+    client.call(&["SET", "b", "2", "PX", "100"]);
+    assert_eq!(client.call(&["EXISTS", "a", "b", "b"]), Reply::Integer(3));
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(client.call(&["EXISTS", "a", "b", "b"]), Reply::Integer(1));
+    assert_eq!(client.call(&["EXISTS", "b"]), Reply::Integer(0));
+}
@@ -0,0 +1,129 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{replica, Client, Reply, Server};
+
+/// Reads the `SET` and `REPLCONF GETACK *` a fake replica gets for a write followed by `WAIT`.
+fn expect_write_and_getack(replica: &mut Client) {
+    assert!(matches!(replica.read(), Reply::Array(command) if command[0] == Reply::bulk("SET")));
+    assert_eq!(
+        replica.read(),
+        Reply::Array(vec![
+            Reply::bulk("REPLCONF"),
+            Reply::bulk("GETACK"),
+            Reply::bulk("*")
+        ])
+    );
+}
+
+/// Acknowledges an offset beyond anything the tests write.
+fn ack(replica: &mut Client) {
+    replica.send(&["REPLCONF", "ACK", "1000000"]);
+}
+
+#[test]
+fn wait_without_timeout_blocks_until_enough_replicas_ack() {
+    let server = Server::start(7520, &[]);
+    let mut fake_replica = replica(&server);
+    let mut client = server.client();
+
+    client.call(&["SET", "key", "value"]);
+    client.send(&["WAIT", "1", "0"]);
+    expect_write_and_getack(&mut fake_replica);
+    assert!(!client.has_pending(Duration::from_millis(500)));
+
+    ack(&mut fake_replica);
+    assert_eq!(client.read(), Reply::Integer(1));
+}
+
+#[test]
+fn wait_for_zero_replicas_returns_the_replica_count_immediately() {
+    let server = Server::start(7522, &[]);
+    let mut client = server.client();
+    client.call(&["SET", "key", "value"]);
+
+    let started = Instant::now();
+    assert_eq!(client.call(&["WAIT", "0", "100"]), Reply::Integer(0));
+    assert!(started.elapsed() < Duration::from_millis(100));
+
+    let _replicas = [replica(&server), replica(&server)];
+    client.call(&["SET", "key", "value"]);
+    let started = Instant::now();
+    assert_eq!(client.call(&["WAIT", "0", "100"]), Reply::Integer(2));
+    assert!(started.elapsed() < Duration::from_millis(100));
+}
+
+#[test]
+fn wait_wakes_up_once_enough_replicas_ack() {
+    let server = Server::start(7523, &[]);
+    let mut first = replica(&server);
+    let mut second = replica(&server);
+    let mut client = server.client();
+
+    client.call(&["SET", "key", "value"]);
+    client.send(&["WAIT", "2", "0"]);
+    expect_write_and_getack(&mut first);
+    expect_write_and_getack(&mut second);
+
+    ack(&mut first);
+    assert!(!client.has_pending(Duration::from_millis(300)));
+    ack(&mut second);
+    assert_eq!(client.read(), Reply::Integer(2));
+}
+
+#[test]
+fn wait_without_replicas_returns_zero_immediately() {
+    let server = Server::start(7527, &[]);
+    let mut client = server.client();
+
+    client.call(&["SET", "key", "value"]);
+    let started = Instant::now();
+    assert_eq!(client.call(&["WAIT", "1", "1000"]), Reply::Integer(0));
+    assert!(started.elapsed() < Duration::from_millis(500));
+}
+
+#[test]
+fn wait_counts_only_remaining_replicas_when_one_drops() {
+    let server = Server::start(7529, &[]);
+    let mut first = replica(&server);
+    let mut second = replica(&server);
+    let mut client = server.client();
+
+    client.call(&["SET", "key", "value"]);
+    let started = Instant::now();
+    client.send(&["WAIT", "2", "1500"]);
+    expect_write_and_getack(&mut first);
+    expect_write_and_getack(&mut second);
+    ack(&mut first);
+    drop(second);
+
+    assert_eq!(client.read(), Reply::Integer(1));
+    assert!(started.elapsed() < Duration::from_millis(2000));
+}
+
+#[test]
+fn replicas_count_for_wait_only_once_online() {
+    let server = Server::start(7534, &[]);
+    let mut fake_replica = replica(&server);
+    let mut client = server.client();
+
+    // Connected, but it never acknowledged anything, so it may still be loading the RDB
+    client.call(&["SET", "key", "value"]);
+    assert_eq!(client.call(&["WAIT", "1", "200"]), Reply::Integer(0));
+    expect_write_and_getack(&mut fake_replica);
+
+    ack(&mut fake_replica);
+    assert_eq!(client.call(&["WAIT", "1", "200"]), Reply::Integer(1));
+}
+
+#[test]
+fn wait_with_invalid_arguments_is_an_error() {
+    let server = Server::start(7537, &[]);
+    let mut client = server.client();
+    let not_an_integer = Reply::Error("ERR value is not an integer or out of range".to_string());
+
+    assert_eq!(client.call(&["WAIT", "foo", "bar"]), not_an_integer);
+    assert_eq!(client.call(&["WAIT", "1", "bar"]), not_an_integer);
+    assert_eq!(client.call(&["PING"]), Reply::Simple("PONG".to_string()));
+}